@@ -0,0 +1,60 @@
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::File;
+use std::process;
+use trie::aggregate::{aggregate, aggregate_by_meta};
+use trie::common::{Prefix, PrefixAs};
+use trie::treebitmap::TreeBitMap;
+
+fn get_first_arg() -> Result<OsString, Box<dyn Error>> {
+    match env::args_os().nth(1) {
+        None => Err(From::from("expected 1 argument, but got none")),
+        Some(file_path) => Ok(file_path),
+    }
+}
+
+fn load_prefixes(tree_bitmap: &mut TreeBitMap<u32, PrefixAs>) -> Result<(), Box<dyn Error>> {
+    let file_path = get_first_arg()?;
+    let file = File::open(file_path)?;
+    let mut rdr = csv::Reader::from_reader(file);
+    for result in rdr.records() {
+        let record = result?;
+        let net: std::net::Ipv4Addr = record[0].parse()?;
+        let len: u8 = record[1].parse()?;
+        let asn: u32 = record[2].parse()?;
+        tree_bitmap.insert(Prefix::new_with_meta(net.into(), len, PrefixAs(asn)));
+    }
+    Ok(())
+}
+
+// Shows how deaggregated a table is, by aggregating it as a whole and per
+// origin AS.
+fn main() {
+    let mut tree_bitmap = TreeBitMap::<u32, PrefixAs>::new(vec![4]);
+
+    if let Err(err) = load_prefixes(&mut tree_bitmap) {
+        println!("error running example: {}", err);
+        process::exit(1);
+    }
+    let pfxs_num = tree_bitmap.iter().count();
+    println!("loaded {} prefixes", pfxs_num);
+
+    let start = std::time::Instant::now();
+    let aggr = aggregate(&tree_bitmap);
+    println!(
+        "aggregated: {} prefixes ({:.1}%) in {}ms",
+        aggr.len(),
+        aggr.len() as f64 / pfxs_num as f64 * 100.0,
+        start.elapsed().as_millis()
+    );
+
+    let start = std::time::Instant::now();
+    let aggr_as = aggregate_by_meta(&tree_bitmap);
+    println!(
+        "aggregated per origin AS: {} prefixes ({:.1}%) in {}ms",
+        aggr_as.len(),
+        aggr_as.len() as f64 / pfxs_num as f64 * 100.0,
+        start.elapsed().as_millis()
+    );
+}
@@ -4,7 +4,7 @@ use std::error::Error;
 use std::ffi::OsString;
 use std::fs::File;
 use std::process;
-use trie::common::{NoMeta, Prefix, PrefixAs};
+use trie::common::{NoMeta, ParseMode, Prefix, PrefixAs};
 use trie::treebitmap::TreeBitMap;
 
 use shrust::{Shell, ShellIO};
@@ -36,7 +36,7 @@ fn load_prefixes(pfxs: &mut Vec<Prefix<u32, PrefixAs>>) -> Result<(), Box<dyn Er
         let asn: u32 = record[2].parse().unwrap();
         let pfx = Prefix::<u32, PrefixAs>::new_with_meta(net.into(), len, PrefixAs(asn));
         pfxs.push(pfx);
-        // trie.insert(&pfx);
+        // trie.insert(pfx);
         // println!("{:?}", pfx);
     }
     Ok(())
@@ -54,7 +54,7 @@ fn main() {
     println!("finished loading {} prefixes...", pfxs.len());
 
     let start = std::time::Instant::now();
-    for pfx in pfxs.iter() {
+    for pfx in pfxs.into_iter() {
         tree_bitmap.insert(pfx);
     }
     let ready = std::time::Instant::now();
@@ -168,16 +168,13 @@ fn main() {
 
     let mut shell = Shell::new(tree_bitmap);
     shell.new_command("s", "search the RIB", 1, |io, tree_bitmap, s| {
-        let s_pref: Vec<&str> = s[0].split("/").collect();
-        let len = s_pref[1].parse::<u8>().unwrap();
-        let s_net: Vec<u8> = s_pref[0]
-            .split(".")
-            .map(|o| -> u8 { o.parse::<u8>().unwrap() })
-            .collect();
-        let pfx = Prefix::<u32, NoMeta>::new(
-            std::net::Ipv4Addr::new(s_net[0], s_net[1], s_net[2], s_net[3]).into(),
-            len,
-        );
+        let pfx = match Prefix::<u32, NoMeta>::parse(s[0], ParseMode::Masking) {
+            Ok(pfx) => pfx,
+            Err(err) => {
+                writeln!(io, "cannot parse {}: {}", s[0], err)?;
+                return Ok(());
+            }
+        };
         let s_pfx = tree_bitmap.match_longest_prefix(&pfx);
         writeln!(io, "{:?}", s_pfx)?;
         Ok(())
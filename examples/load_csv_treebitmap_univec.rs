@@ -4,7 +4,7 @@ use std::error::Error;
 use std::ffi::OsString;
 use std::fs::File;
 use std::process;
-use trie::common::{NoMeta, Prefix, PrefixAs};
+use trie::common::{NoMeta, ParseMode, Prefix, PrefixAs};
 use trie::treebitmap_univec::TreeBitMap;
 
 use shrust::{Shell, ShellIO};
@@ -87,7 +87,7 @@ fn main() {
     );
     println!(
         "memory used by prefixes: {}kb",
-        tree_bitmap.prefixes.len() * std::mem::size_of::<Prefix<u32, NoMeta>>() / 1024
+        tree_bitmap.prefixes.len() * std::mem::size_of::<Option<Prefix<u32, NoMeta>>>() / 1024
     );
     println!(
         "stride division  {:?}",
@@ -167,16 +167,13 @@ fn main() {
 
     let mut shell = Shell::new(tree_bitmap);
     shell.new_command("s", "search the RIB", 1, |io, tree_bitmap, s| {
-        let s_pref: Vec<&str> = s[0].split("/").collect();
-        let len = s_pref[1].parse::<u8>().unwrap();
-        let s_net: Vec<u8> = s_pref[0]
-            .split(".")
-            .map(|o| -> u8 { o.parse::<u8>().unwrap() })
-            .collect();
-        let pfx = Prefix::<u32, NoMeta>::new(
-            std::net::Ipv4Addr::new(s_net[0], s_net[1], s_net[2], s_net[3]).into(),
-            len,
-        );
+        let pfx = match Prefix::<u32, NoMeta>::parse(s[0], ParseMode::Masking) {
+            Ok(pfx) => pfx,
+            Err(err) => {
+                writeln!(io, "cannot parse {}: {}", s[0], err)?;
+                return Ok(());
+            }
+        };
         let s_pfx = tree_bitmap.match_longest_prefix(&pfx);
         writeln!(io, "{:?}", s_pfx)?;
         Ok(())
@@ -32,7 +32,7 @@ fn load_prefixes(pfxs: &mut Vec<Prefix<u32, PrefixAs>>) -> Result<(), Box<dyn Er
         let asn: u32 = record[2].parse().unwrap();
         let pfx = Prefix::<u32, PrefixAs>::new_with_meta(net.into(), len, PrefixAs(asn));
         pfxs.push(pfx);
-        // trie.insert(&pfx);
+        // trie.insert(pfx);
         // println!("{:?}", pfx);
     }
     Ok(())
@@ -48,8 +48,8 @@ fn main() {
     }
     // let pfxs_copy = pfxs.iter().map(|p| p.strip_meta());
 
-    for pfx in pfxs.iter() {
-        trie.insert(&pfx);
+    for pfx in pfxs.into_iter() {
+        trie.insert(pfx);
     }
 
     let (total_nodes, total_prefixes) = trie.1.iter().fold((0, 0), |total_n: (u64, u64), n| {
@@ -32,7 +32,7 @@ fn load_prefixes(pfxs: &mut Vec<Prefix<u32, PrefixAs>>) -> Result<(), Box<dyn Er
         let asn: u32 = record[2].parse().unwrap();
         let pfx = Prefix::<u32, PrefixAs>::new_with_meta(net.into(), len, PrefixAs(asn));
         pfxs.push(pfx);
-        // trie.insert(&pfx);
+        // trie.insert(pfx);
         // println!("{:?}", pfx);
     }
     Ok(())
@@ -56,7 +56,7 @@ fn main() {
             process::exit(1);
         }
 
-        for pfx in pfxs.iter() {
+        for pfx in pfxs.into_iter() {
             tree_bitmap.insert(pfx);
         }
 
@@ -3,7 +3,7 @@ use std::error::Error;
 use std::ffi::OsString;
 use std::fs::File;
 use std::process;
-use trie::common::{Prefix, PrefixAs};
+use trie::common::{NoMeta, Prefix, PrefixAs};
 use trie::treebitmap_univec::TreeBitMap;
 
 fn get_first_arg() -> Result<OsString, Box<dyn Error>> {
@@ -38,6 +38,22 @@ fn load_prefixes(pfxs: &mut Vec<Prefix<u32, PrefixAs>>) -> Result<(), Box<dyn Er
     Ok(())
 }
 
+// The average time of a longest match lookup for the addresses in `addrs`,
+// over a couple of rounds.
+fn search_time_nanos(tree_bitmap: &TreeBitMap<u32, PrefixAs>, addrs: &[u32]) -> f64 {
+    let rounds = 10;
+    let start = std::time::Instant::now();
+    for _ in 0..rounds {
+        for addr in addrs.iter() {
+            // Keeps the optimizer from dropping the lookups.
+            std::hint::black_box(
+                tree_bitmap.match_longest_prefix_only(&Prefix::<u32, NoMeta>::new(*addr, 32)),
+            );
+        }
+    }
+    start.elapsed().as_nanos() as f64 / (rounds * addrs.len()) as f64
+}
+
 fn main() {
     let strides_vec = [
         vec![8],
@@ -56,10 +72,22 @@ fn main() {
             process::exit(1);
         }
 
+        // An address in the middle of every prefix, in the order of the
+        // file, so that consecutive lookups take different paths.
+        let addrs: Vec<u32> = pfxs
+            .iter()
+            .map(|pfx| pfx.net | u32::MAX.checked_shr(pfx.len as u32).unwrap_or(0) >> 1)
+            .collect();
+
         for pfx in pfxs.into_iter() {
             tree_bitmap.insert(pfx);
         }
 
+        // The same lookups before and after compacting the tree.
+        let search_time = search_time_nanos(&tree_bitmap, &addrs);
+        tree_bitmap.compact();
+        let compacted_search_time = search_time_nanos(&tree_bitmap, &addrs);
+
         let total_nodes = tree_bitmap.stats.iter().fold(0, |mut acc, c| {
             acc += c.created_nodes.iter().fold(0, |mut sum, l| {
                 sum += l.count;
@@ -84,13 +112,15 @@ fn main() {
         println!("\"total_prefixes\": {:?},", tree_bitmap.prefixes.len());
         println!(
             "\"prefixes_mem_kb\": {:?},",
-            tree_bitmap.prefixes.len() * std::mem::size_of::<Prefix<u32, PrefixAs>>() / 1024
+            tree_bitmap.prefixes.len() * std::mem::size_of::<Option<Prefix<u32, PrefixAs>>>() / 1024
         );
         println!(
             "\"prefixes_per_node\": {},",
             tree_bitmap.prefixes.len() as f64 / total_nodes as f64
         );
         println!("\"strides\": {:?},", tree_bitmap.strides);
+        println!("\"search_time_nanos\": {},", search_time);
+        println!("\"compacted_search_time_nanos\": {},", compacted_search_time);
 
         println!("\"levels\": [");
 
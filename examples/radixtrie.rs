@@ -78,7 +78,7 @@ fn main() {
     ];
 
     for pfx in pfxs.iter() {
-        trie.insert(pfx);
+        trie.insert(*pfx);
     }
     println!("{:#?}", trie);
     println!("------ end of inserts\n");
@@ -41,7 +41,7 @@ fn main() {
     ];
 
     for pfx in pfxs.iter() {
-        trie.insert(pfx);
+        trie.insert(*pfx);
     }
     println!("------ end of inserts\n");
 
@@ -34,7 +34,7 @@ fn main() {
     ];
 
     for pfx in pfxs.iter() {
-        trie.insert(pfx);
+        trie.insert(*pfx);
     }
  
     let spfx = Prefix6NoMeta::new(0b0100_0001_0000_0000_0000_0000_1111_1111_u128, 32);
@@ -0,0 +1,96 @@
+use crate::common::{AddressFamily, NoMeta, Prefix};
+use num::PrimInt;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+// Returns the smallest set of prefixes that covers exactly the same address
+// space as `pfxs`, in canonical order. More-specifics of other prefixes are
+// dropped and sibling prefixes (the two halves of their supernet) are merged
+// into the supernet, repeatedly. The meta data is ignored.
+//
+// Takes anything that yields prefixes, owned or borrowed, so both a Vec of
+// prefixes and a loaded store (`&TreeBitMap`) will do.
+pub fn aggregate<AF, T, I>(pfxs: I) -> Vec<Prefix<AF, NoMeta>>
+where
+    AF: AddressFamily + PrimInt + Debug,
+    T: Debug,
+    I: IntoIterator,
+    I::Item: Borrow<Prefix<AF, T>>,
+{
+    let mut pfxs: Vec<Prefix<AF, NoMeta>> = pfxs
+        .into_iter()
+        .map(|pfx| pfx.borrow().strip_meta())
+        .collect();
+    pfxs.sort_unstable();
+    aggregate_sorted(pfxs)
+}
+
+// Like `aggregate`, but only prefixes with equal meta data are aggregated
+// with each other, e.g. only the prefixes originated by the same AS. The
+// result holds the aggregated prefixes for every distinct meta value, in
+// canonical order. Prefixes without meta data are left out.
+//
+// Every meta value is aggregated on its own, so the result describes the
+// address space per meta value. It is not guaranteed to give the same
+// longest prefix match results as the input, where prefixes with different
+// meta data are nested.
+pub fn aggregate_by_meta<AF, T, I>(pfxs: I) -> Vec<Prefix<AF, T>>
+where
+    AF: AddressFamily + PrimInt + Debug,
+    T: Debug + Clone + Eq + Hash,
+    I: IntoIterator,
+    I::Item: Borrow<Prefix<AF, T>>,
+{
+    let mut groups: HashMap<T, Vec<Prefix<AF, NoMeta>>> = HashMap::new();
+    for pfx in pfxs {
+        let pfx = pfx.borrow();
+        if let Some(meta) = pfx.meta.as_ref() {
+            groups
+                .entry(meta.clone())
+                .or_default()
+                .push(pfx.strip_meta());
+        }
+    }
+
+    let mut res = vec![];
+    for (meta, mut group) in groups {
+        group.sort_unstable();
+        res.extend(
+            aggregate_sorted(group)
+                .into_iter()
+                .map(|pfx| Prefix::new_with_meta(pfx.net, pfx.len, meta.clone())),
+        );
+    }
+    res.sort_unstable();
+    res
+}
+
+// The actual aggregation, `pfxs` has to be in canonical order.
+//
+// The prefixes that are kept so far are disjoint and in order, so a prefix
+// can only be covered by the last one of them, and only be merged with the
+// last one of them. A merged supernet may in turn be a sibling of the one
+// before it, hence the loop.
+fn aggregate_sorted<AF>(pfxs: Vec<Prefix<AF, NoMeta>>) -> Vec<Prefix<AF, NoMeta>>
+where
+    AF: AddressFamily + PrimInt + Debug,
+{
+    let mut res: Vec<Prefix<AF, NoMeta>> = vec![];
+
+    for mut pfx in pfxs {
+        if res.last().is_some_and(|last| last.covers(&pfx)) {
+            continue;
+        }
+        while let Some(last) = res.last() {
+            if pfx.len == 0 || last.len != pfx.len || last.supernet(1) != pfx.supernet(1) {
+                break;
+            }
+            res.pop();
+            pfx = pfx.supernet(1).unwrap();
+        }
+        res.push(pfx);
+    }
+    res
+}
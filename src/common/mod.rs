@@ -1,26 +1,28 @@
 use num::PrimInt;
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
 use std::ops::BitOr;
 
-pub struct TrieNode<'a, AF, T>
+pub struct TrieNode<AF, T>
 where
     T: fmt::Debug,
     AF: AddressFamily + PrimInt + Debug,
 {
-    pub prefix: Option<&'a Prefix<AF, T>>,
-    pub left: Option<Box<TrieNode<'a, AF, T>>>,
-    pub right: Option<Box<TrieNode<'a, AF, T>>>,
+    pub prefix: Option<Prefix<AF, T>>,
+    pub left: Option<Box<TrieNode<AF, T>>>,
+    pub right: Option<Box<TrieNode<AF, T>>>,
 }
 
-impl<'a, AF, T> TrieNode<'a, AF, T>
+impl<AF, T> TrieNode<AF, T>
 where
     T: fmt::Debug,
     AF: AddressFamily + PrimInt + Debug,
 {
-    pub fn new(pfx: Option<&'a Prefix<AF, T>>) -> TrieNode<'a, AF, T> {
-        TrieNode::<'a, AF, T> {
+    pub fn new(pfx: Option<Prefix<AF, T>>) -> TrieNode<AF, T> {
+        TrieNode::<AF, T> {
             prefix: pfx,
             left: None,
             right: None,
@@ -28,9 +30,10 @@ where
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct PrefixAs(pub u32);
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct NoMeta;
 
 impl fmt::Debug for NoMeta {
@@ -39,6 +42,49 @@ impl fmt::Debug for NoMeta {
     }
 }
 
+// Meta data that holds one value per source, e.g. the origin AS or the path
+// per peer a prefix was learned from, for prefixes that are announced by
+// more than one source. Ordered by source.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MultiMeta<K: Ord, V>(BTreeMap<K, V>);
+
+impl<K: Ord, V> MultiMeta<K, V> {
+    pub fn new() -> MultiMeta<K, V> {
+        MultiMeta(BTreeMap::new())
+    }
+
+    // Sets the value for `source` and hands back its previous value.
+    pub fn insert(&mut self, source: K, value: V) -> Option<V> {
+        self.0.insert(source, value)
+    }
+
+    pub fn remove(&mut self, source: &K) -> Option<V> {
+        self.0.remove(source)
+    }
+
+    pub fn get(&self, source: &K) -> Option<&V> {
+        self.0.get(source)
+    }
+
+    pub fn iter(&self) -> std::collections::btree_map::Iter<'_, K, V> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<K: Ord, V> Default for MultiMeta<K, V> {
+    fn default() -> Self {
+        MultiMeta::new()
+    }
+}
+
 pub trait Meta<AF>
 where
     Self: fmt::Debug + Sized,
@@ -57,9 +103,41 @@ pub trait AddressFamily: PrimInt + Debug {
     const BITMASK: Self;
     const BITS: u8;
     fn fmt_net(net: Self) -> String;
+    // Parses the textual form of an address of this family, e.g.
+    // "192.0.2.0" for u32 and "2001:db8::" for u64 and u128.
+    fn parse_net(s: &str) -> Result<Self, PrefixParseError>;
     // returns the specified nibble from `start_bit` to (and
     // including) `start_bit + len` and shifted to the right.
     fn get_nibble(net: Self, start_bit: u8, len: u8) -> u32;
+
+    // Returns the mask with the first `len` bits set.
+    fn netmask(len: u8) -> Self {
+        assert!(len <= Self::BITS, "prefix length {} out of range", len);
+        if len == 0 {
+            Self::zero()
+        } else {
+            Self::max_value() << (Self::BITS - len) as usize
+        }
+    }
+
+    // Writes a prefix in CIDR notation, "192.0.2.0/24". Families that
+    // don't count the prefix length from the start of the key write their
+    // own.
+    fn fmt_prefix(net: Self, len: u8) -> String {
+        format!("{}/{}", Self::fmt_net(net), len)
+    }
+
+    // The reverse of `fmt_prefix`, hands back the network (host bits and
+    // all) and the length.
+    fn parse_prefix(s: &str) -> Result<(Self, u8), PrefixParseError> {
+        let (addr, len) = s.split_once('/').ok_or(PrefixParseError::MissingLength)?;
+        let net = Self::parse_net(addr)?;
+        let len = len.parse::<u32>().map_err(|_| PrefixParseError::InvalidLength)?;
+        if len > Self::BITS as u32 {
+            return Err(PrefixParseError::LengthOutOfRange(len));
+        }
+        Ok((net, len as u8))
+    }
 }
 
 impl AddressFamily for u32 {
@@ -70,6 +148,12 @@ impl AddressFamily for u32 {
         std::net::Ipv4Addr::from(net).to_string()
     }
 
+    fn parse_net(s: &str) -> Result<Self, PrefixParseError> {
+        s.parse::<std::net::Ipv4Addr>()
+            .map(u32::from)
+            .map_err(PrefixParseError::InvalidAddress)
+    }
+
     fn get_nibble(net: Self, start_bit: u8, len: u8) -> u32 {
         (net << start_bit) >> ((32 - len) % 32)
     }
@@ -82,11 +166,46 @@ impl AddressFamily for u128 {
         std::net::Ipv6Addr::from(net).to_string()
     }
 
+    fn parse_net(s: &str) -> Result<Self, PrefixParseError> {
+        s.parse::<std::net::Ipv6Addr>()
+            .map(u128::from)
+            .map_err(PrefixParseError::InvalidAddress)
+    }
+
     fn get_nibble(net: Self, start_bit: u8, len: u8) -> u32 {
         ((net << start_bit) >> ((128 - len) % 128)) as u32
     }
 }
 
+// 64 bit keys, e.g. the network part of an IPv6 address (the first 64
+// bits), which is all that's needed for routing on up to /64. Keys are
+// written like that network part: "2001:db8:1:2::".
+impl AddressFamily for u64 {
+    const BITMASK: u64 = 0x1u64.rotate_right(1);
+    const BITS: u8 = 64;
+
+    fn fmt_net(net: Self) -> String {
+        std::net::Ipv6Addr::from((net as u128) << 64).to_string()
+    }
+
+    // The last 64 bits of the IPv6 address don't fit in the key, an address
+    // with any of them set is refused instead of silently cut off.
+    fn parse_net(s: &str) -> Result<Self, PrefixParseError> {
+        let addr = u128::from(
+            s.parse::<std::net::Ipv6Addr>()
+                .map_err(PrefixParseError::InvalidAddress)?,
+        );
+        if addr as u64 != 0 {
+            return Err(PrefixParseError::HostBitsSet);
+        }
+        Ok((addr >> 64) as u64)
+    }
+
+    fn get_nibble(net: Self, start_bit: u8, len: u8) -> u32 {
+        ((net << start_bit) >> ((64 - len) % 64)) as u32
+    }
+}
+
 pub struct IPv4(u32);
 
 impl BitOr for IPv4 {
@@ -112,11 +231,14 @@ where
     T: Meta<AF>,
     AF: AddressFamily + PrimInt + Debug,
 {
+    // Both constructors clear the host bits, i.e. the bits in `net` beyond
+    // `len`, so that every prefix has one canonical form. All the stores
+    // depend on that.
     pub fn new(net: AF, len: u8) -> Prefix<AF, T> {
-        T::with_meta(net, len, None)
+        T::with_meta(net & AF::netmask(len), len, None)
     }
     pub fn new_with_meta(net: AF, len: u8, meta: T) -> Prefix<AF, T> {
-        T::with_meta(net, len, Some(meta))
+        T::with_meta(net & AF::netmask(len), len, Some(meta))
     }
     pub fn strip_meta(self: &Self) -> Prefix<AF, NoMeta> {
         Prefix::<AF, NoMeta> {
@@ -125,6 +247,128 @@ where
             meta: None,
         }
     }
+
+    // Whether `addr` lies inside this prefix.
+    pub fn contains(&self, addr: AF) -> bool {
+        addr & AF::netmask(self.len) == self.net
+    }
+
+    // Whether `other` is equal to or more specific than this prefix.
+    pub fn covers<U: Meta<AF>>(&self, other: &Prefix<AF, U>) -> bool {
+        self.len <= other.len && self.contains(other.net)
+    }
+
+    // Whether the two prefixes have any address in common, which for
+    // prefixes means that one of them covers the other.
+    pub fn overlaps<U: Meta<AF>>(&self, other: &Prefix<AF, U>) -> bool {
+        self.covers(other) || other.covers(self)
+    }
+
+    // Returns the prefix that is `n` bits shorter and covers this one, None
+    // if the prefix is shorter than `n` bits.
+    pub fn supernet(&self, n: u8) -> Option<Prefix<AF, NoMeta>> {
+        let len = self.len.checked_sub(n)?;
+        Some(Prefix::new(self.net, len))
+    }
+
+    // Iterates over the prefixes of length `new_len` that together make up
+    // this prefix, in order. Yields nothing if `new_len` is shorter than
+    // the prefix or longer than the address family.
+    pub fn subnets(&self, new_len: u8) -> Subnets<AF> {
+        if new_len < self.len || new_len > AF::BITS {
+            return Subnets {
+                next: None,
+                last: self.net,
+                len: new_len,
+            };
+        }
+        Subnets {
+            next: Some(self.net),
+            last: self.last_addr() & AF::netmask(new_len),
+            len: new_len,
+        }
+    }
+
+    pub fn first_addr(&self) -> AF {
+        self.net
+    }
+
+    pub fn last_addr(&self) -> AF {
+        self.net | !AF::netmask(self.len)
+    }
+
+    // The number of addresses in the prefix. The one count that doesn't fit,
+    // 2^128 for ::/0, is reported as u128::MAX.
+    pub fn num_addresses(&self) -> u128 {
+        1_u128
+            .checked_shl((AF::BITS - self.len) as u32)
+            .unwrap_or(u128::MAX)
+    }
+
+    // Folds `meta` of a re-announced prefix into the meta data of this one,
+    // with `merge` if both have meta data. Otherwise whichever one has meta
+    // data wins.
+    pub fn merge_meta<F>(&mut self, meta: Option<T>, merge: F)
+    where
+        F: FnOnce(&mut T, T),
+    {
+        if let Some(new) = meta {
+            match self.meta.as_mut() {
+                Some(old) => merge(old, new),
+                None => self.meta = Some(new),
+            }
+        }
+    }
+}
+
+// The iterator returned by `Prefix::subnets`.
+pub struct Subnets<AF>
+where
+    AF: AddressFamily + PrimInt + Debug,
+{
+    next: Option<AF>,
+    last: AF,
+    len: u8,
+}
+
+impl<AF> Iterator for Subnets<AF>
+where
+    AF: AddressFamily + PrimInt + Debug,
+{
+    type Item = Prefix<AF, NoMeta>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let net = self.next?;
+        // Only step forward while there's room left before `last`, so that
+        // the step itself, 2^(BITS - len), is never computed for len 0.
+        self.next = if net == self.last {
+            None
+        } else {
+            Some(net + (AF::one() << (AF::BITS - self.len) as usize))
+        };
+        Some(Prefix::new(net, self.len))
+    }
+}
+
+impl<AF, T> Clone for Prefix<AF, T>
+where
+    T: Meta<AF> + Clone,
+    AF: AddressFamily + PrimInt + Debug,
+{
+    fn clone(&self) -> Self {
+        Prefix {
+            net: self.net,
+            len: self.len,
+            meta: self.meta.clone(),
+        }
+    }
+}
+
+impl<AF, T> Copy for Prefix<AF, T>
+where
+    T: Meta<AF> + Copy,
+    AF: AddressFamily + PrimInt + Debug,
+{
 }
 
 impl<T, AF> Meta<AF> for T
@@ -137,14 +381,17 @@ where
     }
 }
 
+// Prefixes are compared as (network, length) pairs, so 10.0.0.0/8 sorts
+// before 10.0.0.0/16, which sorts before 11.0.0.0/8. The meta data takes no
+// part in comparing, equality or hashing, a prefix is identified by its
+// network and length only.
 impl<AF, T> Ord for Prefix<AF, T>
 where
     T: Debug,
     AF: AddressFamily + PrimInt + Debug,
 {
     fn cmp(&self, other: &Self) -> Ordering {
-        (self.net >> (AF::BITS - self.len) as usize)
-            .cmp(&(other.net >> ((AF::BITS - other.len) % 32) as usize))
+        (self.net, self.len).cmp(&(other.net, other.len))
     }
 }
 
@@ -154,8 +401,7 @@ where
     AF: AddressFamily + PrimInt + Debug,
 {
     fn eq(&self, other: &Self) -> bool {
-        self.net >> (AF::BITS - self.len) as usize
-            == other.net >> ((AF::BITS - other.len) % 32) as usize
+        self.net == other.net && self.len == other.len
     }
 }
 
@@ -165,10 +411,7 @@ where
     AF: AddressFamily + PrimInt + Debug,
 {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(
-            (self.net >> (AF::BITS - self.len) as usize)
-                .cmp(&(other.net >> ((AF::BITS - other.len) % 32) as usize)),
-        )
+        Some(self.cmp(other))
     }
 }
 
@@ -179,6 +422,17 @@ where
 {
 }
 
+impl<AF, T> Hash for Prefix<AF, T>
+where
+    T: Debug,
+    AF: AddressFamily + PrimInt + Debug + Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.net.hash(state);
+        self.len.hash(state);
+    }
+}
+
 impl<T, AF> Debug for Prefix<AF, T>
 where
     AF: AddressFamily + PrimInt + Debug,
@@ -194,6 +448,167 @@ where
     }
 }
 
+// Prints the prefix in plain CIDR notation, e.g. "192.0.2.0/24", or the
+// notation of its address family (see `AddressFamily::fmt_prefix`). The meta
+// data is left out.
+impl<T, AF> fmt::Display for Prefix<AF, T>
+where
+    AF: AddressFamily + PrimInt + Debug,
+    T: Debug + Meta<AF>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&AF::fmt_prefix(self.net, self.len))
+    }
+}
+
+// What to do with bits set beyond the prefix length, e.g. the .1 in
+// "192.0.2.1/24", when parsing a prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    // Refuse the prefix with `PrefixParseError::HostBitsSet`.
+    Strict,
+    // Clear the host bits, so "192.0.2.1/24" becomes "192.0.2.0/24".
+    Masking,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrefixParseError {
+    // There's no "/" separating the address and the length.
+    MissingLength,
+    // The part before the "/" isn't an address of this address family.
+    InvalidAddress(std::net::AddrParseError),
+    // The route distinguisher in front of a VPN address can't be parsed.
+    InvalidRouteDistinguisher,
+    // The part after the "/" isn't a number.
+    InvalidLength,
+    // The length is larger than the number of bits in the address family.
+    LengthOutOfRange(u32),
+    // Bits are set beyond the prefix length (only in `ParseMode::Strict`),
+    // or beyond the bits of the address family, e.g. the last 64 bits of
+    // an IPv6 address for u64.
+    HostBitsSet,
+}
+
+impl fmt::Display for PrefixParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrefixParseError::MissingLength => f.write_str("missing prefix length"),
+            PrefixParseError::InvalidAddress(err) => write!(f, "invalid address: {}", err),
+            PrefixParseError::InvalidRouteDistinguisher => {
+                f.write_str("invalid route distinguisher")
+            }
+            PrefixParseError::InvalidLength => f.write_str("invalid prefix length"),
+            PrefixParseError::LengthOutOfRange(len) => {
+                write!(f, "prefix length {} out of range", len)
+            }
+            PrefixParseError::HostBitsSet => f.write_str("host bits set beyond prefix length"),
+        }
+    }
+}
+
+impl std::error::Error for PrefixParseError {}
+
+impl<AF> Prefix<AF, NoMeta>
+where
+    AF: AddressFamily + PrimInt + Debug,
+{
+    // Parses a prefix in CIDR notation, "192.0.2.0/24" for u32 and
+    // "2001:db8::/32" for u128. `mode` decides whether host bits are an
+    // error or get cleared.
+    pub fn parse(s: &str, mode: ParseMode) -> Result<Self, PrefixParseError> {
+        let (net, len) = AF::parse_prefix(s)?;
+        let masked_net = net & AF::netmask(len);
+        if mode == ParseMode::Strict && masked_net != net {
+            return Err(PrefixParseError::HostBitsSet);
+        }
+        Ok(Prefix::new(masked_net, len))
+    }
+}
+
+// Parses in `ParseMode::Strict`, use `Prefix::parse` to clear host bits
+// instead.
+impl<AF> std::str::FromStr for Prefix<AF, NoMeta>
+where
+    AF: AddressFamily + PrimInt + Debug,
+{
+    type Err = PrefixParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Prefix::parse(s, ParseMode::Strict)
+    }
+}
+
+// Selects which parts of a `QueryResult` get computed. The exact match and
+// the longest match are always filled in, they come with the walk down the
+// tree anyway.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MatchOptions {
+    pub include_less_specifics: bool,
+    pub include_more_specifics: bool,
+}
+
+// The outcome of a single query for a prefix.
+//
+// `exact_match` is the stored prefix with the same network and length as
+// the search prefix, `longest_match` the most specific stored prefix that
+// covers it (which is the exact match if there is one). The less-specifics
+// are ordered from short to long and don't include the exact match. The
+// more-specifics come in canonical order. Both are None if they were not
+// asked for in the `MatchOptions`.
+#[derive(Debug)]
+pub struct QueryResult<'a, AF, T>
+where
+    T: Meta<AF>,
+    AF: AddressFamily + PrimInt + Debug,
+{
+    pub exact_match: Option<&'a Prefix<AF, T>>,
+    pub longest_match: Option<&'a Prefix<AF, T>>,
+    pub less_specifics: Option<Vec<&'a Prefix<AF, T>>>,
+    pub more_specifics: Option<Vec<&'a Prefix<AF, T>>>,
+}
+
+// The interface that every prefix store in this crate implements, so that
+// callers (the routing code, the benchmark harness) can swap one structure
+// for another by changing a type parameter only.
+//
+// All stores own the prefixes inserted into them. Searching is always done
+// with a `Prefix<AF, NoMeta>`, the stored prefix is handed back by reference.
+pub trait PrefixStore<AF, T>
+where
+    T: Debug,
+    AF: AddressFamily + PrimInt + Debug,
+{
+    // Stores `pfx`. A prefix with the same network and length is replaced
+    // and handed back.
+    fn insert(&mut self, pfx: Prefix<AF, T>) -> Option<Prefix<AF, T>>;
+
+    // Stores `pfx`, unless a prefix with the same network and length is
+    // stored already. Then the meta data of both is handed to `merge`, which
+    // updates the stored one in place, see `Prefix::merge_meta`.
+    fn upsert<F>(&mut self, pfx: Prefix<AF, T>, merge: F)
+    where
+        F: FnOnce(&mut T, T);
+
+    // Returns the stored prefix that has exactly the same network and length
+    // as `search_pfx`, if any.
+    fn get(&self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&Prefix<AF, T>>;
+
+    // Like `get`, but hands out the stored prefix mutably, so that its meta
+    // data can be updated in place. Changing `net` or `len` through this
+    // reference will corrupt the store.
+    fn get_mut(&mut self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&mut Prefix<AF, T>>;
+
+    // Returns the most specific stored prefix that covers `search_pfx`.
+    fn match_longest_prefix(&self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&Prefix<AF, T>>;
+
+    // Removes the prefix that exactly matches `pfx` and hands it back.
+    fn remove(&mut self, pfx: &Prefix<AF, NoMeta>) -> Option<Prefix<AF, T>>;
+
+    // Iterates over all the stored prefixes in canonical order, i.e. ordered
+    // by network address first and prefix length second.
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Prefix<AF, T>> + 'a>;
+}
+
 pub struct TrieLevelStats {
     pub level: u8,
     pub nodes_num: u32,
@@ -206,24 +621,24 @@ impl fmt::Debug for TrieLevelStats {
     }
 }
 
-pub struct Trie<'a, AF, T>(TrieNode<'a, AF, T>, pub Vec<TrieLevelStats>)
+pub struct Trie<AF, T>(TrieNode<AF, T>, pub Vec<TrieLevelStats>)
 where
     T: Debug,
     AF: AddressFamily + PrimInt + Debug;
 
-impl<'a, AF, T> Trie<'a, AF, T>
+impl<AF, T> Trie<AF, T>
 where
     T: Debug,
     AF: AddressFamily + PrimInt + Debug + fmt::Binary,
 {
-    pub fn new() -> Trie<'a, AF, T> {
+    pub fn new() -> Trie<AF, T> {
         Trie(
             TrieNode {
                 prefix: None,
                 left: None,
                 right: None,
             },
-            (0..33)
+            (0..=AF::BITS)
                 .collect::<Vec<u8>>()
                 .into_iter()
                 .map(|level| TrieLevelStats {
@@ -235,7 +650,9 @@ where
         )
     }
 
-    pub fn insert(&mut self, pfx: &'a Prefix<AF, T>) {
+    // Stores `pfx`, replacing the prefix with the same network and length
+    // if there is one. Hands back the replaced prefix.
+    pub fn insert(&mut self, pfx: Prefix<AF, T>) -> Option<Prefix<AF, T>> {
         let mut cursor = &mut self.0;
 
         let mut first_bit = pfx.net;
@@ -273,22 +690,68 @@ where
         if cursor.prefix.is_none() {
             self.1[level].prefixes_num += 1;
         }
-        cursor.prefix = Some(&pfx);
+        cursor.prefix.replace(pfx)
+    }
+
+    // Follows the bits of `search_pfx` down to the node at depth
+    // `search_pfx.len` and returns the prefix stored there, if any.
+    pub fn get(&self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&Prefix<AF, T>> {
+        let mut cursor = &self.0;
+        let zero: AF = num::zero();
+        let mut first_bit = search_pfx.net;
+
+        for _ in 0..search_pfx.len {
+            cursor = match first_bit & AF::BITMASK {
+                b if b == zero => cursor.left.as_deref()?,
+                _ => cursor.right.as_deref()?,
+            };
+            first_bit = first_bit << 1;
+        }
+        cursor.prefix.as_ref()
+    }
+
+    // Stores `pfx`, or merges its meta data into the stored prefix with the
+    // same network and length, see `Prefix::merge_meta`.
+    pub fn upsert<F>(&mut self, pfx: Prefix<AF, T>, merge: F)
+    where
+        F: FnOnce(&mut T, T),
+    {
+        match self.get_mut(&pfx.strip_meta()) {
+            Some(stored) => stored.merge_meta(pfx.meta, merge),
+            None => {
+                self.insert(pfx);
+            }
+        }
+    }
+
+    pub fn get_mut(&mut self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&mut Prefix<AF, T>> {
+        let mut cursor = &mut self.0;
+        let zero: AF = num::zero();
+        let mut first_bit = search_pfx.net;
+
+        for _ in 0..search_pfx.len {
+            cursor = match first_bit & AF::BITMASK {
+                b if b == zero => cursor.left.as_deref_mut()?,
+                _ => cursor.right.as_deref_mut()?,
+            };
+            first_bit = first_bit << 1;
+        }
+        cursor.prefix.as_mut()
     }
 
     pub fn match_longest_prefix(
         &self,
         search_pfx: &Prefix<AF, NoMeta>,
-    ) -> Option<&'a Prefix<AF, T>> {
+    ) -> Option<&Prefix<AF, T>> {
         let mut cursor = &self.0;
         let mut cursor_pfx: AF = num::zero();
-        let mut match_pfx: Option<&'a Prefix<AF, T>> = None;
+        let mut match_pfx: Option<&Prefix<AF, T>> = None;
 
         let zero: AF = num::zero();
         let mut first_bit = search_pfx.net;
 
         for _ in 0..(search_pfx.len + 1) {
-            if let Some(found_pfx) = cursor.prefix {
+            if let Some(found_pfx) = cursor.prefix.as_ref() {
                 match_pfx = Some(found_pfx);
 
             }
@@ -317,7 +780,64 @@ where
         match_pfx
     }
 
-    fn traverse(node: Box<TrieNode<'a, AF, T>>, nodes_num: usize, prefixes_num: usize) -> (usize, usize) {
+    // Unsets the prefix in the node at depth `pfx.len` and prunes the
+    // branch leading up to it as far as it has become empty, i.e. nodes
+    // without a prefix and without children are dropped. The root node
+    // always stays in place.
+    pub fn remove(&mut self, pfx: &Prefix<AF, NoMeta>) -> Option<Prefix<AF, T>> {
+        Self::remove_below(&mut self.0, &mut self.1, pfx, 0)
+    }
+
+    fn remove_below(
+        node: &mut TrieNode<AF, T>,
+        levels: &mut Vec<TrieLevelStats>,
+        pfx: &Prefix<AF, NoMeta>,
+        level: usize,
+    ) -> Option<Prefix<AF, T>> {
+        if level == pfx.len as usize {
+            let removed = node.prefix.take()?;
+            levels[level].prefixes_num -= 1;
+            return Some(removed);
+        }
+
+        let zero: AF = num::zero();
+        let child_slot = match (pfx.net << level) & AF::BITMASK {
+            b if b == zero => &mut node.left,
+            _ => &mut node.right,
+        };
+        let removed = Self::remove_below(child_slot.as_deref_mut()?, levels, pfx, level + 1)?;
+
+        if let Some(child) = child_slot.as_deref() {
+            if child.prefix.is_none() && child.left.is_none() && child.right.is_none() {
+                *child_slot = None;
+                levels[level + 1].nodes_num -= 1;
+            }
+        }
+        Some(removed)
+    }
+
+    fn collect_prefixes<'a>(node: &'a TrieNode<AF, T>, pfxs: &mut Vec<&'a Prefix<AF, T>>) {
+        if let Some(pfx) = node.prefix.as_ref() {
+            pfxs.push(pfx);
+        }
+        if let Some(left) = node.left.as_deref() {
+            Self::collect_prefixes(left, pfxs);
+        }
+        if let Some(right) = node.right.as_deref() {
+            Self::collect_prefixes(right, pfxs);
+        }
+    }
+
+    // Iterates over all the stored prefixes in canonical order, i.e.
+    // ordered by network address first and prefix length second. That is
+    // the pre-order of the trie, with the 0 (left) side first.
+    pub fn iter(&self) -> std::vec::IntoIter<&Prefix<AF, T>> {
+        let mut pfxs = vec![];
+        Self::collect_prefixes(&self.0, &mut pfxs);
+        pfxs.into_iter()
+    }
+
+    fn traverse(node: Box<TrieNode<AF, T>>, nodes_num: usize, prefixes_num: usize) -> (usize, usize) {
         let mut result = (nodes_num, prefixes_num);
         if node.left.is_some() {
             result.0 += 1;
@@ -339,3 +859,53 @@ where
         Self::traverse(root, 0, 0)
     }
 }
+
+impl<AF, T> PrefixStore<AF, T> for Trie<AF, T>
+where
+    T: Debug,
+    AF: AddressFamily + PrimInt + Debug + fmt::Binary,
+{
+    fn insert(&mut self, pfx: Prefix<AF, T>) -> Option<Prefix<AF, T>> {
+        Trie::insert(self, pfx)
+    }
+
+    fn upsert<F>(&mut self, pfx: Prefix<AF, T>, merge: F)
+    where
+        F: FnOnce(&mut T, T),
+    {
+        Trie::upsert(self, pfx, merge)
+    }
+
+    fn get(&self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&Prefix<AF, T>> {
+        Trie::get(self, search_pfx)
+    }
+
+    fn get_mut(&mut self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&mut Prefix<AF, T>> {
+        Trie::get_mut(self, search_pfx)
+    }
+
+    fn match_longest_prefix(&self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&Prefix<AF, T>> {
+        Trie::match_longest_prefix(self, search_pfx)
+    }
+
+    fn remove(&mut self, pfx: &Prefix<AF, NoMeta>) -> Option<Prefix<AF, T>> {
+        Trie::remove(self, pfx)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Prefix<AF, T>> + 'a> {
+        Box::new(Trie::iter(self))
+    }
+}
+
+impl<'a, AF, T> IntoIterator for &'a Trie<AF, T>
+where
+    T: Debug,
+    AF: AddressFamily + PrimInt + Debug + fmt::Binary,
+{
+    type Item = &'a Prefix<AF, T>;
+    type IntoIter = std::vec::IntoIter<&'a Prefix<AF, T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -0,0 +1,184 @@
+use crate::common::{AddressFamily, NoMeta, Prefix, PrefixParseError};
+use crate::treebitmap::TreeBitMap;
+use std::fmt::Debug;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// An IPv4 or IPv6 prefix to hand to a `DualStackTable`, with a length that
+// fits the address family. The bits of the address beyond the length are
+// cleared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpPrefix {
+    addr: IpAddr,
+    len: u8,
+}
+
+impl IpPrefix {
+    pub fn new(addr: IpAddr, len: u8) -> Result<IpPrefix, PrefixParseError> {
+        let addr = match addr {
+            IpAddr::V4(addr) if len <= <u32 as AddressFamily>::BITS => {
+                IpAddr::V4(Ipv4Addr::from(u32::from(addr) & u32::netmask(len)))
+            }
+            IpAddr::V6(addr) if len <= <u128 as AddressFamily>::BITS => {
+                IpAddr::V6(Ipv6Addr::from(u128::from(addr) & u128::netmask(len)))
+            }
+            _ => return Err(PrefixParseError::LengthOutOfRange(len as u32)),
+        };
+        Ok(IpPrefix { addr, len })
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.len
+    }
+}
+
+// A prefix stored in a `DualStackTable`, borrowed from the tree of its
+// address family.
+#[derive(Debug)]
+pub enum DualStackPrefix<'a, T: Debug> {
+    V4(&'a Prefix<u32, T>),
+    V6(&'a Prefix<u128, T>),
+}
+
+impl<'a, T: Debug> DualStackPrefix<'a, T> {
+    pub fn addr(&self) -> IpAddr {
+        match self {
+            DualStackPrefix::V4(pfx) => IpAddr::V4(Ipv4Addr::from(pfx.net)),
+            DualStackPrefix::V6(pfx) => IpAddr::V6(Ipv6Addr::from(pfx.net)),
+        }
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        match self {
+            DualStackPrefix::V4(pfx) => pfx.len,
+            DualStackPrefix::V6(pfx) => pfx.len,
+        }
+    }
+
+    pub fn meta(&self) -> Option<&'a T> {
+        match self {
+            DualStackPrefix::V4(pfx) => pfx.meta.as_ref(),
+            DualStackPrefix::V6(pfx) => pfx.meta.as_ref(),
+        }
+    }
+}
+
+// One IPv4 and one IPv6 TreeBitMap behind a single interface that takes
+// `IpPrefix`es and `std::net::IpAddr`s, every call goes to the tree for the
+// family of the address. Both trees have their own stride configuration.
+pub struct DualStackTable<T: Debug> {
+    pub v4: TreeBitMap<u32, T>,
+    pub v6: TreeBitMap<u128, T>,
+}
+
+impl<T: Debug> DualStackTable<T> {
+    pub fn new(v4_strides: Vec<u8>, v6_strides: Vec<u8>) -> DualStackTable<T> {
+        DualStackTable {
+            v4: TreeBitMap::new(v4_strides),
+            v6: TreeBitMap::new(v6_strides),
+        }
+    }
+
+    // Stores the prefix, replacing the one with the same address and length
+    // if there is one, and hands back the meta data of the replaced prefix.
+    pub fn insert(&mut self, pfx: IpPrefix, meta: T) -> Option<T> {
+        let IpPrefix { addr, len } = pfx;
+        match addr {
+            IpAddr::V4(addr) => self
+                .v4
+                .insert(Prefix::new_with_meta(u32::from(addr), len, meta))
+                .and_then(|pfx| pfx.meta),
+            IpAddr::V6(addr) => self
+                .v6
+                .insert(Prefix::new_with_meta(u128::from(addr), len, meta))
+                .and_then(|pfx| pfx.meta),
+        }
+    }
+
+    // Stores the prefix, or merges `meta` into the meta data of the stored
+    // prefix with the same address and length, see `Prefix::merge_meta`.
+    pub fn upsert<F>(&mut self, pfx: IpPrefix, meta: T, merge: F)
+    where
+        F: FnOnce(&mut T, T),
+    {
+        let IpPrefix { addr, len } = pfx;
+        match addr {
+            IpAddr::V4(addr) => self
+                .v4
+                .upsert(Prefix::new_with_meta(u32::from(addr), len, meta), merge),
+            IpAddr::V6(addr) => self
+                .v6
+                .upsert(Prefix::new_with_meta(u128::from(addr), len, meta), merge),
+        }
+    }
+
+    // Returns the stored prefix with exactly this address and length.
+    pub fn get(&self, pfx: IpPrefix) -> Option<DualStackPrefix<'_, T>> {
+        let IpPrefix { addr, len } = pfx;
+        match addr {
+            IpAddr::V4(addr) => self
+                .v4
+                .get(&Prefix::new(u32::from(addr), len))
+                .map(DualStackPrefix::V4),
+            IpAddr::V6(addr) => self
+                .v6
+                .get(&Prefix::new(u128::from(addr), len))
+                .map(DualStackPrefix::V6),
+        }
+    }
+
+    // Returns the most specific stored prefix that covers `pfx`.
+    pub fn match_longest_prefix(&self, pfx: IpPrefix) -> Option<DualStackPrefix<'_, T>> {
+        let IpPrefix { addr, len } = pfx;
+        match addr {
+            IpAddr::V4(addr) => self
+                .v4
+                .match_longest_prefix(&Prefix::<u32, NoMeta>::new(u32::from(addr), len))
+                .pop()
+                .map(DualStackPrefix::V4),
+            IpAddr::V6(addr) => self
+                .v6
+                .match_longest_prefix(&Prefix::<u128, NoMeta>::new(u128::from(addr), len))
+                .pop()
+                .map(DualStackPrefix::V6),
+        }
+    }
+
+    // Returns the most specific stored prefix that covers a single address,
+    // i.e. the route for it.
+    pub fn lookup(&self, addr: IpAddr) -> Option<DualStackPrefix<'_, T>> {
+        let len = match addr {
+            IpAddr::V4(_) => <u32 as AddressFamily>::BITS,
+            IpAddr::V6(_) => <u128 as AddressFamily>::BITS,
+        };
+        self.match_longest_prefix(IpPrefix { addr, len })
+    }
+
+    // Removes the prefix with exactly this address and length and hands
+    // back its meta data.
+    pub fn remove(&mut self, pfx: IpPrefix) -> Option<T> {
+        let IpPrefix { addr, len } = pfx;
+        match addr {
+            IpAddr::V4(addr) => self
+                .v4
+                .remove(&Prefix::new(u32::from(addr), len))
+                .and_then(|pfx| pfx.meta),
+            IpAddr::V6(addr) => self
+                .v6
+                .remove(&Prefix::new(u128::from(addr), len))
+                .and_then(|pfx| pfx.meta),
+        }
+    }
+
+    // Iterates over all the IPv4 prefixes and then all the IPv6 prefixes,
+    // both in canonical order.
+    pub fn iter(&self) -> impl Iterator<Item = DualStackPrefix<'_, T>> {
+        self.v4
+            .iter()
+            .map(DualStackPrefix::V4)
+            .chain(self.v6.iter().map(DualStackPrefix::V6))
+    }
+}
@@ -4,5 +4,10 @@ pub mod treebitmap_univec;
 pub mod common;
 pub mod triebitvec;
 pub mod radixtrie;
+pub mod dualstack;
+pub mod aggregate;
+pub mod ortc;
+pub mod vpn;
+pub mod treebitmap_static;
 
 pub mod tests;
\ No newline at end of file
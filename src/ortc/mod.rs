@@ -0,0 +1,206 @@
+use crate::common::{AddressFamily, Prefix};
+use crate::treebitmap::TreeBitMap;
+use num::PrimInt;
+use std::fmt::Debug;
+
+// Optimal Routing Table Constructor (Draves, King, Venkatachary and Zill,
+// "Constructing Optimal IP Routing Tables", 1999).
+//
+// The next hop for an address is the meta data of its longest matching
+// prefix. An address without a matching prefix, or whose longest matching
+// prefix has no meta data, has no next hop (None): its packets get
+// dropped. ORTC builds the table with the fewest prefixes that gives every
+// address the same next hop as the original table. That table may need
+// prefixes without meta data to punch holes into a covering prefix, where
+// the original had no route at all.
+
+// The outcome of `compress`. The prefix counts are taken from the
+// `StrideStats` of both trees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrtcStats {
+    pub prefixes_before: usize,
+    pub prefixes_after: usize,
+    // Whether the compressed table was verified to forward every address
+    // like the original, see `forwarding_equivalent`.
+    pub equivalent: bool,
+}
+
+impl OrtcStats {
+    // The number of prefixes after compression as a fraction of the number
+    // before, so lower is better. An empty table has a ratio of 1.
+    pub fn compression_ratio(&self) -> f64 {
+        if self.prefixes_before == 0 {
+            return 1.0;
+        }
+        self.prefixes_after as f64 / self.prefixes_before as f64
+    }
+}
+
+// A node in the binary trie ORTC works on, one level per bit.
+struct OrtcNode<'a, T> {
+    // The next hop of the prefix that ends at this node, if there is one.
+    pfx: Option<Option<&'a T>>,
+    children: [Option<Box<OrtcNode<'a, T>>>; 2],
+    // The candidate next hops for this node, filled in by the second pass.
+    hops: Vec<Option<&'a T>>,
+}
+
+impl<'a, T: Eq> OrtcNode<'a, T> {
+    fn new() -> OrtcNode<'a, T> {
+        OrtcNode {
+            pfx: None,
+            children: [None, None],
+            hops: vec![],
+        }
+    }
+
+    fn insert<AF>(&mut self, pfx: &'a Prefix<AF, T>)
+    where
+        T: Debug,
+        AF: AddressFamily + PrimInt + Debug,
+    {
+        let mut node = self;
+        for bit in 0..pfx.len {
+            let child = AF::get_nibble(pfx.net, bit, 1) as usize;
+            node = node.children[child].get_or_insert_with(|| Box::new(OrtcNode::new()));
+        }
+        node.pfx = Some(pfx.meta.as_ref());
+    }
+
+    // The first two passes in one go. On the way down every node gets
+    // either no or two children, the missing child is created and so
+    // inherits the next hop from the nearest prefix above it (leaf
+    // pushing). On the way up the leaves take that inherited next hop as
+    // their only candidate, and every other node takes the intersection
+    // of the candidates of its children, or their union if the
+    // intersection is empty.
+    fn normalize(&mut self, inherited: Option<&'a T>) {
+        let inherited = self.pfx.unwrap_or(inherited);
+        if self.children.iter().all(|c| c.is_none()) {
+            self.hops = vec![inherited];
+            return;
+        }
+
+        for child in self.children.iter_mut() {
+            child
+                .get_or_insert_with(|| Box::new(OrtcNode::new()))
+                .normalize(inherited);
+        }
+        let (left, right) = match &self.children {
+            [Some(left), Some(right)] => (&left.hops, &right.hops),
+            _ => unreachable!(),
+        };
+
+        self.hops = left.iter().filter(|h| right.contains(*h)).copied().collect();
+        if self.hops.is_empty() {
+            self.hops = left.clone();
+            self.hops.extend(right.iter().filter(|h| !left.contains(*h)));
+        }
+    }
+
+    // The third pass. A node that can keep the next hop it inherits from
+    // above doesn't need a prefix, any other node gets a prefix with one
+    // of its candidates.
+    fn assign<AF>(
+        &self,
+        net: AF,
+        len: u8,
+        inherited: Option<&'a T>,
+        pfxs: &mut Vec<(AF, u8, Option<&'a T>)>,
+    ) where
+        AF: AddressFamily + PrimInt + Debug,
+    {
+        let hop = if self.hops.contains(&inherited) {
+            inherited
+        } else {
+            pfxs.push((net, len, self.hops[0]));
+            self.hops[0]
+        };
+
+        if let [Some(left), Some(right)] = &self.children {
+            left.assign(net, len + 1, hop, pfxs);
+            right.assign(net | (AF::BITMASK >> len as usize), len + 1, hop, pfxs);
+        }
+    }
+}
+
+// Returns a table with the minimal number of prefixes that forwards every
+// address like `tree`, in a TreeBitMap with the same strides, together with
+// the stats for the compression.
+//
+// The trie is built from scratch and keeps a list of candidate next hops
+// per node, which may get long for tables with many different next hops.
+pub fn compress<AF, T>(tree: &TreeBitMap<AF, T>) -> (TreeBitMap<AF, T>, OrtcStats)
+where
+    AF: AddressFamily + PrimInt + Debug,
+    T: Debug + Clone + Eq,
+{
+    let mut root = OrtcNode::new();
+    for pfx in tree.iter() {
+        root.insert(pfx);
+    }
+    root.normalize(None);
+    let mut pfxs = vec![];
+    root.assign(AF::zero(), 0, None, &mut pfxs);
+
+    let mut compressed = TreeBitMap::new(tree.strides.clone());
+    for (net, len, hop) in pfxs {
+        compressed.insert(Prefix {
+            net,
+            len,
+            meta: hop.cloned(),
+        });
+    }
+
+    let stats = OrtcStats {
+        prefixes_before: prefixes_num(tree),
+        prefixes_after: prefixes_num(&compressed),
+        equivalent: forwarding_equivalent(tree, &compressed),
+    };
+    (compressed, stats)
+}
+
+// Whether both tables give every address the same next hop. The longest
+// match for an address can only change where a prefix of either table
+// begins or right after one ends, so it's enough to compare the next hops
+// at those addresses.
+pub fn forwarding_equivalent<AF, T>(a: &TreeBitMap<AF, T>, b: &TreeBitMap<AF, T>) -> bool
+where
+    AF: AddressFamily + PrimInt + Debug,
+    T: Debug + Eq,
+{
+    let mut addrs = vec![AF::zero()];
+    for pfx in a.iter().chain(b.iter()) {
+        addrs.push(pfx.first_addr());
+        if let Some(addr) = pfx.last_addr().checked_add(&AF::one()) {
+            addrs.push(addr);
+        }
+    }
+    addrs.sort_unstable();
+    addrs.dedup();
+
+    addrs
+        .into_iter()
+        .all(|addr| next_hop(a, addr) == next_hop(b, addr))
+}
+
+fn next_hop<AF, T>(tree: &TreeBitMap<AF, T>, addr: AF) -> Option<&T>
+where
+    AF: AddressFamily + PrimInt + Debug,
+    T: Debug,
+{
+    tree.match_longest_prefix(&Prefix::new(addr, AF::BITS))
+        .pop()
+        .and_then(|pfx| pfx.meta.as_ref())
+}
+
+fn prefixes_num<AF, T>(tree: &TreeBitMap<AF, T>) -> usize
+where
+    AF: AddressFamily + PrimInt + Debug,
+    T: Debug,
+{
+    tree.stats
+        .iter()
+        .map(|s| s.prefixes_num.iter().map(|p| p.count).sum::<usize>())
+        .sum()
+}
@@ -1,28 +1,28 @@
-use crate::common::{AddressFamily, NoMeta, Prefix};
+use crate::common::{AddressFamily, NoMeta, Prefix, PrefixStore};
 use num::PrimInt;
 use std::fmt;
 use std::fmt::Debug;
 
 #[derive(Debug)]
-pub struct RadixTrieNode<'a, AF, T>
+pub struct RadixTrieNode<AF, T>
 where
     T: fmt::Debug,
     AF: AddressFamily + PrimInt + Debug,
 {
-    pub prefix: Option<&'a Prefix<AF, T>>,
+    pub prefix: Option<Prefix<AF, T>>,
     pub bit_pos: u8,
     pub bit_id: AF,
-    pub left: Option<Box<RadixTrieNode<'a, AF, T>>>,
-    pub right: Option<Box<RadixTrieNode<'a, AF, T>>>,
+    pub left: Option<Box<RadixTrieNode<AF, T>>>,
+    pub right: Option<Box<RadixTrieNode<AF, T>>>,
 }
 
-impl<'a, AF, T> RadixTrieNode<'a, AF, T>
+impl<AF, T> RadixTrieNode<AF, T>
 where
     T: fmt::Debug,
     AF: AddressFamily + PrimInt + Debug,
 {
-    pub fn new(pfx: Option<&'a Prefix<AF, T>>) -> RadixTrieNode<'a, AF, T> {
-        RadixTrieNode::<'a, AF, T> {
+    pub fn new(pfx: Option<Prefix<AF, T>>) -> RadixTrieNode<AF, T> {
+        RadixTrieNode::<AF, T> {
             prefix: pfx,
             bit_pos: 0,
             bit_id: AF::zero(),
@@ -32,9 +32,13 @@ where
     }
 }
 
+// Stats for all the nodes at one depth level in the trie (the root node
+// being at level 0).
 pub struct LevelStats {
     pub level: u8,
-    pub compression: u16,
+    // The sum of the number of bits spanned by the edges leading to the
+    // nodes at this level, i.e. nodes_num for an uncompressed trie.
+    pub compression: u32,
     pub nodes_num: u32,
     pub prefixes_num: u32,
 }
@@ -50,17 +54,17 @@ impl fmt::Debug for LevelStats {
 }
 
 #[derive(Debug)]
-pub struct RadixTrie<'a, AF, T>(RadixTrieNode<'a, AF, T>, pub Vec<LevelStats>)
+pub struct RadixTrie<AF, T>(RadixTrieNode<AF, T>, pub Vec<LevelStats>)
 where
     T: Debug,
     AF: AddressFamily + PrimInt + Debug;
 
-impl<'a, AF, T> RadixTrie<'a, AF, T>
+impl<AF, T> RadixTrie<AF, T>
 where
     T: Debug,
     AF: AddressFamily + PrimInt + Debug + fmt::Binary,
 {
-    pub fn new() -> RadixTrie<'a, AF, T> {
+    pub fn new() -> RadixTrie<AF, T> {
         RadixTrie(
             RadixTrieNode {
                 prefix: None,
@@ -69,7 +73,7 @@ where
                 left: None,
                 right: None,
             },
-            (0..33)
+            (0..=AF::BITS)
                 .collect::<Vec<u8>>()
                 .into_iter()
                 .map(|level| LevelStats {
@@ -82,13 +86,17 @@ where
         )
     }
 
-    pub fn insert(&mut self, pfx: &'a Prefix<AF, T>) {
+    // Stores `pfx`, replacing the prefix with the same network and length
+    // if there is one. Hands back the replaced prefix.
+    pub fn insert(&mut self, pfx: Prefix<AF, T>) -> Option<Prefix<AF, T>> {
         let mut cursor = &mut self.0;
 
         let zero = num::zero();
         let mut level: u8 = 0; // used for stats only
 
         loop {
+            let cursor_bit_pos = cursor.bit_pos;
+
             // we might already be at the place we need to be,
             // at either a leaf or an internal node, just
             // set our insert prefix here and be done with it.
@@ -96,8 +104,7 @@ where
                 if cursor.prefix.is_none() {
                     self.1[level as usize].prefixes_num += 1;
                 }
-                cursor.prefix = Some(&pfx);
-                break;
+                return cursor.prefix.replace(pfx);
             }
 
             let mut next_cursor = match (pfx.net << cursor.bit_pos as usize) & AF::BITMASK {
@@ -109,13 +116,15 @@ where
                 // No node in the direction we're heading, so we can create a new leaf node
                 // with our prefix length and the prefix.
                 None => {
-                    let mut new_leaf = RadixTrieNode::new(Some(&pfx));
-                    new_leaf.bit_pos = pfx.len;
-                    new_leaf.bit_id = pfx.net >> (AF::BITS - pfx.len) as usize;
-                    *next_cursor = Some(Box::new(new_leaf));
+                    let bit_pos = pfx.len;
+                    let bit_id = pfx.net >> (AF::BITS - pfx.len) as usize;
                     self.1[(level + 1) as usize].nodes_num += 1;
                     self.1[(level + 1) as usize].prefixes_num += 1;
-                    self.1[(level + 1) as usize].compression += (pfx.len - level) as u16;
+                    self.1[(level + 1) as usize].compression += (pfx.len - cursor_bit_pos) as u32;
+                    let mut new_leaf = RadixTrieNode::new(Some(pfx));
+                    new_leaf.bit_pos = bit_pos;
+                    new_leaf.bit_id = bit_id;
+                    *next_cursor = Some(Box::new(new_leaf));
                     break;
                 }
                 // There is a node on the left side, we need to see if that node's
@@ -131,10 +140,9 @@ where
                     {
                         // This prefix already exists here, that's the end
                         if next_node.prefix.is_none() {
-                            self.1[level as usize].prefixes_num += 1;
+                            self.1[(level + 1) as usize].prefixes_num += 1;
                         }
-                        next_node.prefix = Some(&pfx);
-                        break;
+                        return next_node.prefix.replace(pfx);
                     }
                     // Check if the to-be-inserted prefix is aligned  the next_node AND it's less
                     // specific than our to-be-inserted prefix. If so let's move on.
@@ -178,15 +186,13 @@ where
                         intermediary_node.bit_id = in_bit_id;
 
                         // If we've reached the pfx.len in the intermediary_node,
-                        // that means that it will have to host a prefix, (so
-                        // it'll become a regular internal node).
+                        // that means that it would have to host a prefix, but
+                        // that's exactly the case where the intermediary node
+                        // overshoots and the prefix goes into `insert_node`
+                        // directly below the cursor (see below).
                         // Also we should break out of the loop after inserting
                         // it into the trie.
-                        let mut done = false;
-                        if intermediary_node.bit_pos == pfx.len {
-                            intermediary_node.prefix = Some(&pfx);
-                            done = true;
-                        }
+                        let done = intermediary_node.bit_pos == pfx.len;
 
                         // Now, check if we should attach the next_node to the
                         // left or the right of our new intermediary node.
@@ -198,7 +204,9 @@ where
                             .leading_zeros()
                             == 0;
 
-                        let mut insert_node = RadixTrieNode::new(Some(&pfx));
+                        let insert_bit_pos = pfx.len;
+                        let insert_bit_id = pfx.net >> (AF::BITS - pfx.len) as usize;
+                        let mut insert_node = RadixTrieNode::new(Some(pfx));
 
                         let (next_node, next_node_opp) = match l_r_bit_next_node {
                             false => (&mut intermediary_node.left, &mut intermediary_node.right),
@@ -207,23 +215,42 @@ where
 
                         // We are overshooting our intended child, we have to go back
                         // to inserting this final prefix node on cursor, not next_node.
-                        if in_bit_pos >= pfx.len {
-                            self.1[(level) as usize].nodes_num += 1;
-                            self.1[(level) as usize].compression += (pfx.len - level) as u16;
-                            self.1[(level) as usize].prefixes_num += 1;
+                        if done {
+                            self.1[(level + 1) as usize].nodes_num += 1;
+                            self.1[(level + 1) as usize].compression +=
+                                (insert_bit_pos - cursor_bit_pos) as u32;
+                            self.1[(level + 1) as usize].prefixes_num += 1;
+
+                            // The existing sub-tree moves down one level, below insert_node.
+                            if let Some(moved_node) = next_cursor.as_deref() {
+                                Self::count_subtree(
+                                    &mut self.1,
+                                    moved_node,
+                                    cursor_bit_pos,
+                                    (level + 1) as usize,
+                                    true,
+                                );
+                                Self::count_subtree(
+                                    &mut self.1,
+                                    moved_node,
+                                    insert_bit_pos,
+                                    (level + 2) as usize,
+                                    false,
+                                );
+                            }
 
                             match l_r_bit_next_node {
                                 true => {
                                     insert_node.right = std::mem::take(&mut next_cursor);
-                                    insert_node.bit_pos = pfx.len;
-                                    insert_node.bit_id = pfx.net >> (AF::BITS - pfx.len) as usize;
+                                    insert_node.bit_pos = insert_bit_pos;
+                                    insert_node.bit_id = insert_bit_id;
                                     *next_cursor = Some(Box::new(insert_node));
                                     break;
                                 }
                                 false => {
                                     insert_node.left = std::mem::take(&mut next_cursor);
-                                    insert_node.bit_pos = pfx.len;
-                                    insert_node.bit_id = pfx.net >> (AF::BITS - pfx.len) as usize;
+                                    insert_node.bit_pos = insert_bit_pos;
+                                    insert_node.bit_id = insert_bit_id;
                                     *next_cursor = Some(Box::new(insert_node));
                                     break;
                                 }
@@ -234,32 +261,43 @@ where
 
                             // we've created two nodes at this point (intermediary_node and insert_node), so add two to the counter
                             self.1[(level + 1) as usize].nodes_num += 1;
-                            self.1[(level + 1) as usize].compression += (pfx.len - level) as u16;
-                            // only insert_node has always a prefix attached.
-                            if intermediary_node.prefix.is_some() {
-                                self.1[(level + 1) as usize].prefixes_num += 1;
-                                self.1[(level + 2) as usize].prefixes_num += 1;
-                            } else {
-                                self.1[(level + 1) as usize].prefixes_num += 1;
-                            }
+                            self.1[(level + 1) as usize].compression +=
+                                (in_bit_pos - cursor_bit_pos) as u32;
 
                             // cursor is cut off at this point after this assignment!
                             *next_node = std::mem::take(&mut next_cursor);
-                            if !done {
-                                // let mut nn = RadixTrieNode::new(Some(&pfx));
-
-                                insert_node.bit_pos = pfx.len;
-                                insert_node.bit_id = pfx.net >> (AF::BITS - pfx.len) as usize;
-                                self.1[(level + 2) as usize].nodes_num += 1;
-                                *next_node_opp = Some(Box::new(insert_node));
-                                done = true;
+
+                            // The existing sub-tree moves down one level, below the
+                            // intermediary node.
+                            if let Some(moved_node) = next_node.as_deref() {
+                                Self::count_subtree(
+                                    &mut self.1,
+                                    moved_node,
+                                    cursor_bit_pos,
+                                    (level + 1) as usize,
+                                    true,
+                                );
+                                Self::count_subtree(
+                                    &mut self.1,
+                                    moved_node,
+                                    in_bit_pos,
+                                    (level + 2) as usize,
+                                    false,
+                                );
                             }
+
+                            insert_node.bit_pos = insert_bit_pos;
+                            insert_node.bit_id = insert_bit_id;
+                            // only insert_node has always a prefix attached.
+                            self.1[(level + 2) as usize].nodes_num += 1;
+                            self.1[(level + 2) as usize].prefixes_num += 1;
+                            self.1[(level + 2) as usize].compression +=
+                                (insert_bit_pos - in_bit_pos) as u32;
+                            *next_node_opp = Some(Box::new(insert_node));
+
                             // Weld the cursor to the newly created intermediary node.
                             *next_cursor = Some(Box::new(intermediary_node));
-
-                            if done {
-                                break;
-                            }
+                            break;
                         }
                     };
                 }
@@ -267,14 +305,70 @@ where
             cursor = next_cursor.as_deref_mut().unwrap();
             level += 1;
         }
+        None
+    }
+
+    // Descends to the node with a `bit_pos` equal to the length of
+    // `search_pfx` and returns the prefix stored there, if any. Gives up as
+    // soon as a node is encountered that isn't on the path to `search_pfx`.
+    pub fn get(&self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&Prefix<AF, T>> {
+        let mut cursor = &self.0;
+        let zero: AF = num::zero();
+
+        while cursor.bit_pos < search_pfx.len {
+            let next_node = match (search_pfx.net << cursor.bit_pos as usize) & AF::BITMASK {
+                b if b == zero => cursor.left.as_deref()?,
+                _ => cursor.right.as_deref()?,
+            };
+            if next_node.bit_pos > search_pfx.len
+                || search_pfx.net >> (AF::BITS - next_node.bit_pos) as usize != next_node.bit_id
+            {
+                return None;
+            }
+            cursor = next_node;
+        }
+        cursor.prefix.as_ref()
+    }
+
+    // Stores `pfx`, or merges its meta data into the stored prefix with the
+    // same network and length, see `Prefix::merge_meta`.
+    pub fn upsert<F>(&mut self, pfx: Prefix<AF, T>, merge: F)
+    where
+        F: FnOnce(&mut T, T),
+    {
+        match self.get_mut(&pfx.strip_meta()) {
+            Some(stored) => stored.merge_meta(pfx.meta, merge),
+            None => {
+                self.insert(pfx);
+            }
+        }
+    }
+
+    pub fn get_mut(&mut self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&mut Prefix<AF, T>> {
+        let mut cursor = &mut self.0;
+        let zero: AF = num::zero();
+
+        while cursor.bit_pos < search_pfx.len {
+            let next_node = match (search_pfx.net << cursor.bit_pos as usize) & AF::BITMASK {
+                b if b == zero => cursor.left.as_deref_mut()?,
+                _ => cursor.right.as_deref_mut()?,
+            };
+            if next_node.bit_pos > search_pfx.len
+                || search_pfx.net >> (AF::BITS - next_node.bit_pos) as usize != next_node.bit_id
+            {
+                return None;
+            }
+            cursor = next_node;
+        }
+        cursor.prefix.as_mut()
     }
 
     pub fn match_longest_prefix(
         &self,
         search_pfx: &Prefix<AF, NoMeta>,
-    ) -> Option<&'a Prefix<AF, T>> {
+    ) -> Option<&Prefix<AF, T>> {
         let mut cursor = Some(&self.0);
-        let mut match_pfx: Option<&'a Prefix<AF, T>> = None;
+        let mut match_pfx: Option<&Prefix<AF, T>> = None;
         let zero: AF = num::zero();
 
         let mut next_pos = search_pfx.net << cursor.unwrap().bit_pos as usize;
@@ -289,7 +383,9 @@ where
                 b if b == zero && bit_id_match => {
                     cursor = cursor
                         .and_then(|c| {
-                            match_pfx = c.prefix;
+                            // Intermediary nodes don't carry a prefix, keep
+                            // the last one we found in that case.
+                            match_pfx = c.prefix.as_ref().or(match_pfx);
                             c.left.as_deref()
                         })
                         .and_then(|c| Some(c));
@@ -297,7 +393,7 @@ where
                 _ if bit_id_match => {
                     cursor = cursor
                         .and_then(|c| {
-                            match_pfx = c.prefix;
+                            match_pfx = c.prefix.as_ref().or(match_pfx);
                             c.right.as_deref()
                         })
                         .and_then(|c| Some(c));
@@ -322,11 +418,147 @@ where
         match_pfx
     }
 
+    // Unsets the prefix on the node that hosts it. If that leaves the node
+    // without a prefix and with less than two children, the node is taken
+    // out, so that the trie stays path-compressed. Intermediary nodes that
+    // are left with a single child on the way back up are collapsed likewise.
+    // The root node always stays in place.
+    pub fn remove(&mut self, pfx: &Prefix<AF, NoMeta>) -> Option<Prefix<AF, T>> {
+        if pfx.len == 0 {
+            let removed = self.0.prefix.take()?;
+            self.1[0].prefixes_num -= 1;
+            return Some(removed);
+        }
+        Self::remove_below(&mut self.0, &mut self.1, pfx, 0)
+    }
+
+    fn remove_below(
+        node: &mut RadixTrieNode<AF, T>,
+        levels: &mut Vec<LevelStats>,
+        pfx: &Prefix<AF, NoMeta>,
+        depth: usize,
+    ) -> Option<Prefix<AF, T>> {
+        let zero: AF = num::zero();
+        let bit_pos = node.bit_pos;
+
+        let child_slot = match (pfx.net << bit_pos as usize) & AF::BITMASK {
+            b if b == zero => &mut node.left,
+            _ => &mut node.right,
+        };
+        let child = child_slot.as_deref_mut()?;
+
+        // The child should be on the path to our prefix, and not be a
+        // more-specific of it.
+        if child.bit_pos > pfx.len
+            || pfx.net >> (AF::BITS - child.bit_pos) as usize != child.bit_id
+        {
+            return None;
+        }
+
+        let removed = if child.bit_pos == pfx.len {
+            let removed = child.prefix.take()?;
+            levels[depth + 1].prefixes_num -= 1;
+            removed
+        } else {
+            Self::remove_below(child, levels, pfx, depth + 1)?
+        };
+
+        Self::compress(child_slot, levels, bit_pos, depth + 1);
+        Some(removed)
+    }
+
+    // Restores the path compression for the node in `slot`, that sits at
+    // `depth` below a parent node with `parent_bit_pos`. A node without a
+    // prefix is dropped if it has no children, and replaced by its child
+    // if it has only one.
+    fn compress(
+        slot: &mut Option<Box<RadixTrieNode<AF, T>>>,
+        levels: &mut Vec<LevelStats>,
+        parent_bit_pos: u8,
+        depth: usize,
+    ) {
+        match slot.as_deref() {
+            Some(node)
+                if node.prefix.is_none() && (node.left.is_none() || node.right.is_none()) => {}
+            _ => {
+                return;
+            }
+        };
+
+        let mut node = slot.take().unwrap();
+        levels[depth].nodes_num -= 1;
+        levels[depth].compression -= (node.bit_pos - parent_bit_pos) as u32;
+
+        let child = node.left.take().or_else(|| node.right.take());
+        if let Some(child_node) = child.as_deref() {
+            // The sub-tree of the child moves up one level, straight below
+            // the parent.
+            Self::count_subtree(levels, child_node, node.bit_pos, depth + 1, true);
+            Self::count_subtree(levels, child_node, parent_bit_pos, depth, false);
+        }
+        *slot = child;
+    }
+
+    // Adds the nodes and prefixes in the sub-tree of `node` (including
+    // itself) to the stats, with `node` sitting at `depth` below a parent
+    // node with `parent_bit_pos`. With `subtract` set they're taken off the
+    // stats instead. Used to keep the stats straight when a sub-tree moves
+    // up or down a level.
+    fn count_subtree(
+        levels: &mut Vec<LevelStats>,
+        node: &RadixTrieNode<AF, T>,
+        parent_bit_pos: u8,
+        depth: usize,
+        subtract: bool,
+    ) {
+        let level = &mut levels[depth];
+        let compression = (node.bit_pos - parent_bit_pos) as u32;
+        let prefixes_num = node.prefix.is_some() as u32;
+
+        if subtract {
+            level.nodes_num -= 1;
+            level.prefixes_num -= prefixes_num;
+            level.compression -= compression;
+        } else {
+            level.nodes_num += 1;
+            level.prefixes_num += prefixes_num;
+            level.compression += compression;
+        }
+
+        if let Some(left) = node.left.as_deref() {
+            Self::count_subtree(levels, left, node.bit_pos, depth + 1, subtract);
+        }
+        if let Some(right) = node.right.as_deref() {
+            Self::count_subtree(levels, right, node.bit_pos, depth + 1, subtract);
+        }
+    }
+
+    fn collect_prefixes<'a>(node: &'a RadixTrieNode<AF, T>, pfxs: &mut Vec<&'a Prefix<AF, T>>) {
+        if let Some(pfx) = node.prefix.as_ref() {
+            pfxs.push(pfx);
+        }
+        if let Some(left) = node.left.as_deref() {
+            Self::collect_prefixes(left, pfxs);
+        }
+        if let Some(right) = node.right.as_deref() {
+            Self::collect_prefixes(right, pfxs);
+        }
+    }
+
+    // Iterates over all the stored prefixes in canonical order, i.e.
+    // ordered by network address first and prefix length second. That is
+    // the pre-order of the trie, with the 0 (left) side first.
+    pub fn iter(&self) -> std::vec::IntoIter<&Prefix<AF, T>> {
+        let mut pfxs = vec![];
+        Self::collect_prefixes(&self.0, &mut pfxs);
+        pfxs.into_iter()
+    }
+
     fn traverse(
-        node: Box<RadixTrieNode<'a, AF, T>>,
-        mut levels: &'a mut Vec<LevelStats>,
+        node: Box<RadixTrieNode<AF, T>>,
+        mut levels: &mut Vec<LevelStats>,
         mut depth: usize,
-    ) -> (&'a mut Vec<LevelStats>, usize) {
+    ) -> (&mut Vec<LevelStats>, usize) {
         let my_depth = depth;
         levels[my_depth].nodes_num += 1;
 
@@ -350,10 +582,60 @@ where
 
     pub fn traverse_count(
         self,
-        stats_buf: &'a mut Vec<LevelStats>,
-    ) -> (&'a mut Vec<LevelStats>, usize) {
+        stats_buf: &mut Vec<LevelStats>,
+    ) -> (&mut Vec<LevelStats>, usize) {
         let root = Box::new(self.0);
         stats_buf[0].nodes_num = 1;
         Self::traverse(root, stats_buf, 0)
     }
 }
+
+impl<AF, T> PrefixStore<AF, T> for RadixTrie<AF, T>
+where
+    T: Debug,
+    AF: AddressFamily + PrimInt + Debug + fmt::Binary,
+{
+    fn insert(&mut self, pfx: Prefix<AF, T>) -> Option<Prefix<AF, T>> {
+        RadixTrie::insert(self, pfx)
+    }
+
+    fn upsert<F>(&mut self, pfx: Prefix<AF, T>, merge: F)
+    where
+        F: FnOnce(&mut T, T),
+    {
+        RadixTrie::upsert(self, pfx, merge)
+    }
+
+    fn get(&self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&Prefix<AF, T>> {
+        RadixTrie::get(self, search_pfx)
+    }
+
+    fn get_mut(&mut self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&mut Prefix<AF, T>> {
+        RadixTrie::get_mut(self, search_pfx)
+    }
+
+    fn match_longest_prefix(&self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&Prefix<AF, T>> {
+        RadixTrie::match_longest_prefix(self, search_pfx)
+    }
+
+    fn remove(&mut self, pfx: &Prefix<AF, NoMeta>) -> Option<Prefix<AF, T>> {
+        RadixTrie::remove(self, pfx)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Prefix<AF, T>> + 'a> {
+        Box::new(RadixTrie::iter(self))
+    }
+}
+
+impl<'a, AF, T> IntoIterator for &'a RadixTrie<AF, T>
+where
+    T: Debug,
+    AF: AddressFamily + PrimInt + Debug + fmt::Binary,
+{
+    type Item = &'a Prefix<AF, T>;
+    type IntoIter = std::vec::IntoIter<&'a Prefix<AF, T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
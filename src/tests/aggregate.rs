@@ -0,0 +1,119 @@
+#[cfg(test)]
+mod test {
+    use crate::aggregate::{aggregate, aggregate_by_meta};
+    use crate::common::*;
+    use crate::treebitmap::TreeBitMap;
+
+    fn pfxs(strs: &[&str]) -> Vec<Prefix<u32, NoMeta>> {
+        strs.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_aggregate() {
+        // Siblings are merged, and the merged supernet again with its own
+        // sibling.
+        assert_eq!(
+            aggregate(pfxs(&["10.0.2.0/23", "10.0.1.0/24", "10.0.0.0/24"])),
+            pfxs(&["10.0.0.0/22"])
+        );
+        // More-specifics and duplicates are dropped.
+        assert_eq!(
+            aggregate(pfxs(&[
+                "10.1.0.0/16",
+                "10.0.0.0/8",
+                "10.0.0.0/8",
+                "10.255.255.0/24"
+            ])),
+            pfxs(&["10.0.0.0/8"])
+        );
+        // Adjacent prefixes that aren't the two halves of a supernet stay.
+        assert_eq!(
+            aggregate(pfxs(&["10.0.2.0/24", "10.0.1.0/24"])),
+            pfxs(&["10.0.1.0/24", "10.0.2.0/24"])
+        );
+        assert_eq!(
+            aggregate(pfxs(&["128.0.0.0/1", "0.0.0.0/1"])),
+            pfxs(&["0.0.0.0/0"])
+        );
+        assert!(aggregate(Vec::<Prefix<u32, NoMeta>>::new()).is_empty());
+
+        let pfxs6: Vec<Prefix<u128, NoMeta>> = ["2001:db8:8000::/33", "2001:db8::/33"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        assert_eq!(aggregate(pfxs6).pop().unwrap().to_string(), "2001:db8::/32");
+    }
+
+    #[test]
+    fn test_aggregate_by_meta() {
+        let pfxs: Vec<Prefix<u32, PrefixAs>> = [
+            ("10.0.0.0/24", 1),
+            ("10.0.1.0/24", 1),
+            ("10.0.2.0/24", 2),
+            ("10.0.3.0/24", 1),
+            ("10.0.3.128/25", 1),
+            ("10.0.3.0/25", 2),
+        ]
+        .iter()
+        .map(|(s, asn)| {
+            let pfx: Prefix<u32, NoMeta> = s.parse().unwrap();
+            Prefix::new_with_meta(pfx.net, pfx.len, PrefixAs(*asn))
+        })
+        .collect();
+
+        let res: Vec<(String, u32)> = aggregate_by_meta(&pfxs)
+            .iter()
+            .map(|p| (p.to_string(), p.meta.unwrap().0))
+            .collect();
+        assert_eq!(
+            res,
+            vec![
+                ("10.0.0.0/23".to_string(), 1),
+                ("10.0.2.0/24".to_string(), 2),
+                ("10.0.3.0/24".to_string(), 1),
+                ("10.0.3.0/25".to_string(), 2),
+            ]
+        );
+    }
+
+    // Aggregates the prefixes in `data/test.csv` straight out of a
+    // TreeBitMap and checks the result against the input.
+    #[test]
+    fn test_aggregate_table() {
+        let mut tree_bitmap = TreeBitMap::<u32, PrefixAs>::new(vec![4]);
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_path("./data/test.csv")
+            .unwrap();
+        for record in rdr.records() {
+            let record = record.unwrap();
+            let net: std::net::Ipv4Addr = record[0].parse().unwrap();
+            let len: u8 = record[1].parse().unwrap();
+            let asn: u32 = record[2].parse().unwrap();
+            tree_bitmap.insert(Prefix::new_with_meta(net.into(), len, PrefixAs(asn)));
+        }
+
+        let res = aggregate(&tree_bitmap);
+        assert!(res.len() < tree_bitmap.iter().count());
+
+        // Disjoint, in order and without siblings left to merge.
+        for w in res.windows(2) {
+            assert!(w[0].last_addr() < w[1].first_addr());
+            assert!(w[0].len != w[1].len || w[0].supernet(1) != w[1].supernet(1));
+        }
+        // Every input prefix is covered, and no address was added: the
+        // input prefixes that aren't covered by another one are disjoint,
+        // so their sizes add up to the size of the whole input.
+        let mut covering: Vec<&Prefix<u32, PrefixAs>> = vec![];
+        for pfx in tree_bitmap.iter() {
+            assert!(res.iter().any(|r| r.covers(pfx)));
+            if !covering.last().is_some_and(|c| c.covers(pfx)) {
+                covering.push(pfx);
+            }
+        }
+        assert_eq!(
+            res.iter().map(|p| p.num_addresses()).sum::<u128>(),
+            covering.iter().map(|p| p.num_addresses()).sum::<u128>()
+        );
+    }
+}
@@ -46,7 +46,7 @@ mod test {
             let start = std::time::Instant::now();
 
             for pfx in pfxs.iter() {
-                radix_trie.insert(pfx);
+                radix_trie.insert(*pfx);
             }
             let ready = std::time::Instant::now();
 
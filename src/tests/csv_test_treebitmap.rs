@@ -59,7 +59,7 @@ mod test {
                 let start = std::time::Instant::now();
 
                 for pfx in pfxs.iter() {
-                    tree_bitmap.insert(pfx);
+                    tree_bitmap.insert(*pfx);
                 }
                 let ready = std::time::Instant::now();
                 let dur_insert_nanos = ready.checked_duration_since(start).unwrap().as_nanos();
@@ -0,0 +1,146 @@
+#![allow(unused_imports)]
+#[cfg(test)]
+mod test {
+    use crate::common::{NoMeta, Prefix, PrefixAs};
+    use crate::stride_layout;
+    use crate::treebitmap;
+    use crate::treebitmap_static::{StrideLayout, TreeBitMap};
+    use std::error::Error;
+    use std::fs::File;
+    use std::process;
+
+    // What the runs need from both trees.
+    trait Bench {
+        fn type_name(&self) -> &'static str;
+        fn insert(&mut self, pfx: Prefix<u32, PrefixAs>);
+        fn search(&self, pfx: &Prefix<u32, NoMeta>) -> usize;
+    }
+
+    impl Bench for treebitmap::TreeBitMap<u32, PrefixAs> {
+        fn type_name(&self) -> &'static str {
+            "treebitmap_localvec"
+        }
+        fn insert(&mut self, pfx: Prefix<u32, PrefixAs>) {
+            self.insert(pfx);
+        }
+        fn search(&self, pfx: &Prefix<u32, NoMeta>) -> usize {
+            self.match_longest_prefix(pfx).len()
+        }
+    }
+
+    impl<L: StrideLayout<u32, PrefixAs>> Bench for TreeBitMap<u32, PrefixAs, L> {
+        fn type_name(&self) -> &'static str {
+            "treebitmap_static"
+        }
+        fn insert(&mut self, pfx: Prefix<u32, PrefixAs>) {
+            self.insert(pfx);
+        }
+        fn search(&self, pfx: &Prefix<u32, NoMeta>) -> usize {
+            self.match_longest_prefix(pfx).len()
+        }
+    }
+
+    // Compares the TreeBitMap with its strides fixed at compile time to the
+    // runtime configured one with the same strides, in the output format of
+    // the other csv tests. The number of prefixes found is printed as well,
+    // it should be the same for both. Run with:
+    // `cargo test --release tests::csv_test_treebitmap_static::test -- --show-output --test-threads=1`
+    #[test]
+    fn test_csv() {
+        const CSV_FILE_PATH: &str = "./data/test.csv";
+
+        fn load_prefixes(pfxs: &mut Vec<Prefix<u32, PrefixAs>>) -> Result<(), Box<dyn Error>> {
+            let file = File::open(CSV_FILE_PATH)?;
+            let mut rdr = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .from_reader(file);
+            for result in rdr.records() {
+                let record = result?;
+                let net: std::net::Ipv4Addr = record[0].parse()?;
+                let len: u8 = record[1].parse()?;
+                let asn: u32 = record[2].parse()?;
+                let pfx = Prefix::<u32, PrefixAs>::new_with_meta(net.into(), len, PrefixAs(asn));
+                pfxs.push(pfx);
+            }
+            Ok(())
+        }
+
+        fn run<B: Bench>(tree: &mut B, strides: &[u8], n: u8, pfxs: &[Prefix<u32, PrefixAs>]) {
+            let start = std::time::Instant::now();
+            for pfx in pfxs.iter() {
+                tree.insert(*pfx);
+            }
+            let ready = std::time::Instant::now();
+            let dur_insert_nanos = ready.checked_duration_since(start).unwrap().as_nanos();
+            let inserts_num = pfxs.len();
+
+            let inet_max = 255;
+            let len_max = 32;
+
+            let mut found_num = 0;
+            let start = std::time::Instant::now();
+            for i_net in 0..inet_max {
+                for s_len in 0..len_max {
+                    for ii_net in 0..inet_max {
+                        let pfx = Prefix::<u32, NoMeta>::new(
+                            std::net::Ipv4Addr::new(i_net, ii_net, 0, 0).into(),
+                            s_len,
+                        );
+                        found_num += tree.search(&pfx);
+                    }
+                }
+            }
+            let ready = std::time::Instant::now();
+
+            let dur_search_nanos = ready.checked_duration_since(start).unwrap().as_nanos();
+            let searches_num = inet_max as u128 * inet_max as u128 * len_max as u128;
+
+            println!("{{");
+            println!("\"type\": \"{}\",", tree.type_name());
+            println!("\"strides\": {:?},", strides);
+            println!("\"run_no\": {},", n);
+            println!("\"inserts_num\": {},", inserts_num);
+            println!("\"insert_duration_nanos\": {},", dur_insert_nanos);
+            println!(
+                "\"insert_time_nanos\": {},",
+                dur_insert_nanos as f32 / inserts_num as f32
+            );
+            println!("\"searches_num\": {},", searches_num);
+            println!("\"found_num\": {},", found_num);
+            println!("\"search_duration_nanos\": {},", dur_search_nanos);
+            println!(
+                "\"search_time_nanos\": {}",
+                dur_search_nanos as f32 / searches_num as f32
+            );
+        }
+
+        // Alternates runs of both trees with the stride layout `L`.
+        fn run_both<L: StrideLayout<u32, PrefixAs>>(pfxs: &[Prefix<u32, PrefixAs>], last: bool) {
+            let strides = TreeBitMap::<u32, PrefixAs, L>::new().strides();
+            println!("[");
+            for n in 1..6 {
+                let mut tree_bitmap = treebitmap::TreeBitMap::<u32, PrefixAs>::new(strides.clone());
+                run(&mut tree_bitmap, &strides, n, pfxs);
+                println!("}},");
+                let mut tree_bitmap = TreeBitMap::<u32, PrefixAs, L>::new();
+                run(&mut tree_bitmap, &strides, n, pfxs);
+                println!("}}{}", if n != 5 { "," } else { "" });
+            }
+            println!("]{}", if last { "" } else { "," });
+        }
+
+        let mut pfxs: Vec<Prefix<u32, PrefixAs>> = vec![];
+        if let Err(err) = load_prefixes(&mut pfxs) {
+            println!("error running example: {}", err);
+            process::exit(1);
+        }
+
+        println!("[");
+        run_both::<stride_layout![4, 4, 4, 4, 4, 4, 4, 4]>(&pfxs, false);
+        run_both::<stride_layout![6, 6, 6, 6, 4, 4]>(&pfxs, false);
+        run_both::<stride_layout![3, 4, 5, 6, 5, 5, 4]>(&pfxs, false);
+        run_both::<stride_layout![8, 8, 8, 8]>(&pfxs, false);
+        run_both::<stride_layout![3, 4, 4, 6, 7, 8]>(&pfxs, true);
+        println!("]");
+    }
+}
@@ -47,7 +47,7 @@ mod test {
             let start = std::time::Instant::now();
 
             for pfx in pfxs.iter() {
-                trie.insert(pfx);
+                trie.insert(*pfx);
             }
             let ready = std::time::Instant::now();
 
@@ -0,0 +1,67 @@
+#[cfg(test)]
+mod test {
+    use crate::common::{PrefixAs, PrefixParseError};
+    use crate::dualstack::{DualStackTable, IpPrefix};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn test_dualstack() {
+        let mut table = DualStackTable::<PrefixAs>::new(vec![4], vec![6, 6, 6, 6, 4, 4]);
+        let v4: IpAddr = Ipv4Addr::new(193, 0, 10, 10).into();
+        let v6: IpAddr = Ipv6Addr::new(0x2001, 0x67c, 0x1bc, 0, 0, 0, 0, 1).into();
+        let pfx = |addr: IpAddr, len: u8| IpPrefix::new(addr, len).unwrap();
+
+        // The length has to fit the address family.
+        assert_eq!(
+            IpPrefix::new(v4, 33),
+            Err(PrefixParseError::LengthOutOfRange(33))
+        );
+        assert_eq!(
+            IpPrefix::new(v6, 129),
+            Err(PrefixParseError::LengthOutOfRange(129))
+        );
+        assert!(IpPrefix::new(v4, 32).is_ok());
+        assert_eq!(pfx(v6, 128).addr(), v6);
+        assert_eq!(
+            pfx(v4, 24).addr(),
+            IpAddr::from(Ipv4Addr::new(193, 0, 10, 0))
+        );
+
+        // The host bits beyond the length are cleared on insert.
+        assert_eq!(table.insert(pfx(v4, 16), PrefixAs(1)), None);
+        assert_eq!(table.insert(pfx(v4, 24), PrefixAs(2)), None);
+        assert_eq!(table.insert(pfx(v6, 32), PrefixAs(3)), None);
+        assert_eq!(table.insert(pfx(v6, 48), PrefixAs(40)), None);
+        assert_eq!(table.insert(pfx(v6, 48), PrefixAs(4)), Some(PrefixAs(40)));
+        assert_eq!(table.v4.iter().count(), 2);
+        assert_eq!(table.v6.iter().count(), 2);
+
+        table.upsert(pfx(v4, 16), PrefixAs(10), |old, new| old.0 += new.0);
+        assert_eq!(table.get(pfx(v4, 16)).unwrap().meta(), Some(&PrefixAs(11)));
+        table.upsert(pfx(v4, 16), PrefixAs(1), |old, new| *old = new);
+
+        let res = table.get(pfx(v4, 24)).unwrap();
+        assert_eq!(res.addr(), IpAddr::from(Ipv4Addr::new(193, 0, 10, 0)));
+        assert_eq!(res.prefix_len(), 24);
+        assert_eq!(res.meta(), Some(&PrefixAs(2)));
+        assert!(table.get(pfx(v4, 23)).is_none());
+
+        assert_eq!(table.lookup(v4).unwrap().meta(), Some(&PrefixAs(2)));
+        assert_eq!(table.lookup(v6).unwrap().meta(), Some(&PrefixAs(4)));
+        let other_v6: IpAddr = Ipv6Addr::new(0x2001, 0x67c, 0xffff, 0, 0, 0, 0, 1).into();
+        assert_eq!(table.lookup(other_v6).unwrap().meta(), Some(&PrefixAs(3)));
+        assert_eq!(
+            table.match_longest_prefix(pfx(v4, 20)).unwrap().meta(),
+            Some(&PrefixAs(1))
+        );
+        assert!(table.lookup(Ipv4Addr::new(10, 0, 0, 1).into()).is_none());
+
+        let lens: Vec<u8> = table.iter().map(|p| p.prefix_len()).collect();
+        assert_eq!(lens, vec![16, 24, 32, 48]);
+
+        assert_eq!(table.remove(pfx(v6, 48)), Some(PrefixAs(4)));
+        assert!(table.remove(pfx(v6, 48)).is_none());
+        assert_eq!(table.lookup(v6).unwrap().meta(), Some(&PrefixAs(3)));
+        assert_eq!(table.lookup(v4).unwrap().meta(), Some(&PrefixAs(2)));
+    }
+}
@@ -4,4 +4,13 @@ mod csv_test_treebitmap_univec;
 mod csv_test_treebitmap;
 mod csv_test_radixtrie;
 mod csv_test_trie;
-mod radix;
\ No newline at end of file
+mod radix;
+mod prefix_store;
+mod trie;
+mod dualstack;
+mod prefix;
+mod aggregate;
+mod ortc;
+mod vpn;
+mod treebitmap_static;
+mod csv_test_treebitmap_static;
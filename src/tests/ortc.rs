@@ -0,0 +1,166 @@
+#[cfg(test)]
+mod test {
+    use crate::common::*;
+    use crate::ortc::{compress, forwarding_equivalent};
+    use crate::treebitmap::TreeBitMap;
+
+    fn tree(strs: &[(&str, Option<u32>)]) -> TreeBitMap<u32, PrefixAs> {
+        let mut tree = TreeBitMap::new(vec![4]);
+        for (s, asn) in strs {
+            let pfx: Prefix<u32, NoMeta> = s.parse().unwrap();
+            tree.insert(match asn {
+                Some(asn) => Prefix::new_with_meta(pfx.net, pfx.len, PrefixAs(*asn)),
+                None => Prefix::new(pfx.net, pfx.len),
+            });
+        }
+        tree
+    }
+
+    fn strs(tree: &TreeBitMap<u32, PrefixAs>) -> Vec<(String, Option<u32>)> {
+        tree.iter()
+            .map(|p| (p.to_string(), p.meta.map(|m| m.0)))
+            .collect()
+    }
+
+    #[test]
+    fn test_compress() {
+        // More-specifics with the same next hop as their covering prefix
+        // go.
+        let (res, stats) = compress(&tree(&[
+            ("10.0.0.0/8", Some(1)),
+            ("10.0.0.0/9", Some(1)),
+            ("10.1.0.0/16", Some(1)),
+        ]));
+        assert_eq!(strs(&res), vec![("10.0.0.0/8".to_string(), Some(1))]);
+        assert_eq!(stats.prefixes_before, 3);
+        assert_eq!(stats.prefixes_after, 1);
+        assert!(stats.equivalent);
+        assert!((stats.compression_ratio() - 1.0 / 3.0).abs() < 1e-9);
+
+        // The next hop used most below a prefix takes its place.
+        let (res, stats) = compress(&tree(&[
+            ("10.0.0.0/8", Some(1)),
+            ("10.0.0.0/9", Some(2)),
+            ("10.128.0.0/10", Some(2)),
+            ("10.192.0.0/10", Some(2)),
+        ]));
+        assert_eq!(strs(&res), vec![("10.0.0.0/8".to_string(), Some(2))]);
+        assert!(stats.equivalent);
+
+        // A covering prefix whose next hop isn't used anywhere takes over
+        // the next hop of one of its halves.
+        let (res, stats) = compress(&tree(&[
+            ("10.0.0.0/8", Some(3)),
+            ("10.0.0.0/9", Some(1)),
+            ("10.128.0.0/9", Some(2)),
+            ("10.255.0.0/16", Some(1)),
+        ]));
+        assert_eq!(
+            strs(&res),
+            vec![
+                ("10.0.0.0/8".to_string(), Some(1)),
+                ("10.128.0.0/9".to_string(), Some(2)),
+                ("10.255.0.0/16".to_string(), Some(1)),
+            ]
+        );
+        assert_eq!(stats.prefixes_after, 3);
+        assert!(stats.equivalent);
+
+        // Where part of the address space has no route, that part can be
+        // punched out of a merged prefix with a prefix without next hop.
+        let (res, stats) = compress(&tree(&[
+            ("10.0.0.0/10", Some(1)),
+            ("10.64.0.0/10", Some(1)),
+            ("10.128.0.0/10", Some(1)),
+        ]));
+        assert_eq!(
+            strs(&res),
+            vec![
+                ("10.0.0.0/8".to_string(), Some(1)),
+                ("10.192.0.0/10".to_string(), None),
+            ]
+        );
+        assert!(stats.equivalent);
+        // Prefixes without next hop in the input may just as well go.
+        let (res, stats) = compress(&tree(&[
+            ("10.0.0.0/8", Some(1)),
+            ("10.0.0.0/9", None),
+            ("10.0.0.0/10", None),
+        ]));
+        assert_eq!(strs(&res), vec![("10.128.0.0/9".to_string(), Some(1))]);
+        assert!(stats.equivalent);
+
+        // The default route is a prefix like any other.
+        let (res, stats) = compress(&tree(&[
+            ("0.0.0.0/0", Some(1)),
+            ("0.0.0.0/1", Some(2)),
+            ("128.0.0.0/1", Some(2)),
+        ]));
+        assert_eq!(strs(&res), vec![("0.0.0.0/0".to_string(), Some(2))]);
+        assert!(stats.equivalent);
+
+        let (res, stats) = compress(&tree(&[]));
+        assert_eq!(res.iter().count(), 0);
+        assert!(stats.equivalent);
+        assert!((stats.compression_ratio() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_forwarding_equivalent() {
+        let a = tree(&[("10.0.0.0/9", Some(1)), ("10.128.0.0/9", Some(1))]);
+        assert!(forwarding_equivalent(&a, &tree(&[("10.0.0.0/8", Some(1))])));
+        assert!(!forwarding_equivalent(&a, &tree(&[("10.0.0.0/8", Some(2))])));
+        assert!(!forwarding_equivalent(
+            &a,
+            &tree(&[("10.0.0.0/8", Some(1)), ("10.1.2.3/32", None)])
+        ));
+        assert!(!forwarding_equivalent(&a, &tree(&[("10.0.0.0/7", Some(1))])));
+        assert!(forwarding_equivalent(
+            &a,
+            &tree(&[("10.0.0.0/7", Some(1)), ("11.0.0.0/8", None)])
+        ));
+    }
+
+    // Compresses `data/test.csv` and compares the next hops of both
+    // tables at a spread of addresses, independent of the verification
+    // in `compress`.
+    #[test]
+    fn test_compress_table() {
+        let mut tree_bitmap = TreeBitMap::<u32, PrefixAs>::new(vec![3, 4, 5, 6, 5, 5, 4]);
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_path("./data/test.csv")
+            .unwrap();
+        for record in rdr.records() {
+            let record = record.unwrap();
+            let net: std::net::Ipv4Addr = record[0].parse().unwrap();
+            let len: u8 = record[1].parse().unwrap();
+            let asn: u32 = record[2].parse().unwrap();
+            tree_bitmap.insert(Prefix::new_with_meta(net.into(), len, PrefixAs(asn)));
+        }
+
+        let (res, stats) = compress(&tree_bitmap);
+        assert!(stats.equivalent);
+        assert_eq!(stats.prefixes_before, tree_bitmap.iter().count());
+        assert_eq!(stats.prefixes_after, res.iter().count());
+        assert!(stats.prefixes_after < stats.prefixes_before);
+        assert_eq!(res.strides, tree_bitmap.strides);
+
+        let next_hop = |tree: &TreeBitMap<u32, PrefixAs>, addr: u32| {
+            tree.match_longest_prefix(&Prefix::new(addr, 32))
+                .pop()
+                .and_then(|p| p.meta)
+        };
+        let mut addr: u32 = 1;
+        for _ in 0..100_000 {
+            addr = addr.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            assert_eq!(next_hop(&tree_bitmap, addr), next_hop(&res, addr));
+        }
+        for pfx in tree_bitmap.iter() {
+            assert_eq!(
+                next_hop(&tree_bitmap, pfx.last_addr()),
+                next_hop(&res, pfx.last_addr())
+            );
+        }
+    }
+}
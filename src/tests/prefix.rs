@@ -0,0 +1,285 @@
+#[cfg(test)]
+mod test {
+    use crate::common::*;
+
+    #[test]
+    fn test_parse_ipv4() {
+        let pfx: Prefix<u32, NoMeta> = "192.0.2.0/24".parse().unwrap();
+        assert_eq!(pfx.net, u32::from(std::net::Ipv4Addr::new(192, 0, 2, 0)));
+        assert_eq!(pfx.len, 24);
+
+        let pfx: Prefix<u32, NoMeta> = "0.0.0.0/0".parse().unwrap();
+        assert_eq!((pfx.net, pfx.len), (0, 0));
+
+        assert_eq!(
+            "192.0.2.0".parse::<Prefix<u32, NoMeta>>().unwrap_err(),
+            PrefixParseError::MissingLength
+        );
+        assert!(matches!(
+            "192.0.2/24".parse::<Prefix<u32, NoMeta>>(),
+            Err(PrefixParseError::InvalidAddress(_))
+        ));
+        assert!(matches!(
+            "2001:db8::/32".parse::<Prefix<u32, NoMeta>>(),
+            Err(PrefixParseError::InvalidAddress(_))
+        ));
+        assert_eq!(
+            "192.0.2.0/x".parse::<Prefix<u32, NoMeta>>().unwrap_err(),
+            PrefixParseError::InvalidLength
+        );
+        assert_eq!(
+            "192.0.2.0/33".parse::<Prefix<u32, NoMeta>>().unwrap_err(),
+            PrefixParseError::LengthOutOfRange(33)
+        );
+        assert_eq!(
+            "192.0.2.1/24".parse::<Prefix<u32, NoMeta>>().unwrap_err(),
+            PrefixParseError::HostBitsSet
+        );
+
+        let pfx = Prefix::<u32, NoMeta>::parse("192.0.2.1/24", ParseMode::Masking).unwrap();
+        assert_eq!(pfx.net, u32::from(std::net::Ipv4Addr::new(192, 0, 2, 0)));
+        assert_eq!(pfx.to_string(), "192.0.2.0/24");
+    }
+
+    #[test]
+    fn test_parse_ipv6() {
+        let pfx: Prefix<u128, NoMeta> = "2001:db8::/32".parse().unwrap();
+        assert_eq!(pfx.net, 0x2001_0db8 << 96);
+        assert_eq!(pfx.len, 32);
+
+        let pfx: Prefix<u128, NoMeta> = "::1/128".parse().unwrap();
+        assert_eq!((pfx.net, pfx.len), (1, 128));
+
+        assert!(matches!(
+            "192.0.2.0/24".parse::<Prefix<u128, NoMeta>>(),
+            Err(PrefixParseError::InvalidAddress(_))
+        ));
+        assert_eq!(
+            "2001:db8::/129"
+                .parse::<Prefix<u128, NoMeta>>()
+                .unwrap_err(),
+            PrefixParseError::LengthOutOfRange(129)
+        );
+        assert_eq!(
+            "2001:db8::1/64"
+                .parse::<Prefix<u128, NoMeta>>()
+                .unwrap_err(),
+            PrefixParseError::HostBitsSet
+        );
+
+        let pfx = Prefix::<u128, NoMeta>::parse("2001:db8::1/64", ParseMode::Masking).unwrap();
+        assert_eq!(pfx.to_string(), "2001:db8::/64");
+    }
+
+    #[test]
+    fn test_parse_u64() {
+        let pfx: Prefix<u64, NoMeta> = "2001:db8:1::/48".parse().unwrap();
+        assert_eq!(pfx.net, 0x2001_0db8_0001_0000);
+        assert_eq!(pfx.len, 48);
+        assert_eq!(pfx.to_string(), "2001:db8:1::/48");
+
+        let pfx: Prefix<u64, NoMeta> = "2001:db8:1:2::/64".parse().unwrap();
+        assert_eq!((pfx.net, pfx.len), (0x2001_0db8_0001_0002, 64));
+        assert_eq!(pfx.last_addr(), pfx.net);
+        // The interface identifier doesn't fit in the key, so it can't be
+        // set, whatever the mode.
+        assert_eq!(
+            "2001:db8:1:2::1/64"
+                .parse::<Prefix<u64, NoMeta>>()
+                .unwrap_err(),
+            PrefixParseError::HostBitsSet
+        );
+        assert_eq!(
+            Prefix::<u64, NoMeta>::parse("2001:db8::1/64", ParseMode::Masking).unwrap_err(),
+            PrefixParseError::HostBitsSet
+        );
+
+        assert_eq!(
+            "2001:db8::/65".parse::<Prefix<u64, NoMeta>>().unwrap_err(),
+            PrefixParseError::LengthOutOfRange(65)
+        );
+        assert!(matches!(
+            "192.0.2.0/24".parse::<Prefix<u64, NoMeta>>(),
+            Err(PrefixParseError::InvalidAddress(_))
+        ));
+
+        assert_eq!(u64::get_nibble(0x2001_0db8_0001_0002, 0, 4), 0x2);
+        assert_eq!(u64::get_nibble(0x2001_0db8_0001_0002, 60, 4), 0x2);
+        assert_eq!(u64::get_nibble(0x2001_0db8_0001_0002, 16, 8), 0x0d);
+    }
+
+    #[test]
+    fn test_display() {
+        let pfx = Prefix::<u32, PrefixAs>::new_with_meta(
+            std::net::Ipv4Addr::new(193, 0, 10, 0).into(),
+            24,
+            PrefixAs(1),
+        );
+        assert_eq!(format!("{}", pfx), "193.0.10.0/24");
+        assert_eq!(format!("{:?}", pfx), "193.0.10.0/24 with Some(PrefixAs(1))");
+        assert_eq!(Prefix::<u32, NoMeta>::new(0, 0).to_string(), "0.0.0.0/0");
+    }
+
+    #[test]
+    fn test_canonical_ipv4() {
+        // Host bits are cleared on construction.
+        let pfx = Prefix::<u32, NoMeta>::new(std::net::Ipv4Addr::new(10, 1, 2, 3).into(), 8);
+        assert_eq!(pfx.net, u32::from(std::net::Ipv4Addr::new(10, 0, 0, 0)));
+        let pfx = Prefix::<u32, NoMeta>::new(std::net::Ipv4Addr::new(10, 1, 2, 3).into(), 0);
+        assert_eq!(pfx.net, 0);
+
+        // Equal network and different lengths aren't equal, ordering is by
+        // network first and length second.
+        let p8: Prefix<u32, NoMeta> = "10.0.0.0/8".parse().unwrap();
+        let p16: Prefix<u32, NoMeta> = "10.0.0.0/16".parse().unwrap();
+        let p11: Prefix<u32, NoMeta> = "11.0.0.0/8".parse().unwrap();
+        assert_ne!(p8, p16);
+        assert!(p8 < p16 && p16 < p11);
+
+        // The meta data isn't part of the identity of a prefix.
+        let a = Prefix::<u32, PrefixAs>::new_with_meta(p8.net, 8, PrefixAs(1));
+        let b = Prefix::<u32, PrefixAs>::new_with_meta(p8.net, 8, PrefixAs(2));
+        assert_eq!(a, b);
+
+        let mut set = std::collections::HashSet::new();
+        assert!(set.insert(a));
+        assert!(!set.insert(b));
+        assert!(set.insert(Prefix::new_with_meta(p16.net, 16, PrefixAs(1))));
+
+        // Copy as long as the meta data is Copy.
+        let c = p8;
+        assert_eq!(c, p8);
+    }
+
+    #[test]
+    fn test_canonical_ipv6() {
+        let mut pfxs: Vec<Prefix<u128, NoMeta>> = [
+            "2001:db8:1::/48",
+            "2001:db8::/32",
+            "::/0",
+            "2001:db8::/48",
+            "ff00::/8",
+            "2001:db8::/33",
+            "2001::/16",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+        pfxs.sort();
+        let sorted: Vec<String> = pfxs.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            sorted,
+            vec![
+                "::/0",
+                "2001::/16",
+                "2001:db8::/32",
+                "2001:db8::/33",
+                "2001:db8::/48",
+                "2001:db8:1::/48",
+                "ff00::/8",
+            ]
+        );
+
+        let pfx = Prefix::<u128, NoMeta>::new(u128::MAX, 64);
+        assert_eq!(pfx.to_string(), "ffff:ffff:ffff:ffff::/64");
+        let mut map = std::collections::HashMap::new();
+        map.insert(pfx, 1);
+        assert_eq!(
+            map.get(&"ffff:ffff:ffff:ffff::/64".parse().unwrap()),
+            Some(&1)
+        );
+    }
+
+    #[test]
+    fn test_algebra_ipv4() {
+        let p16: Prefix<u32, NoMeta> = "193.0.0.0/16".parse().unwrap();
+        let p24: Prefix<u32, NoMeta> = "193.0.10.0/24".parse().unwrap();
+        let other: Prefix<u32, NoMeta> = "193.1.0.0/16".parse().unwrap();
+        let all: Prefix<u32, NoMeta> = "0.0.0.0/0".parse().unwrap();
+
+        assert!(p16.contains(std::net::Ipv4Addr::new(193, 0, 255, 255).into()));
+        assert!(!p16.contains(std::net::Ipv4Addr::new(193, 1, 0, 0).into()));
+        assert!(all.contains(u32::MAX));
+
+        assert!(p16.covers(&p24) && p16.covers(&p16) && !p24.covers(&p16));
+        assert!(all.covers(&p24));
+        let with_meta = Prefix::<u32, PrefixAs>::new_with_meta(p24.net, 24, PrefixAs(1));
+        assert!(p16.covers(&with_meta));
+        assert!(p16.overlaps(&p24) && p24.overlaps(&p16));
+        assert!(!p16.overlaps(&other));
+
+        assert_eq!(p24.supernet(8), Some(p16));
+        assert_eq!(p24.supernet(24), Some(all));
+        assert_eq!(p24.supernet(25), None);
+
+        let subnets: Vec<String> = p24.subnets(26).map(|p| p.to_string()).collect();
+        assert_eq!(
+            subnets,
+            vec![
+                "193.0.10.0/26",
+                "193.0.10.64/26",
+                "193.0.10.128/26",
+                "193.0.10.192/26"
+            ]
+        );
+        assert_eq!(p24.subnets(24).collect::<Vec<_>>(), vec![p24]);
+        assert_eq!(p24.subnets(23).count(), 0);
+        assert_eq!(p24.subnets(32).count(), 256);
+        assert_eq!(p24.subnets(33).count(), 0);
+        assert_eq!(all.subnets(1).count(), 2);
+        assert_eq!(all.subnets(0).collect::<Vec<_>>(), vec![all]);
+        let last: Prefix<u32, NoMeta> = "255.255.255.255/32".parse().unwrap();
+        assert_eq!(last.supernet(4).unwrap().subnets(32).last(), Some(last));
+
+        assert_eq!(
+            p24.first_addr(),
+            u32::from(std::net::Ipv4Addr::new(193, 0, 10, 0))
+        );
+        assert_eq!(
+            p24.last_addr(),
+            u32::from(std::net::Ipv4Addr::new(193, 0, 10, 255))
+        );
+        assert_eq!(all.last_addr(), u32::MAX);
+        assert_eq!(p24.num_addresses(), 256);
+        assert_eq!(all.num_addresses(), 1 << 32);
+        assert_eq!(last.num_addresses(), 1);
+    }
+
+    #[test]
+    fn test_algebra_ipv6() {
+        let p32: Prefix<u128, NoMeta> = "2001:db8::/32".parse().unwrap();
+        let p48: Prefix<u128, NoMeta> = "2001:db8:1::/48".parse().unwrap();
+        let all: Prefix<u128, NoMeta> = "::/0".parse().unwrap();
+
+        let addr: std::net::Ipv6Addr = "2001:db8:1::1".parse().unwrap();
+        assert!(p32.contains(addr.into()) && p48.contains(addr.into()));
+        assert!(!p48.contains(p32.net));
+        assert!(p32.covers(&p48) && !p48.covers(&p32));
+        assert!(p32.overlaps(&p48) && all.overlaps(&p48));
+
+        assert_eq!(p48.supernet(16), Some(p32));
+        assert_eq!(p48.supernet(48), Some(all));
+
+        let subnets: Vec<String> = p32.subnets(34).map(|p| p.to_string()).collect();
+        assert_eq!(
+            subnets,
+            vec![
+                "2001:db8::/34",
+                "2001:db8:4000::/34",
+                "2001:db8:8000::/34",
+                "2001:db8:c000::/34"
+            ]
+        );
+        assert_eq!(all.subnets(1).count(), 2);
+        let last: Prefix<u128, NoMeta> = "ffff::/16".parse().unwrap();
+        assert_eq!(all.subnets(16).last(), Some(last));
+        assert_eq!(p48.subnets(129).count(), 0);
+
+        assert_eq!(
+            std::net::Ipv6Addr::from(p48.last_addr()).to_string(),
+            "2001:db8:1:ffff:ffff:ffff:ffff:ffff"
+        );
+        assert_eq!(p48.num_addresses(), 1 << 80);
+        assert_eq!(all.num_addresses(), u128::MAX);
+    }
+}
@@ -0,0 +1,255 @@
+#[cfg(test)]
+mod test {
+    use crate::common::*;
+    use crate::radixtrie::RadixTrie;
+    use crate::treebitmap;
+    use crate::treebitmap_univec;
+
+    fn pfxs() -> Vec<Prefix<u32, PrefixAs>> {
+        vec![
+            Prefix::new_with_meta(std::net::Ipv4Addr::new(0, 0, 0, 0).into(), 1, PrefixAs(1)),
+            Prefix::new_with_meta(
+                std::net::Ipv4Addr::new(192, 0, 0, 0).into(),
+                16,
+                PrefixAs(2),
+            ),
+            Prefix::new_with_meta(
+                std::net::Ipv4Addr::new(192, 0, 0, 0).into(),
+                23,
+                PrefixAs(3),
+            ),
+            Prefix::new_with_meta(
+                std::net::Ipv4Addr::new(192, 0, 10, 0).into(),
+                24,
+                PrefixAs(4),
+            ),
+            Prefix::new_with_meta(
+                std::net::Ipv4Addr::new(193, 0, 10, 0).into(),
+                24,
+                PrefixAs(5),
+            ),
+            Prefix::new_with_meta(
+                std::net::Ipv4Addr::new(255, 255, 255, 255).into(),
+                32,
+                PrefixAs(6),
+            ),
+        ]
+    }
+
+    // Runs the same scenario against every store, only through the trait.
+    fn exercise<S: PrefixStore<u32, PrefixAs>>(store: &mut S) {
+        for pfx in pfxs() {
+            assert!(store.insert(pfx).is_none());
+        }
+        assert_eq!(store.iter().count(), pfxs().len());
+
+        // Inserting a stored prefix again replaces it and hands back the
+        // old one, upsert merges the meta data instead.
+        let dpfx = Prefix::<u32, NoMeta>::new(std::net::Ipv4Addr::new(192, 0, 10, 0).into(), 24);
+        let old = store.insert(Prefix::new_with_meta(dpfx.net, dpfx.len, PrefixAs(40)));
+        assert_eq!(old.unwrap().meta, Some(PrefixAs(4)));
+        assert_eq!(store.get(&dpfx).unwrap().meta, Some(PrefixAs(40)));
+        store.upsert(Prefix::new_with_meta(dpfx.net, dpfx.len, PrefixAs(2)), |old, new| {
+            old.0 += new.0
+        });
+        assert_eq!(store.get(&dpfx).unwrap().meta, Some(PrefixAs(42)));
+        store.upsert(Prefix::new(dpfx.net, dpfx.len), |_, _| panic!("nothing to merge"));
+        assert_eq!(store.get(&dpfx).unwrap().meta, Some(PrefixAs(42)));
+        store.insert(Prefix::new(dpfx.net, dpfx.len));
+        store.upsert(Prefix::new_with_meta(dpfx.net, dpfx.len, PrefixAs(4)), |_, _| {
+            panic!("nothing to merge")
+        });
+        assert_eq!(store.get(&dpfx).unwrap().meta, Some(PrefixAs(4)));
+        assert_eq!(store.iter().count(), pfxs().len());
+
+        let npfx = Prefix::<u32, NoMeta>::new(std::net::Ipv4Addr::new(10, 0, 0, 0).into(), 8);
+        store.upsert(Prefix::new_with_meta(npfx.net, npfx.len, PrefixAs(8)), |_, _| {
+            panic!("nothing to merge")
+        });
+        assert_eq!(store.get(&npfx).unwrap().meta, Some(PrefixAs(8)));
+        assert!(store.remove(&npfx).is_some());
+        assert_eq!(store.iter().count(), pfxs().len());
+
+        for pfx in pfxs().iter() {
+            let pfx_nm = pfx.strip_meta();
+            assert_eq!(store.get(&pfx_nm).unwrap().meta, pfx.meta);
+            assert_eq!(store.match_longest_prefix(&pfx_nm).unwrap().meta, pfx.meta);
+        }
+
+        let mpfx = Prefix::<u32, NoMeta>::new(std::net::Ipv4Addr::new(193, 0, 10, 0).into(), 24);
+        store.get_mut(&mpfx).unwrap().meta = Some(PrefixAs(55));
+        assert_eq!(store.get(&mpfx).unwrap().meta, Some(PrefixAs(55)));
+        store.get_mut(&mpfx).unwrap().meta = Some(PrefixAs(5));
+
+        let spfx = Prefix::<u32, NoMeta>::new(std::net::Ipv4Addr::new(192, 0, 1, 0).into(), 24);
+        assert!(store.get(&spfx).is_none());
+        assert!(store.get_mut(&spfx).is_none());
+        let spfx_short = Prefix::<u32, NoMeta>::new(std::net::Ipv4Addr::new(192, 0, 0, 0).into(), 22);
+        assert!(store.get(&spfx_short).is_none());
+        assert_eq!(
+            store.match_longest_prefix(&spfx).unwrap().meta,
+            Some(PrefixAs(3))
+        );
+
+        let rpfx = Prefix::<u32, NoMeta>::new(std::net::Ipv4Addr::new(192, 0, 0, 0).into(), 23);
+        assert_eq!(store.remove(&rpfx).unwrap().meta, Some(PrefixAs(3)));
+        assert!(store.remove(&rpfx).is_none());
+        assert!(store.get(&rpfx).is_none());
+        assert_eq!(
+            store.match_longest_prefix(&spfx).unwrap().meta,
+            Some(PrefixAs(2))
+        );
+        assert_eq!(store.iter().count(), pfxs().len() - 1);
+
+        // The default route matches whatever isn't covered by anything else.
+        let upfx = Prefix::<u32, NoMeta>::new(std::net::Ipv4Addr::new(200, 0, 0, 0).into(), 24);
+        assert!(store.match_longest_prefix(&upfx).is_none());
+        store.insert(Prefix::new_with_meta(0, 0, PrefixAs(7)));
+        assert_eq!(store.match_longest_prefix(&upfx).unwrap().meta, Some(PrefixAs(7)));
+        assert_eq!(store.match_longest_prefix(&spfx).unwrap().meta, Some(PrefixAs(2)));
+        assert_eq!(store.remove(&Prefix::new(0, 0)).unwrap().meta, Some(PrefixAs(7)));
+        assert!(store.match_longest_prefix(&upfx).is_none());
+    }
+
+    // Inserts a mix of nested and adjacent prefixes out of order, both
+    // `iter` and `IntoIterator` should hand them back ordered by network
+    // first and length second.
+    fn exercise_order<S>(store: &mut S)
+    where
+        S: PrefixStore<u32, PrefixAs>,
+        for<'a> &'a S: IntoIterator<Item = &'a Prefix<u32, PrefixAs>>,
+    {
+        let mut pfxs: Vec<(u32, u8)> = vec![];
+        for (i, len) in [24, 8, 32, 16, 12, 1, 23, 31, 20, 9].iter().enumerate() {
+            for net in [
+                std::net::Ipv4Addr::new(193, 0, 10, 128),
+                std::net::Ipv4Addr::new(10, 255, 0, 1),
+                std::net::Ipv4Addr::new(193, 0, 11, 0),
+                std::net::Ipv4Addr::new(255, 255, 255, 255),
+            ]
+            .iter()
+            {
+                let net = u32::from(*net) & !(u32::MAX.checked_shr(*len as u32).unwrap_or(0));
+                pfxs.push((net, *len));
+                store.insert(Prefix::new_with_meta(net, *len, PrefixAs(i as u32)));
+            }
+        }
+        pfxs.sort_unstable();
+        pfxs.dedup();
+
+        let found: Vec<(u32, u8)> = store.iter().map(|p| (p.net, p.len)).collect();
+        assert_eq!(found, pfxs);
+        let found: Vec<(u32, u8)> = (&*store).into_iter().map(|p| (p.net, p.len)).collect();
+        assert_eq!(found, pfxs);
+    }
+
+    // IPv6 routing on up to /64 with 64 bit keys.
+    fn exercise_u64<S: PrefixStore<u64, PrefixAs>>(store: &mut S) {
+        let pfxs: Vec<Prefix<u64, PrefixAs>> = [
+            ("2001:db8::/32", 1),
+            ("2001:db8:1::/48", 2),
+            ("2001:db8:1:2::/64", 3),
+            ("2001:db8:ffff:ffff::/64", 4),
+            ("ffff:ffff:ffff:ffff::/64", 5),
+        ]
+        .iter()
+        .map(|(s, asn)| {
+            let pfx: Prefix<u64, NoMeta> = s.parse().unwrap();
+            Prefix::new_with_meta(pfx.net, pfx.len, PrefixAs(*asn))
+        })
+        .collect();
+        for pfx in pfxs.iter() {
+            assert!(store.insert(*pfx).is_none());
+        }
+
+        for pfx in pfxs.iter() {
+            assert_eq!(store.get(&pfx.strip_meta()).unwrap().meta, pfx.meta);
+            assert_eq!(
+                store.match_longest_prefix(&pfx.strip_meta()).unwrap().meta,
+                pfx.meta
+            );
+        }
+        let spfx: Prefix<u64, NoMeta> = "2001:db8:1:3::/64".parse().unwrap();
+        assert_eq!(store.match_longest_prefix(&spfx).unwrap().meta, Some(PrefixAs(2)));
+        let spfx: Prefix<u64, NoMeta> = "2001:db9::/64".parse().unwrap();
+        assert!(store.match_longest_prefix(&spfx).is_none());
+
+        let found: Vec<String> = store.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            found,
+            [
+                "2001:db8::/32",
+                "2001:db8:1::/48",
+                "2001:db8:1:2::/64",
+                "2001:db8:ffff:ffff::/64",
+                "ffff:ffff:ffff:ffff::/64"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trie() {
+        exercise(&mut Trie::<u32, PrefixAs>::new());
+        exercise_order(&mut Trie::<u32, PrefixAs>::new());
+        exercise_u64(&mut Trie::<u64, PrefixAs>::new());
+    }
+
+    #[test]
+    fn test_radixtrie() {
+        exercise(&mut RadixTrie::<u32, PrefixAs>::new());
+        exercise_order(&mut RadixTrie::<u32, PrefixAs>::new());
+        exercise_u64(&mut RadixTrie::<u64, PrefixAs>::new());
+    }
+
+    #[test]
+    fn test_treebitmap() {
+        exercise(&mut treebitmap::TreeBitMap::<u32, PrefixAs>::new(vec![4]));
+        exercise(&mut treebitmap::TreeBitMap::<u32, PrefixAs>::new(vec![
+            3, 4, 5, 6, 5, 5, 4,
+        ]));
+        exercise(&mut treebitmap::TreeBitMap::<u32, PrefixAs>::new(vec![8]));
+        exercise(&mut treebitmap::TreeBitMap::<u32, PrefixAs>::new(vec![
+            3, 4, 4, 6, 7, 8,
+        ]));
+        exercise_order(&mut treebitmap::TreeBitMap::<u32, PrefixAs>::new(vec![4]));
+        exercise_order(&mut treebitmap::TreeBitMap::<u32, PrefixAs>::new(vec![
+            3, 4, 5, 6, 5, 5, 4,
+        ]));
+        exercise_order(&mut treebitmap::TreeBitMap::<u32, PrefixAs>::new(vec![8]));
+        exercise_order(&mut treebitmap::TreeBitMap::<u32, PrefixAs>::new(vec![
+            3, 4, 4, 6, 7, 8,
+        ]));
+        exercise_u64(&mut treebitmap::TreeBitMap::<u64, PrefixAs>::new(vec![4]));
+        exercise_u64(&mut treebitmap::TreeBitMap::<u64, PrefixAs>::new(vec![
+            6, 6, 6, 6, 6, 6, 6, 6, 6, 5, 5,
+        ]));
+    }
+
+    #[test]
+    fn test_treebitmap_univec() {
+        exercise(&mut treebitmap_univec::TreeBitMap::<u32, PrefixAs>::new(
+            vec![4],
+        ));
+        exercise(&mut treebitmap_univec::TreeBitMap::<u32, PrefixAs>::new(
+            vec![3, 4, 5, 6, 5, 5, 4],
+        ));
+        exercise_order(&mut treebitmap_univec::TreeBitMap::<u32, PrefixAs>::new(
+            vec![4],
+        ));
+        exercise_order(&mut treebitmap_univec::TreeBitMap::<u32, PrefixAs>::new(
+            vec![3, 4, 5, 6, 5, 5, 4],
+        ));
+        exercise_order(&mut treebitmap_univec::TreeBitMap::<u32, PrefixAs>::new(
+            vec![8],
+        ));
+        exercise_order(&mut treebitmap_univec::TreeBitMap::<u32, PrefixAs>::new(
+            vec![3, 4, 4, 6, 7, 8],
+        ));
+        exercise_u64(&mut treebitmap_univec::TreeBitMap::<u64, PrefixAs>::new(
+            vec![4],
+        ));
+        exercise_u64(&mut treebitmap_univec::TreeBitMap::<u64, PrefixAs>::new(
+            vec![8],
+        ));
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::clone_on_copy)]
+
 #[cfg(test)]
 mod test {
     use crate::common::{NoMeta, Prefix, PrefixAs};
@@ -8,12 +10,12 @@ mod test {
         let trie = &mut RadixTrie::<u32, NoMeta>::new();
 
         let min_pfx = Prefix::new(std::net::Ipv4Addr::new(0, 0, 0, 0).into(), 1);
-        trie.insert(&min_pfx);
+        trie.insert(min_pfx.clone());
         let res = trie.match_longest_prefix(&min_pfx).unwrap();
         assert_eq!(res, &min_pfx);
 
         let max_pfx = Prefix::new(std::net::Ipv4Addr::new(255, 255, 255, 255).into(), 32);
-        trie.insert(&max_pfx);
+        trie.insert(max_pfx.clone());
         let res = trie.match_longest_prefix(&max_pfx).unwrap();
         assert_eq!(res, &max_pfx);
     }
@@ -82,7 +84,7 @@ mod test {
         ];
 
         for pfx in pfxs.iter() {
-            trie.insert(pfx);
+            trie.insert(pfx.clone());
         }
 
         println!("{:#?}", trie);
@@ -111,8 +113,8 @@ mod test {
         let mut trie: RadixTrie<u32, NoMeta> = RadixTrie::new();
         let pfx1 = Prefix::<u32, NoMeta>::new(std::net::Ipv4Addr::new(192, 1, 12, 0).into(), 24);
         let pfx2 = Prefix::<u32, NoMeta>::new(std::net::Ipv4Addr::new(192, 1, 12, 0).into(), 24);
-        trie.insert(&pfx1);
-        trie.insert(&pfx2);
+        trie.insert(pfx1.clone());
+        trie.insert(pfx2.clone());
 
         assert_eq!(trie.match_longest_prefix(&pfx1).unwrap(), &pfx1);
         assert_eq!(trie.match_longest_prefix(&pfx1).unwrap(), &pfx2);
@@ -132,7 +134,7 @@ mod test {
 
         pfx_vec.reverse();
         for pfx in &pfx_vec {
-            trie.insert(&pfx);
+            trie.insert(pfx.clone());
         }
 
         for s_len in 2..32 {
@@ -164,7 +166,7 @@ mod test {
             let mut i_len_s = 0;
             for pfx in &pfx_vec {
                 i_len_s += 1;
-                tree_bitmap.insert(&pfx);
+                tree_bitmap.insert(pfx.clone());
 
                 let res_pfx = Prefix::<u32, NoMeta>::new(
                     std::net::Ipv4Addr::new(*i_net, 0, 0, 0).into(),
@@ -184,4 +186,52 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_remove_ipv4() {
+        let pfxs: Vec<Prefix<u32, PrefixAs>> = vec![
+            Prefix::new_with_meta(std::net::Ipv4Addr::new(0, 0, 0, 0).into(), 0, PrefixAs(0)),
+            Prefix::new_with_meta(std::net::Ipv4Addr::new(192, 0, 0, 0).into(), 16, PrefixAs(1)),
+            Prefix::new_with_meta(std::net::Ipv4Addr::new(192, 0, 0, 0).into(), 23, PrefixAs(2)),
+            Prefix::new_with_meta(std::net::Ipv4Addr::new(192, 0, 10, 0).into(), 23, PrefixAs(3)),
+            Prefix::new_with_meta(std::net::Ipv4Addr::new(192, 0, 9, 0).into(), 24, PrefixAs(4)),
+            Prefix::new_with_meta(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 23, PrefixAs(5)),
+            Prefix::new_with_meta(std::net::Ipv4Addr::new(193, 0, 10, 0).into(), 24, PrefixAs(6)),
+            Prefix::new_with_meta(std::net::Ipv4Addr::new(193, 0, 10, 10).into(), 32, PrefixAs(7)),
+            Prefix::new_with_meta(std::net::Ipv4Addr::new(1, 0, 128, 0).into(), 24, PrefixAs(8)),
+            Prefix::new_with_meta(std::net::Ipv4Addr::new(1, 0, 0, 0).into(), 24, PrefixAs(9)),
+            Prefix::new_with_meta(std::net::Ipv4Addr::new(1, 0, 0, 0).into(), 8, PrefixAs(10)),
+        ];
+
+        // Remove every prefix in turn, the trie should end up looking exactly
+        // like a trie built from the remaining prefixes only.
+        for (i, r_pfx) in pfxs.iter().enumerate() {
+            let mut trie: RadixTrie<u32, PrefixAs> = RadixTrie::new();
+            for pfx in pfxs.iter() {
+                trie.insert(pfx.clone());
+            }
+
+            let removed = trie.remove(&r_pfx.strip_meta()).unwrap();
+            assert_eq!(removed.meta, r_pfx.meta);
+            assert!(trie.remove(&r_pfx.strip_meta()).is_none());
+
+            let mut expect_trie: RadixTrie<u32, PrefixAs> = RadixTrie::new();
+            for (j, pfx) in pfxs.iter().enumerate() {
+                if i != j {
+                    expect_trie.insert(pfx.clone());
+                }
+            }
+            assert_eq!(format!("{:?}", trie), format!("{:?}", expect_trie));
+            for (l, el) in trie.1.iter().zip(expect_trie.1.iter()) {
+                assert_eq!(l.nodes_num, el.nodes_num);
+                assert_eq!(l.prefixes_num, el.prefixes_num);
+                assert_eq!(l.compression, el.compression);
+            }
+
+            for pfx in pfxs.iter().filter(|p| p.meta != r_pfx.meta) {
+                let res = trie.match_longest_prefix(&pfx.strip_meta()).unwrap();
+                assert_eq!(res.meta, pfx.meta);
+            }
+        }
+    }
 }
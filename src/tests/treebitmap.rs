@@ -1,7 +1,11 @@
+#![allow(clippy::clone_on_copy)]
+
 #[cfg(test)]
 mod test {
     use crate::common::*;
-    use crate::treebitmap::TreeBitMap;
+    use crate::treebitmap::{
+        Stride, Stride3, Stride4, Stride5, Stride6, Stride7, Stride8, TreeBitMap,
+    };
 
     #[test]
     fn test_insert_extremes_ipv4() {
@@ -9,13 +13,13 @@ mod test {
         let trie = &mut TreeBitMap::<u32, NoMeta>::new(strides);
         let min_pfx = Prefix::new(std::net::Ipv4Addr::new(0, 0, 0, 0).into(), 1);
 
-        trie.insert(&min_pfx);
+        trie.insert(min_pfx.clone());
         let res = trie.match_longest_prefix(&min_pfx);
         assert_eq!(res.len(), 1);
         assert_eq!(res[0], &min_pfx);
 
         let max_pfx = Prefix::new(std::net::Ipv4Addr::new(255, 255, 255, 255).into(), 32);
-        trie.insert(&max_pfx);
+        trie.insert(max_pfx.clone());
         let res = trie.match_longest_prefix(&max_pfx);
         assert_eq!(res.len(), 1);
         assert_eq!(res[0], &max_pfx);
@@ -85,7 +89,7 @@ mod test {
         ];
 
         for pfx in pfxs.iter() {
-            tree_bitmap.insert(pfx);
+            tree_bitmap.insert(pfx.clone());
         }
 
         for pfx in pfxs.iter() {
@@ -134,7 +138,7 @@ mod test {
             let mut i_len_s = 0;
             for pfx in &pfx_vec {
                 i_len_s += 1;
-                tree_bitmap.insert(&pfx);
+                tree_bitmap.insert(pfx.clone());
 
                 let res_pfx = Prefix::<u32, NoMeta>::new(
                     std::net::Ipv4Addr::new(*i_net, 0, 0, 0).into(),
@@ -154,4 +158,210 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_more_specifics_ipv4() {
+        for strides in [vec![4], vec![3, 4, 5, 6, 5, 5, 4], vec![6, 6, 6, 6, 4, 4]].iter() {
+            let mut tree_bitmap: TreeBitMap<u32, PrefixAs> = TreeBitMap::new(strides.to_owned());
+            let pfxs = [
+                Prefix::<u32, PrefixAs>::new(std::net::Ipv4Addr::new(0, 0, 0, 0).into(), 1),
+                Prefix::new(std::net::Ipv4Addr::new(192, 0, 0, 0).into(), 8),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 15),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 16),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 17),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 10, 0).into(), 23),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 10, 0).into(), 24),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 11, 0).into(), 24),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 10, 10).into(), 32),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 128, 0).into(), 20),
+                Prefix::new(std::net::Ipv4Addr::new(193, 1, 0, 0).into(), 16),
+            ];
+            for pfx in pfxs.iter() {
+                tree_bitmap.insert(pfx.clone());
+            }
+
+            let searches = [
+                Prefix::<u32, NoMeta>::new(std::net::Ipv4Addr::new(0, 0, 0, 0).into(), 0),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 8),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 16),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 18),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 10, 0).into(), 23),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 10, 0).into(), 24),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 10, 10).into(), 32),
+                Prefix::new(std::net::Ipv4Addr::new(10, 0, 0, 0).into(), 8),
+            ];
+            for spfx in searches.iter() {
+                let mut found: Vec<(u32, u8)> = tree_bitmap
+                    .more_specifics(spfx)
+                    .map(|p| (p.net, p.len))
+                    .collect();
+                found.sort_unstable();
+
+                let mut expected: Vec<(u32, u8)> = pfxs
+                    .iter()
+                    .filter(|p| {
+                        p.len > spfx.len
+                            && (spfx.len == 0
+                                || p.net >> (32 - spfx.len) == spfx.net >> (32 - spfx.len))
+                    })
+                    .map(|p| (p.net, p.len))
+                    .collect();
+                expected.sort_unstable();
+
+                assert_eq!(found, expected, "more specifics of {:?}", spfx);
+            }
+        }
+    }
+
+    #[test]
+    fn test_match_prefix_ipv4() {
+        for strides in [vec![4], vec![3, 4, 5, 6, 5, 5, 4], vec![6, 6, 6, 6, 4, 4]].iter() {
+            let mut tree_bitmap: TreeBitMap<u32, PrefixAs> = TreeBitMap::new(strides.to_owned());
+            let pfxs = [
+                Prefix::new_with_meta(std::net::Ipv4Addr::new(128, 0, 0, 0).into(), 1, PrefixAs(1)),
+                Prefix::new_with_meta(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 16, PrefixAs(2)),
+                Prefix::new_with_meta(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 20, PrefixAs(3)),
+                Prefix::new_with_meta(std::net::Ipv4Addr::new(193, 0, 10, 0).into(), 24, PrefixAs(4)),
+                Prefix::new_with_meta(std::net::Ipv4Addr::new(193, 0, 11, 0).into(), 24, PrefixAs(5)),
+            ];
+            for pfx in pfxs.iter() {
+                tree_bitmap.insert(pfx.clone());
+            }
+            let all = MatchOptions {
+                include_less_specifics: true,
+                include_more_specifics: true,
+            };
+
+            // Exact match with both less- and more-specifics.
+            let spfx = Prefix::new(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 16);
+            let res = tree_bitmap.match_prefix(&spfx, &all);
+            assert_eq!(res.exact_match.unwrap().meta, Some(PrefixAs(2)));
+            assert_eq!(res.longest_match.unwrap().meta, Some(PrefixAs(2)));
+            let less: Vec<u8> = res.less_specifics.unwrap().iter().map(|p| p.len).collect();
+            assert_eq!(less, vec![1]);
+            let mut more: Vec<u32> = res
+                .more_specifics
+                .unwrap()
+                .iter()
+                .map(|p| p.meta.as_ref().unwrap().0)
+                .collect();
+            more.sort_unstable();
+            assert_eq!(more, vec![3, 4, 5]);
+
+            // No exact match, the longest match is not part of the
+            // less-specifics.
+            let spfx = Prefix::new(std::net::Ipv4Addr::new(193, 0, 10, 0).into(), 23);
+            let res = tree_bitmap.match_prefix(&spfx, &all);
+            assert!(res.exact_match.is_none());
+            assert_eq!(res.longest_match.unwrap().meta, Some(PrefixAs(3)));
+            let less: Vec<u8> = res.less_specifics.unwrap().iter().map(|p| p.len).collect();
+            assert_eq!(less, vec![1, 16, 20]);
+            assert_eq!(res.more_specifics.unwrap().len(), 2);
+
+            // Only what was asked for gets computed.
+            let spfx = Prefix::new(std::net::Ipv4Addr::new(193, 0, 10, 0).into(), 24);
+            let res = tree_bitmap.match_prefix(&spfx, &MatchOptions::default());
+            assert_eq!(res.exact_match.unwrap().meta, Some(PrefixAs(4)));
+            assert!(res.less_specifics.is_none());
+            assert!(res.more_specifics.is_none());
+
+            let spfx = Prefix::new(std::net::Ipv4Addr::new(10, 0, 0, 0).into(), 8);
+            let res = tree_bitmap.match_prefix(&spfx, &all);
+            assert!(res.exact_match.is_none());
+            assert!(res.longest_match.is_none());
+            assert!(res.less_specifics.unwrap().is_empty());
+            assert!(res.more_specifics.unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_strides_u64() {
+        let tree_bitmap = TreeBitMap::<u64, NoMeta>::new(vec![4]);
+        assert_eq!(tree_bitmap.strides, [4; 16]);
+        assert_eq!(tree_bitmap.stats[1].created_nodes.len(), 16);
+        let tree_bitmap = TreeBitMap::<u64, NoMeta>::new(vec![6, 6, 6, 6, 6, 6, 6, 6, 6, 5, 5]);
+        assert_eq!(tree_bitmap.strides.iter().sum::<u8>(), 64);
+    }
+
+    #[test]
+    #[should_panic(expected = "don't add up to the 64 bits")]
+    fn test_strides_u64_invalid() {
+        TreeBitMap::<u64, NoMeta>::new(vec![6]);
+    }
+
+    // A stride of 0 would never add up to the bits of the address family.
+    #[test]
+    #[should_panic(expected = "contain a stride size other than 3 to 8")]
+    fn test_strides_zero() {
+        TreeBitMap::<u32, NoMeta>::new(vec![0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "contain a stride size other than 3 to 8")]
+    fn test_strides_too_large() {
+        TreeBitMap::<u128, NoMeta>::new(vec![200]);
+    }
+
+    // Walks over every nibble of every length in the order of the bits in
+    // the bitmap. Every nibble should get a bit of its own, at the right
+    // position, and the indexes should count the bits set before it, both
+    // in a full bitmap and in one with only every other bit set.
+    fn exhaustive_stride<S>()
+    where
+        S: Stride
+            + std::ops::BitAnd<Output = S>
+            + std::ops::BitOr<Output = S>
+            + std::ops::Not<Output = S>,
+        <S as Stride>::PtrSize: Copy,
+    {
+        let mut nibbles = vec![];
+        for len in 0..=S::STRIDE_LEN {
+            for nibble in 0..(1_u32 << len) {
+                nibbles.push((nibble, len));
+            }
+        }
+
+        let mut full = S::zero();
+        let mut every_other = S::zero();
+        for (pos, (nibble, len)) in nibbles.iter().enumerate() {
+            let bit_pos = S::get_bit_pos(*nibble, *len);
+            assert_eq!(Stride::leading_zeros(bit_pos), pos as u32);
+            assert!(bit_pos & full == S::zero());
+            full = full | bit_pos;
+            if pos % 2 == 0 {
+                every_other = every_other | bit_pos;
+            }
+        }
+        // The nibbles take every bit but the last one, which stays unused.
+        assert_eq!(Stride::leading_zeros(!full), nibbles.len() as u32);
+        for (pos, (nibble, len)) in nibbles.iter().enumerate() {
+            assert_eq!(S::get_pfx_index(full, *nibble, *len), pos);
+            if pos % 2 == 0 {
+                assert_eq!(S::get_pfx_index(every_other, *nibble, *len), pos / 2);
+            }
+        }
+
+        let mut ptrbitarr = S::into_ptrbitarr_size(S::zero());
+        for nibble in 0..(1_u32 << S::STRIDE_LEN) {
+            let bit_pos = S::get_bit_pos(nibble, S::STRIDE_LEN);
+            assert!(S::into_stride_size(S::into_ptrbitarr_size(bit_pos)) == bit_pos);
+            if nibble % 2 == 0 {
+                ptrbitarr = S::into_ptrbitarr_size(S::into_stride_size(ptrbitarr) | bit_pos);
+                assert_eq!(S::get_ptr_index(ptrbitarr, nibble), nibble as usize / 2);
+            }
+        }
+        for nibble in (0..(1_u32 << S::STRIDE_LEN)).step_by(2) {
+            assert_eq!(S::get_ptr_index(ptrbitarr, nibble), nibble as usize / 2);
+        }
+    }
+
+    #[test]
+    fn test_strides_exhaustive() {
+        exhaustive_stride::<Stride3>();
+        exhaustive_stride::<Stride4>();
+        exhaustive_stride::<Stride5>();
+        exhaustive_stride::<Stride6>();
+        exhaustive_stride::<Stride7>();
+        exhaustive_stride::<Stride8>();
+    }
 }
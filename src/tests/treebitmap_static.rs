@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod test {
+    use crate::common::*;
+    use crate::stride_layout;
+    use crate::treebitmap;
+    use crate::treebitmap_static::TreeBitMap;
+
+    fn load_test_csv() -> Vec<Prefix<u32, PrefixAs>> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_path("./data/test.csv")
+            .unwrap();
+        rdr.records()
+            .map(|record| {
+                let record = record.unwrap();
+                let net: std::net::Ipv4Addr = record[0].parse().unwrap();
+                let len: u8 = record[1].parse().unwrap();
+                let asn: u32 = record[2].parse().unwrap();
+                Prefix::new_with_meta(net.into(), len, PrefixAs(asn))
+            })
+            .collect()
+    }
+
+    // Every search should come up with the same prefixes as the runtime
+    // configured TreeBitMap with the same strides.
+    fn compare<L>(strides: Vec<u8>)
+    where
+        L: crate::treebitmap_static::StrideLayout<u32, PrefixAs>,
+    {
+        let mut static_tree = TreeBitMap::<u32, PrefixAs, L>::new();
+        assert_eq!(static_tree.strides(), strides);
+        let mut tree = treebitmap::TreeBitMap::<u32, PrefixAs>::new(strides);
+        let pfxs = load_test_csv();
+        for pfx in pfxs.iter() {
+            assert_eq!(static_tree.insert(*pfx), tree.insert(*pfx));
+        }
+
+        for pfx in pfxs.iter() {
+            let pfx = pfx.strip_meta();
+            assert_eq!(
+                static_tree.match_longest_prefix(&pfx),
+                tree.match_longest_prefix(&pfx)
+            );
+        }
+        let mut addr: u32 = 1;
+        for len in 0..=32 {
+            for _ in 0..1000 {
+                addr = addr.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let pfx = Prefix::<u32, NoMeta>::new(addr, len);
+                assert_eq!(
+                    static_tree.match_longest_prefix(&pfx),
+                    tree.match_longest_prefix(&pfx)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_compare_treebitmap() {
+        compare::<stride_layout![4, 4, 4, 4, 4, 4, 4, 4]>(vec![4, 4, 4, 4, 4, 4, 4, 4]);
+        compare::<stride_layout![3, 4, 5, 6, 5, 5, 4]>(vec![3, 4, 5, 6, 5, 5, 4]);
+        compare::<stride_layout![6, 6, 6, 6, 4, 4]>(vec![6, 6, 6, 6, 4, 4]);
+        compare::<stride_layout![8, 8, 8, 8]>(vec![8, 8, 8, 8]);
+        compare::<stride_layout![3, 4, 4, 6, 7, 8]>(vec![3, 4, 4, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_insert_replace() {
+        let mut tree = TreeBitMap::<u32, PrefixAs, stride_layout![5, 5, 5, 5, 4, 4, 4]>::new();
+        let pfx: Prefix<u32, NoMeta> = "10.0.0.0/8".parse().unwrap();
+        assert!(tree
+            .insert(Prefix::new_with_meta(pfx.net, pfx.len, PrefixAs(1)))
+            .is_none());
+        let old = tree.insert(Prefix::new_with_meta(pfx.net, pfx.len, PrefixAs(2)));
+        assert_eq!(old.unwrap().meta, Some(PrefixAs(1)));
+        assert_eq!(tree.match_longest_prefix(&pfx).len(), 1);
+        assert_eq!(tree.match_longest_prefix(&pfx)[0].meta, Some(PrefixAs(2)));
+
+        // The default route and a host route, at both ends of the layout.
+        tree.insert(Prefix::new_with_meta(0, 0, PrefixAs(0)));
+        let host: Prefix<u32, NoMeta> = "10.1.2.3/32".parse().unwrap();
+        tree.insert(Prefix::new_with_meta(host.net, host.len, PrefixAs(3)));
+        let found: Vec<String> = tree
+            .match_longest_prefix(&host)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(found, ["0.0.0.0/0", "10.0.0.0/8", "10.1.2.3/32"]);
+        let other: Prefix<u32, NoMeta> = "11.0.0.0/8".parse().unwrap();
+        assert_eq!(tree.match_longest_prefix(&other)[0].meta, Some(PrefixAs(0)));
+    }
+
+    #[test]
+    fn test_u64() {
+        let mut tree = TreeBitMap::<u64, NoMeta, stride_layout![6, 6, 6, 6, 6, 6, 6, 6, 6, 5, 5]>::new();
+        for s in ["2001:db8::/32", "2001:db8:1::/48", "2001:db8:1:2::/64"].iter() {
+            tree.insert(s.parse().unwrap());
+        }
+        let pfx: Prefix<u64, NoMeta> = "2001:db8:1:3::/64".parse().unwrap();
+        let found: Vec<String> = tree
+            .match_longest_prefix(&pfx)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(found, ["2001:db8::/32", "2001:db8:1::/48"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "don't add up to the 32 bits")]
+    fn test_layout_invalid() {
+        TreeBitMap::<u32, NoMeta, stride_layout![4, 4, 4, 4, 4, 4, 4]>::new();
+    }
+}
@@ -1,7 +1,9 @@
 #[cfg(test)]
 mod test {
     use crate::common::*;
-    use crate::treebitmap_univec::TreeBitMap;
+    use crate::treebitmap_univec::{
+        Stride, Stride3, Stride4, Stride5, Stride6, Stride7, Stride8, TreeBitMap,
+    };
 
     #[test]
     fn test_insert_extremes_ipv4() {
@@ -88,7 +90,7 @@ mod test {
             tree_bitmap.insert(pfx);
         }
 
-        for pfx in tree_bitmap.prefixes.iter() {
+        for pfx in tree_bitmap.prefixes.iter().flatten() {
             let pfx_nm = pfx.strip_meta();
             let res = tree_bitmap.match_longest_prefix_only(&pfx_nm);
             println!("{:?}", pfx);
@@ -152,4 +154,585 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_remove_ipv4() {
+        for strides in [vec![4], vec![3, 4, 5, 6, 5, 5, 4], vec![8]].iter() {
+            let mut tree_bitmap: TreeBitMap<u32, PrefixAs> = TreeBitMap::new(strides.to_owned());
+            let pfxs: Vec<Prefix<u32, PrefixAs>> = (1..33)
+                .map(|i_len| {
+                    Prefix::new_with_meta(
+                        std::net::Ipv4Addr::new(193, 0, 10, 10).into(),
+                        i_len,
+                        PrefixAs(i_len as u32),
+                    )
+                })
+                .collect();
+
+            for pfx in pfxs.iter() {
+                tree_bitmap.insert(*pfx);
+            }
+            let nodes_num = tree_bitmap.nodes.len();
+            let prefixes_num = tree_bitmap.prefixes.len();
+
+            // Withdraw from the most specific end, the lookups should fall
+            // back on the next less-specific prefix every time.
+            for pfx in pfxs.iter().rev() {
+                let removed = tree_bitmap.remove(&pfx.strip_meta()).unwrap();
+                assert_eq!(removed.len, pfx.len);
+                assert_eq!(removed.meta.unwrap().0, pfx.len as u32);
+                assert!(tree_bitmap.remove(&pfx.strip_meta()).is_none());
+
+                let res = tree_bitmap.match_longest_prefix_only(&pfx.strip_meta());
+                if pfx.len > 1 {
+                    assert_eq!(res.unwrap().len, pfx.len - 1);
+                } else {
+                    assert!(res.is_none());
+                }
+            }
+
+            // Everything but the root node should have been released.
+            for s in tree_bitmap.stats.iter() {
+                assert_eq!(s.prefixes_num.iter().map(|c| c.count).sum::<usize>(), 0);
+                assert_eq!(s.created_nodes.iter().skip(1).map(|c| c.count).sum::<usize>(), 0);
+            }
+            assert_eq!(tree_bitmap.iter().count(), 0);
+            assert!(tree_bitmap.prefixes.iter().all(|p| p.is_none()));
+
+            // Re-inserting should reuse the vacated slots.
+            for pfx in pfxs.iter() {
+                tree_bitmap.insert(*pfx);
+            }
+            assert_eq!(tree_bitmap.nodes.len(), nodes_num);
+            assert_eq!(tree_bitmap.prefixes.len(), prefixes_num);
+            for pfx in pfxs.iter() {
+                let res = tree_bitmap.match_longest_prefix_only(&pfx.strip_meta());
+                assert_eq!(res.unwrap().meta, pfx.meta);
+            }
+        }
+    }
+
+    #[test]
+    fn test_more_specifics_ipv4() {
+        for strides in [vec![4], vec![3, 4, 5, 6, 5, 5, 4], vec![8]].iter() {
+            let mut tree_bitmap: TreeBitMap<u32, PrefixAs> = TreeBitMap::new(strides.to_owned());
+            let pfxs = [
+                Prefix::<u32, PrefixAs>::new(std::net::Ipv4Addr::new(0, 0, 0, 0).into(), 1),
+                Prefix::new(std::net::Ipv4Addr::new(192, 0, 0, 0).into(), 8),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 15),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 16),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 17),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 10, 0).into(), 23),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 10, 0).into(), 24),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 11, 0).into(), 24),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 10, 10).into(), 32),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 128, 0).into(), 20),
+                Prefix::new(std::net::Ipv4Addr::new(193, 1, 0, 0).into(), 16),
+            ];
+            for pfx in pfxs.iter() {
+                tree_bitmap.insert(*pfx);
+            }
+
+            let searches = [
+                Prefix::<u32, NoMeta>::new(std::net::Ipv4Addr::new(0, 0, 0, 0).into(), 0),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 8),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 16),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 18),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 10, 0).into(), 23),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 10, 0).into(), 24),
+                Prefix::new(std::net::Ipv4Addr::new(193, 0, 10, 10).into(), 32),
+                Prefix::new(std::net::Ipv4Addr::new(10, 0, 0, 0).into(), 8),
+            ];
+            for spfx in searches.iter() {
+                let mut found: Vec<(u32, u8)> = tree_bitmap
+                    .more_specifics(spfx)
+                    .map(|p| (p.net, p.len))
+                    .collect();
+                found.sort_unstable();
+
+                let mut expected: Vec<(u32, u8)> = pfxs
+                    .iter()
+                    .filter(|p| {
+                        p.len > spfx.len
+                            && (spfx.len == 0
+                                || p.net >> (32 - spfx.len) == spfx.net >> (32 - spfx.len))
+                    })
+                    .map(|p| (p.net, p.len))
+                    .collect();
+                expected.sort_unstable();
+
+                assert_eq!(found, expected, "more specifics of {:?}", spfx);
+            }
+        }
+    }
+
+    #[test]
+    fn test_match_prefix_ipv4() {
+        for strides in [vec![4], vec![3, 4, 5, 6, 5, 5, 4], vec![8]].iter() {
+            let mut tree_bitmap: TreeBitMap<u32, PrefixAs> = TreeBitMap::new(strides.to_owned());
+            let pfxs = [
+                Prefix::new_with_meta(std::net::Ipv4Addr::new(128, 0, 0, 0).into(), 1, PrefixAs(1)),
+                Prefix::new_with_meta(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 16, PrefixAs(2)),
+                Prefix::new_with_meta(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 20, PrefixAs(3)),
+                Prefix::new_with_meta(std::net::Ipv4Addr::new(193, 0, 10, 0).into(), 24, PrefixAs(4)),
+                Prefix::new_with_meta(std::net::Ipv4Addr::new(193, 0, 11, 0).into(), 24, PrefixAs(5)),
+            ];
+            for pfx in pfxs.iter() {
+                tree_bitmap.insert(*pfx);
+            }
+            let all = MatchOptions {
+                include_less_specifics: true,
+                include_more_specifics: true,
+            };
+
+            // Exact match with both less- and more-specifics.
+            let spfx = Prefix::new(std::net::Ipv4Addr::new(193, 0, 0, 0).into(), 16);
+            let res = tree_bitmap.match_prefix(&spfx, &all);
+            assert_eq!(res.exact_match.unwrap().meta, Some(PrefixAs(2)));
+            assert_eq!(res.longest_match.unwrap().meta, Some(PrefixAs(2)));
+            let less: Vec<u8> = res.less_specifics.unwrap().iter().map(|p| p.len).collect();
+            assert_eq!(less, vec![1]);
+            let mut more: Vec<u32> = res
+                .more_specifics
+                .unwrap()
+                .iter()
+                .map(|p| p.meta.as_ref().unwrap().0)
+                .collect();
+            more.sort_unstable();
+            assert_eq!(more, vec![3, 4, 5]);
+
+            // No exact match, the longest match is not part of the
+            // less-specifics.
+            let spfx = Prefix::new(std::net::Ipv4Addr::new(193, 0, 10, 0).into(), 23);
+            let res = tree_bitmap.match_prefix(&spfx, &all);
+            assert!(res.exact_match.is_none());
+            assert_eq!(res.longest_match.unwrap().meta, Some(PrefixAs(3)));
+            let less: Vec<u8> = res.less_specifics.unwrap().iter().map(|p| p.len).collect();
+            assert_eq!(less, vec![1, 16, 20]);
+            assert_eq!(res.more_specifics.unwrap().len(), 2);
+
+            // Only what was asked for gets computed.
+            let spfx = Prefix::new(std::net::Ipv4Addr::new(193, 0, 10, 0).into(), 24);
+            let res = tree_bitmap.match_prefix(&spfx, &MatchOptions::default());
+            assert_eq!(res.exact_match.unwrap().meta, Some(PrefixAs(4)));
+            assert!(res.less_specifics.is_none());
+            assert!(res.more_specifics.is_none());
+
+            let spfx = Prefix::new(std::net::Ipv4Addr::new(10, 0, 0, 0).into(), 8);
+            let res = tree_bitmap.match_prefix(&spfx, &all);
+            assert!(res.exact_match.is_none());
+            assert!(res.longest_match.is_none());
+            assert!(res.less_specifics.unwrap().is_empty());
+            assert!(res.more_specifics.unwrap().is_empty());
+        }
+    }
+
+    // MOAS: one prefix announced by several origins, seen through several
+    // peers. Every peer announces and withdraws its own origin for it.
+    #[test]
+    fn test_multi_value_ipv4() {
+        let mut tree_bitmap: TreeBitMap<u32, MultiMeta<u32, PrefixAs>> =
+            TreeBitMap::new(vec![3, 4, 5, 6, 5, 5, 4]);
+        let pfx: Prefix<u32, NoMeta> = "193.0.0.0/21".parse().unwrap();
+        let less_specific: Prefix<u32, NoMeta> = "193.0.0.0/16".parse().unwrap();
+
+        assert_eq!(tree_bitmap.add_value(&pfx, 1, PrefixAs(3333)), None);
+        assert_eq!(tree_bitmap.add_value(&pfx, 2, PrefixAs(3333)), None);
+        assert_eq!(tree_bitmap.add_value(&pfx, 3, PrefixAs(12654)), None);
+        assert_eq!(
+            tree_bitmap.add_value(&pfx, 2, PrefixAs(12654)),
+            Some(PrefixAs(3333))
+        );
+        assert_eq!(tree_bitmap.add_value(&less_specific, 1, PrefixAs(1)), None);
+        assert_eq!(tree_bitmap.iter().count(), 2);
+
+        let res = tree_bitmap.match_longest_prefix_only(&pfx).unwrap();
+        let values: Vec<(u32, PrefixAs)> = res
+            .meta
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (*k, *v))
+            .collect();
+        assert_eq!(
+            values,
+            vec![(1, PrefixAs(3333)), (2, PrefixAs(12654)), (3, PrefixAs(12654))]
+        );
+
+        // Withdrawing one peer leaves the others be.
+        assert_eq!(tree_bitmap.withdraw_value(&pfx, &2), Some(PrefixAs(12654)));
+        assert_eq!(tree_bitmap.withdraw_value(&pfx, &2), None);
+        assert_eq!(tree_bitmap.get_value(&pfx, &1), Some(&PrefixAs(3333)));
+        assert_eq!(tree_bitmap.get_value(&pfx, &2), None);
+        assert_eq!(tree_bitmap.get(&pfx).unwrap().meta.as_ref().unwrap().len(), 2);
+        assert_eq!(tree_bitmap.get_value(&less_specific, &1), Some(&PrefixAs(1)));
+
+        // Only with the last value gone the prefix goes.
+        assert_eq!(tree_bitmap.withdraw_value(&pfx, &1), Some(PrefixAs(3333)));
+        assert!(tree_bitmap.get(&pfx).is_some());
+        assert_eq!(tree_bitmap.withdraw_value(&pfx, &3), Some(PrefixAs(12654)));
+        assert!(tree_bitmap.get(&pfx).is_none());
+        assert_eq!(tree_bitmap.match_longest_prefix_only(&pfx).unwrap().len, 16);
+        assert_eq!(tree_bitmap.withdraw_value(&pfx, &3), None);
+        assert_eq!(tree_bitmap.iter().count(), 1);
+    }
+
+    // Walks over every nibble of every length in the order of the bits in
+    // the bitmap. Every nibble should get a bit of its own, at the right
+    // position, and the indexes should count the bits set before it, both
+    // in a full bitmap and in one with only every other bit set.
+    fn exhaustive_stride<S>()
+    where
+        S: Stride
+            + std::ops::BitAnd<Output = S>
+            + std::ops::BitOr<Output = S>
+            + std::ops::Not<Output = S>,
+        <S as Stride>::PtrSize: Copy,
+    {
+        let mut nibbles = vec![];
+        for len in 0..=S::STRIDE_LEN {
+            for nibble in 0..(1_u32 << len) {
+                nibbles.push((nibble, len));
+            }
+        }
+
+        let mut full = S::zero();
+        let mut every_other = S::zero();
+        for (pos, (nibble, len)) in nibbles.iter().enumerate() {
+            let bit_pos = S::get_bit_pos(*nibble, *len);
+            assert_eq!(Stride::leading_zeros(bit_pos), pos as u32);
+            assert!(bit_pos & full == S::zero());
+            full = full | bit_pos;
+            if pos % 2 == 0 {
+                every_other = every_other | bit_pos;
+            }
+        }
+        // The nibbles take every bit but the last one, which stays unused.
+        assert_eq!(Stride::leading_zeros(!full), nibbles.len() as u32);
+        for (pos, (nibble, len)) in nibbles.iter().enumerate() {
+            assert_eq!(S::get_pfx_index(full, *nibble, *len), pos);
+            if pos % 2 == 0 {
+                assert_eq!(S::get_pfx_index(every_other, *nibble, *len), pos / 2);
+            }
+        }
+
+        let mut ptrbitarr = S::into_ptrbitarr_size(S::zero());
+        for nibble in 0..(1_u32 << S::STRIDE_LEN) {
+            let bit_pos = S::get_bit_pos(nibble, S::STRIDE_LEN);
+            assert!(S::into_stride_size(S::into_ptrbitarr_size(bit_pos)) == bit_pos);
+            if nibble % 2 == 0 {
+                ptrbitarr = S::into_ptrbitarr_size(S::into_stride_size(ptrbitarr) | bit_pos);
+                assert_eq!(S::get_ptr_index(ptrbitarr, nibble), nibble as usize / 2);
+            }
+        }
+        for nibble in (0..(1_u32 << S::STRIDE_LEN)).step_by(2) {
+            assert_eq!(S::get_ptr_index(ptrbitarr, nibble), nibble as usize / 2);
+        }
+    }
+
+    #[test]
+    fn test_strides_exhaustive() {
+        exhaustive_stride::<Stride3>();
+        exhaustive_stride::<Stride4>();
+        exhaustive_stride::<Stride5>();
+        exhaustive_stride::<Stride6>();
+        exhaustive_stride::<Stride7>();
+        exhaustive_stride::<Stride8>();
+    }
+
+    fn load_test_csv() -> Vec<Prefix<u32, PrefixAs>> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_path("./data/test.csv")
+            .unwrap();
+        rdr.records()
+            .map(|record| {
+                let record = record.unwrap();
+                let net: std::net::Ipv4Addr = record[0].parse().unwrap();
+                let len: u8 = record[1].parse().unwrap();
+                let asn: u32 = record[2].parse().unwrap();
+                Prefix::new_with_meta(net.into(), len, PrefixAs(asn))
+            })
+            .collect()
+    }
+
+    // With a root table every longest match lookup should come up with the
+    // same prefix as the tree without one, also after removing prefixes
+    // that end above, on and below the table.
+    fn compare_root_table(strides: Vec<u8>, bits: u8) {
+        let mut pfxs = load_test_csv();
+        pfxs.push(Prefix::new_with_meta(0, 0, PrefixAs(0)));
+        pfxs.push(Prefix::new_with_meta(0x0a00_0000, 8, PrefixAs(8)));
+        pfxs.push(Prefix::new_with_meta(0x0a01_0000, bits, PrefixAs(bits as u32)));
+
+        let mut tree = TreeBitMap::<u32, PrefixAs>::new(strides.clone());
+        let mut table_tree = TreeBitMap::<u32, PrefixAs>::with_root_table(strides, bits);
+        let compare = |tree: &TreeBitMap<u32, PrefixAs>,
+                           table_tree: &TreeBitMap<u32, PrefixAs>| {
+            let mut addr: u32 = 1;
+            for len in 0..=32 {
+                for _ in 0..1000 {
+                    addr = addr.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    let pfx = Prefix::<u32, NoMeta>::new(addr, len);
+                    assert_eq!(
+                        table_tree.match_longest_prefix_only(&pfx),
+                        tree.match_longest_prefix_only(&pfx)
+                    );
+                }
+            }
+            for pfx in pfxs.iter() {
+                let pfx = pfx.strip_meta();
+                assert_eq!(
+                    table_tree.match_longest_prefix_only(&pfx),
+                    tree.match_longest_prefix_only(&pfx)
+                );
+            }
+        };
+
+        for pfx in pfxs.iter() {
+            assert_eq!(table_tree.insert(*pfx), tree.insert(*pfx));
+        }
+        compare(&tree, &table_tree);
+
+        for pfx in pfxs.iter().step_by(2) {
+            assert_eq!(
+                table_tree.remove(&pfx.strip_meta()),
+                tree.remove(&pfx.strip_meta())
+            );
+        }
+        compare(&tree, &table_tree);
+
+        for pfx in pfxs.iter().skip(1).step_by(2) {
+            assert_eq!(
+                table_tree.remove(&pfx.strip_meta()),
+                tree.remove(&pfx.strip_meta())
+            );
+        }
+        assert_eq!(table_tree.iter().count(), 0);
+        assert!(table_tree
+            .match_longest_prefix_only(&Prefix::new(0x0a01_0203, 32))
+            .is_none());
+
+        for pfx in pfxs.iter().rev() {
+            table_tree.insert(*pfx);
+            tree.insert(*pfx);
+        }
+        compare(&tree, &table_tree);
+    }
+
+    #[test]
+    fn test_root_table_ipv4() {
+        compare_root_table(vec![8], 16);
+        compare_root_table(vec![8], 24);
+        compare_root_table(vec![3, 4, 5, 4, 4, 4, 4, 4], 16);
+        compare_root_table(vec![6, 6, 6, 6, 4, 4], 24);
+    }
+
+    #[test]
+    #[should_panic(expected = "contain a stride size other than 3 to 8")]
+    fn test_strides_zero() {
+        TreeBitMap::<u32, NoMeta>::new(vec![0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "no strides given")]
+    fn test_strides_empty() {
+        TreeBitMap::<u32, NoMeta>::new(vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "don't end on bit 16 of the root table")]
+    fn test_root_table_off_stride() {
+        TreeBitMap::<u32, NoMeta>::with_root_table(vec![6, 6, 6, 6, 4, 4], 16);
+    }
+
+    #[test]
+    fn test_match_longest_prefix_batch_ipv4() {
+        let pfxs = load_test_csv();
+
+        // Addresses in and around the stored prefixes, and random ones.
+        let mut addrs: Vec<u32> = pfxs.iter().map(|p| p.net).collect();
+        addrs.extend(pfxs.iter().map(|p| p.net | 0xff));
+        let mut addr: u32 = 1;
+        for _ in 0..10_000 {
+            addr = addr.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            addrs.push(addr);
+        }
+
+        for (strides, bits) in [
+            (vec![4], None),
+            (vec![3, 4, 5, 6, 5, 5, 4], None),
+            (vec![8], None),
+            (vec![8], Some(16)),
+            (vec![6, 6, 6, 6, 4, 4], Some(24)),
+        ]
+        .iter()
+        {
+            let mut tree = match bits {
+                Some(bits) => TreeBitMap::<u32, PrefixAs>::with_root_table(strides.clone(), *bits),
+                None => TreeBitMap::<u32, PrefixAs>::new(strides.clone()),
+            };
+            for pfx in pfxs.iter() {
+                tree.insert(*pfx);
+            }
+            tree.insert(Prefix::new_with_meta(0, 0, PrefixAs(0)));
+
+            // A batch size that doesn't divide into the lanes.
+            let mut results = vec![None; addrs.len() - 3];
+            tree.match_longest_prefix_batch(&addrs[3..], &mut results);
+            for (addr, result) in addrs[3..].iter().zip(results.iter()) {
+                let pfx = Prefix::<u32, NoMeta>::new(*addr, 32);
+                assert_eq!(*result, tree.match_longest_prefix_only(&pfx));
+            }
+        }
+
+        let tree = TreeBitMap::<u32, PrefixAs>::new(vec![4]);
+        let mut results = vec![Some(&pfxs[0]); 2];
+        tree.match_longest_prefix_batch(&addrs[..2], &mut results);
+        assert_eq!(results, [None, None]);
+    }
+
+    // A compacted tree should answer every query like the one it was
+    // compacted from, also after changing it and compacting it again.
+    #[test]
+    fn test_compact_ipv4() {
+        let pfxs = load_test_csv();
+
+        let compare = |tree: &TreeBitMap<u32, PrefixAs>, compacted: &TreeBitMap<u32, PrefixAs>| {
+            assert!(tree.iter().eq(compacted.iter()));
+            let mut addr: u32 = 1;
+            for len in 0..=32 {
+                for _ in 0..500 {
+                    addr = addr.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    let pfx = Prefix::<u32, NoMeta>::new(addr, len);
+                    assert_eq!(
+                        compacted.match_longest_prefix(&pfx),
+                        tree.match_longest_prefix(&pfx)
+                    );
+                    assert_eq!(
+                        compacted.match_longest_prefix_only(&pfx),
+                        tree.match_longest_prefix_only(&pfx)
+                    );
+                }
+            }
+            for pfx in pfxs.iter().step_by(7) {
+                let pfx = pfx.strip_meta();
+                assert_eq!(compacted.get(&pfx), tree.get(&pfx));
+                assert!(compacted.more_specifics(&pfx).eq(tree.more_specifics(&pfx)));
+            }
+        };
+
+        for (strides, bits) in [
+            (vec![4], None),
+            (vec![3, 4, 5, 6, 5, 5, 4], None),
+            (vec![8], Some(16)),
+            (vec![3, 4, 4, 6, 7, 8], None),
+        ]
+        .iter()
+        {
+            let new = || match bits {
+                Some(bits) => TreeBitMap::<u32, PrefixAs>::with_root_table(strides.clone(), *bits),
+                None => TreeBitMap::<u32, PrefixAs>::new(strides.clone()),
+            };
+            let mut tree = new();
+            let mut compacted = new();
+            for pfx in pfxs.iter() {
+                tree.insert(*pfx);
+                compacted.insert(*pfx);
+            }
+            for pfx in pfxs.iter().step_by(3) {
+                tree.remove(&pfx.strip_meta());
+                compacted.remove(&pfx.strip_meta());
+            }
+
+            compacted.compact();
+            compare(&tree, &compacted);
+            // No vacated slots are left.
+            assert_eq!(compacted.prefixes.len(), tree.iter().count());
+            let nodes_num: usize = tree
+                .stats
+                .iter()
+                .map(|s| s.created_nodes.iter().map(|c| c.count).sum::<usize>())
+                .sum();
+            assert_eq!(compacted.nodes.len(), nodes_num);
+
+            // Put back what was removed and take out some others.
+            for pfx in pfxs.iter().step_by(3) {
+                assert_eq!(tree.insert(*pfx), compacted.insert(*pfx));
+            }
+            for pfx in pfxs.iter().skip(1).step_by(4) {
+                assert_eq!(
+                    tree.remove(&pfx.strip_meta()),
+                    compacted.remove(&pfx.strip_meta())
+                );
+            }
+            compare(&tree, &compacted);
+
+            compacted.compact();
+            compare(&tree, &compacted);
+        }
+    }
+
+    // Building the tree bottom-up should end up with exactly the nodes,
+    // prefixes and stats of inserting the same prefixes one by one in
+    // canonical order, in any other order only the lookups are the same.
+    #[test]
+    fn test_bulk_load_ipv4() {
+        let mut pfxs = load_test_csv();
+        pfxs.push(Prefix::new_with_meta(0, 0, PrefixAs(0)));
+        pfxs.push(Prefix::new_with_meta(0xffff_ffff, 32, PrefixAs(32)));
+        let mut sorted = pfxs.clone();
+        sorted.sort();
+        sorted.dedup();
+
+        // Every tenth prefix once more, with other meta data, in another
+        // order.
+        let mut shuffled = pfxs.clone();
+        shuffled.reverse();
+        shuffled.extend(
+            pfxs.iter()
+                .step_by(10)
+                .map(|p| Prefix::new_with_meta(p.net, p.len, PrefixAs(p.meta.unwrap().0 + 1))),
+        );
+
+        for strides in [vec![4], vec![3, 4, 5, 6, 5, 5, 4], vec![8], vec![3, 4, 4, 6, 7, 8]].iter() {
+            let mut tree = TreeBitMap::<u32, PrefixAs>::new(strides.clone());
+            for pfx in sorted.iter() {
+                tree.insert(*pfx);
+            }
+            let bulk = TreeBitMap::from_sorted_iter(strides.clone(), sorted.iter().copied());
+            assert_eq!(format!("{:?}", bulk.nodes), format!("{:?}", tree.nodes));
+            assert_eq!(format!("{:?}", bulk.stats), format!("{:?}", tree.stats));
+            assert_eq!(bulk.prefixes, tree.prefixes);
+            for pfx in sorted.iter() {
+                let pfx = pfx.strip_meta();
+                assert_eq!(
+                    bulk.match_longest_prefix(&pfx),
+                    tree.match_longest_prefix(&pfx)
+                );
+            }
+
+            // The last one of the duplicates wins, like with `insert`.
+            let mut tree = TreeBitMap::<u32, PrefixAs>::new(strides.clone());
+            for pfx in shuffled.iter() {
+                tree.insert(*pfx);
+            }
+            let bulk = TreeBitMap::bulk_load(strides.clone(), shuffled.clone());
+            assert_eq!(bulk.prefixes.len(), sorted.len());
+            let metas = |tree: &TreeBitMap<u32, PrefixAs>| -> Vec<(String, Option<PrefixAs>)> {
+                tree.iter().map(|p| (p.to_string(), p.meta)).collect()
+            };
+            assert_eq!(metas(&bulk), metas(&tree));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "the prefixes aren't sorted")]
+    fn test_from_sorted_iter_unsorted() {
+        let pfxs: Vec<Prefix<u32, NoMeta>> = vec![
+            "10.1.0.0/16".parse().unwrap(),
+            "10.0.0.0/8".parse().unwrap(),
+        ];
+        TreeBitMap::from_sorted_iter(vec![4], pfxs);
+    }
 }
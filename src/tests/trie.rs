@@ -0,0 +1,46 @@
+#[cfg(test)]
+mod test {
+    use crate::common::*;
+
+    #[test]
+    fn test_remove_ipv4() {
+        let mut trie: Trie<u32, PrefixAs> = Trie::new();
+        let pfxs: Vec<Prefix<u32, PrefixAs>> = vec![
+            Prefix::new_with_meta(std::net::Ipv4Addr::new(0, 0, 0, 0).into(), 0, PrefixAs(0)),
+            Prefix::new_with_meta(std::net::Ipv4Addr::new(192, 0, 0, 0).into(), 16, PrefixAs(1)),
+            Prefix::new_with_meta(std::net::Ipv4Addr::new(192, 0, 0, 0).into(), 23, PrefixAs(2)),
+            Prefix::new_with_meta(std::net::Ipv4Addr::new(192, 0, 1, 0).into(), 24, PrefixAs(3)),
+            Prefix::new_with_meta(std::net::Ipv4Addr::new(193, 0, 10, 10).into(), 32, PrefixAs(4)),
+        ];
+
+        for pfx in pfxs.iter() {
+            trie.insert(*pfx);
+        }
+
+        // 192.0.1.0/24 hangs off of 192.0.0.0/23, removing the /23 should
+        // not prune anything.
+        let nodes_num: u32 = trie.1.iter().map(|l| l.nodes_num).sum();
+        let removed = trie.remove(&pfxs[2].strip_meta()).unwrap();
+        assert_eq!(removed.meta, Some(PrefixAs(2)));
+        assert_eq!(trie.1.iter().map(|l| l.nodes_num).sum::<u32>(), nodes_num);
+        assert_eq!(
+            trie.match_longest_prefix(&pfxs[2].strip_meta()).unwrap().meta,
+            Some(PrefixAs(1))
+        );
+
+        // The /32 has a branch of its own from the eighth bit down.
+        let removed = trie.remove(&pfxs[4].strip_meta()).unwrap();
+        assert_eq!(removed.meta, Some(PrefixAs(4)));
+        assert!(trie.remove(&pfxs[4].strip_meta()).is_none());
+        assert_eq!(trie.1.iter().map(|l| l.nodes_num).sum::<u32>(), nodes_num - 25);
+
+        for pfx in [&pfxs[3], &pfxs[1], &pfxs[0]].iter() {
+            assert_eq!(trie.remove(&pfx.strip_meta()).unwrap().meta, pfx.meta);
+        }
+
+        // Only the root node is left.
+        assert!(trie.1.iter().all(|l| l.nodes_num == 0 && l.prefixes_num == 0));
+        assert_eq!(trie.iter().count(), 0);
+        assert_eq!(trie.traverse_count(), (0, 0));
+    }
+}
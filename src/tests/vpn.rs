@@ -0,0 +1,307 @@
+#[cfg(test)]
+mod test {
+    use crate::common::*;
+    use crate::radixtrie::RadixTrie;
+    use crate::treebitmap;
+    use crate::treebitmap_univec;
+    use crate::vpn::{fmt_rd, parse_rd, Vpnv4, Vpnv6};
+    use num::{Bounded, CheckedAdd, CheckedMul, Num, One, PrimInt, Zero};
+
+    #[test]
+    fn test_rd() {
+        for (s, rd) in [
+            ("65000:1", 0x0000_fde8_0000_0001),
+            ("192.0.2.1:7", 0x0001_c000_0201_0007),
+            ("4200000000:1", 0x0002_fa56_ea00_0001),
+            ("65000L:1", 0x0002_0000_fde8_0001),
+            ("0x3000000000000001", 0x3000_0000_0000_0001),
+        ]
+        .iter()
+        {
+            assert_eq!(parse_rd(s), Ok(*rd));
+            assert_eq!(fmt_rd(*rd), *s);
+        }
+        assert_eq!(
+            parse_rd("65000"),
+            Err(PrefixParseError::InvalidRouteDistinguisher)
+        );
+        assert_eq!(
+            parse_rd("4200000000:65536"),
+            Err(PrefixParseError::InvalidRouteDistinguisher)
+        );
+    }
+
+    #[test]
+    fn test_parse_vpnv4() {
+        let pfx: Prefix<Vpnv4, NoMeta> = "65000:1:10.0.0.0/8".parse().unwrap();
+        assert_eq!(pfx.net.rd(), 0x0000_fde8_0000_0001);
+        assert_eq!(pfx.net.addr(), std::net::Ipv4Addr::new(10, 0, 0, 0));
+        assert_eq!(pfx.len, 72);
+        assert_eq!(pfx.to_string(), "65000:1:10.0.0.0/8");
+        assert_eq!(
+            "192.0.2.1:7:0.0.0.0/0"
+                .parse::<Prefix<Vpnv4, NoMeta>>()
+                .unwrap()
+                .to_string(),
+            "192.0.2.1:7:0.0.0.0/0"
+        );
+
+        // A route distinguisher of another type is written as a hex number.
+        for s in ["0x3000000000000001:10.0.0.0/8", "0x3000000000000000/60"].iter() {
+            let pfx: Prefix<Vpnv4, NoMeta> = s.parse().unwrap();
+            assert_eq!(pfx.net.rd() >> 48, 0x3000);
+            assert_eq!(pfx.to_string(), *s);
+        }
+
+        // Prefixes shorter than the route distinguisher.
+        let pfx: Prefix<Vpnv4, NoMeta> = "65000:0/48".parse().unwrap();
+        assert_eq!(pfx.len, 48);
+        assert_eq!(pfx.to_string(), "65000:0/48");
+        let more_specific: Prefix<Vpnv4, NoMeta> = "65000:1:10.0.0.0/8".parse().unwrap();
+        assert!(pfx.covers(&more_specific));
+
+        assert_eq!(
+            "65000:1:10.0.0.0/33"
+                .parse::<Prefix<Vpnv4, NoMeta>>()
+                .unwrap_err(),
+            PrefixParseError::LengthOutOfRange(33)
+        );
+        assert_eq!(
+            "65000:1:10.0.0.1/8"
+                .parse::<Prefix<Vpnv4, NoMeta>>()
+                .unwrap_err(),
+            PrefixParseError::HostBitsSet
+        );
+        assert!(matches!(
+            "65000:1:10.0.0/8".parse::<Prefix<Vpnv4, NoMeta>>(),
+            Err(PrefixParseError::InvalidAddress(_))
+        ));
+        assert_eq!(
+            "x:1:10.0.0.0/8"
+                .parse::<Prefix<Vpnv4, NoMeta>>()
+                .unwrap_err(),
+            PrefixParseError::InvalidRouteDistinguisher
+        );
+    }
+
+    // The nibbles come out of the key in one piece, also where they cross
+    // from the route distinguisher into the address.
+    #[test]
+    fn test_get_nibble_vpnv4() {
+        let key = Vpnv4::new(0x0000_fde8_0000_0001, std::net::Ipv4Addr::new(10, 0, 0, 255));
+        assert_eq!(Vpnv4::get_nibble(key, 0, 8), 0x00);
+        assert_eq!(Vpnv4::get_nibble(key, 16, 16), 0xfde8);
+        assert_eq!(Vpnv4::get_nibble(key, 60, 8), 0x10);
+        assert_eq!(Vpnv4::get_nibble(key, 62, 6), 0b010000);
+        assert_eq!(Vpnv4::get_nibble(key, 88, 8), 0xff);
+        assert_eq!(Vpnv4::get_nibble(key, 92, 4), 0xf);
+        assert_eq!(Vpnv4::netmask(72).leading_zeros(), 0);
+        assert_eq!((!Vpnv4::netmask(72)).leading_zeros(), 72);
+    }
+
+    fn exercise<S: PrefixStore<Vpnv4, PrefixAs>>(store: &mut S) {
+        let pfx = |s: &str| s.parse::<Prefix<Vpnv4, NoMeta>>().unwrap();
+        for (s, asn) in [
+            ("65000:1:10.0.0.0/8", 1),
+            ("65000:1:10.1.0.0/16", 2),
+            ("65000:2:10.0.0.0/8", 3),
+            ("65000:2:0.0.0.0/0", 4),
+            ("65001:1:10.1.0.0/16", 5),
+        ]
+        .iter()
+        {
+            let p = pfx(s);
+            store.insert(Prefix::new_with_meta(p.net, p.len, PrefixAs(*asn)));
+        }
+
+        let lmp = |store: &S, s: &str| {
+            store
+                .match_longest_prefix(&pfx(s))
+                .map(|p| p.meta.unwrap().0)
+        };
+        assert_eq!(lmp(store, "65000:1:10.1.2.0/24"), Some(2));
+        assert_eq!(lmp(store, "65000:1:10.2.0.0/24"), Some(1));
+        // The default route of one VPN doesn't leak into the others.
+        assert_eq!(lmp(store, "65000:1:11.0.0.0/24"), None);
+        assert_eq!(lmp(store, "65000:2:11.0.0.0/24"), Some(4));
+        assert_eq!(lmp(store, "65000:2:10.1.2.0/24"), Some(3));
+        assert_eq!(lmp(store, "65001:1:10.1.2.0/24"), Some(5));
+        assert_eq!(lmp(store, "65001:1:10.2.0.0/24"), None);
+        assert_eq!(lmp(store, "65002:1:10.1.2.0/24"), None);
+
+        let found: Vec<String> = store.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            found,
+            [
+                "65000:1:10.0.0.0/8",
+                "65000:1:10.1.0.0/16",
+                "65000:2:0.0.0.0/0",
+                "65000:2:10.0.0.0/8",
+                "65001:1:10.1.0.0/16"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stores_vpnv4() {
+        exercise(&mut Trie::<Vpnv4, PrefixAs>::new());
+        exercise(&mut RadixTrie::<Vpnv4, PrefixAs>::new());
+        exercise(&mut treebitmap::TreeBitMap::<Vpnv4, PrefixAs>::new(vec![4]));
+        exercise(&mut treebitmap::TreeBitMap::<Vpnv4, PrefixAs>::new(vec![
+            6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6,
+        ]));
+        exercise(&mut treebitmap_univec::TreeBitMap::<Vpnv4, PrefixAs>::new(
+            vec![4],
+        ));
+        exercise(&mut treebitmap_univec::TreeBitMap::<Vpnv4, PrefixAs>::new(
+            vec![8],
+        ));
+    }
+
+    #[test]
+    fn test_parse_vpnv6() {
+        let pfx: Prefix<Vpnv6, NoMeta> = "65000:1:2001:db8::/32".parse().unwrap();
+        assert_eq!(pfx.net.rd(), 0x0000_fde8_0000_0001);
+        assert_eq!(pfx.net.addr(), "2001:db8::".parse::<std::net::Ipv6Addr>().unwrap());
+        assert_eq!(pfx.len, 96);
+
+        for s in [
+            "65000:1:2001:db8::/32",
+            "192.0.2.1:7:::/0",
+            "0x3000000000000001:2001:db8:1::/48",
+            "65000:0/48",
+            "0x3000000000000000/60",
+        ]
+        .iter()
+        {
+            let pfx: Prefix<Vpnv6, NoMeta> = s.parse().unwrap();
+            assert_eq!(pfx.to_string(), *s);
+        }
+
+        let pfx: Prefix<Vpnv6, NoMeta> = "65000:0/48".parse().unwrap();
+        let more_specific: Prefix<Vpnv6, NoMeta> = "65000:1:2001:db8::/32".parse().unwrap();
+        assert!(pfx.covers(&more_specific));
+
+        assert_eq!(
+            "65000:1:2001:db8::/129"
+                .parse::<Prefix<Vpnv6, NoMeta>>()
+                .unwrap_err(),
+            PrefixParseError::LengthOutOfRange(129)
+        );
+        assert_eq!(
+            "65000:1:2001:db8::1/64"
+                .parse::<Prefix<Vpnv6, NoMeta>>()
+                .unwrap_err(),
+            PrefixParseError::HostBitsSet
+        );
+        assert!(matches!(
+            "65000:1:10.0.0.0/8".parse::<Prefix<Vpnv6, NoMeta>>(),
+            Err(PrefixParseError::InvalidAddress(_))
+        ));
+    }
+
+    #[test]
+    fn test_get_nibble_vpnv6() {
+        let addr: std::net::Ipv6Addr = "2001:db8::ff".parse().unwrap();
+        let key = Vpnv6::new(0x0000_fde8_0000_0001, addr);
+        assert_eq!(Vpnv6::get_nibble(key, 0, 8), 0x00);
+        assert_eq!(Vpnv6::get_nibble(key, 16, 16), 0xfde8);
+        assert_eq!(Vpnv6::get_nibble(key, 60, 8), 0x12);
+        assert_eq!(Vpnv6::get_nibble(key, 62, 6), 0b010010);
+        assert_eq!(Vpnv6::get_nibble(key, 64, 32), 0x2001_0db8);
+        assert_eq!(Vpnv6::get_nibble(key, 184, 8), 0xff);
+        assert_eq!(Vpnv6::get_nibble(key, 188, 4), 0xf);
+        assert_eq!(Vpnv6::netmask(96).leading_zeros(), 0);
+        assert_eq!((!Vpnv6::netmask(96)).leading_zeros(), 96);
+        assert_eq!(Vpnv6::netmask(192), Vpnv6::max_value());
+    }
+
+    // The arithmetic has to carry across the route distinguisher and the
+    // address like a 192 bit integer would.
+    #[test]
+    fn test_arithmetic_vpnv6() {
+        let key = |rd: u64, addr: u128| Vpnv6::new(rd, addr.into());
+        let one = Vpnv6::one();
+        assert_eq!(key(0, u128::MAX) + one, key(1, 0));
+        assert_eq!(key(1, 0) - one, key(0, u128::MAX));
+        assert_eq!(Vpnv6::max_value() + one, Vpnv6::zero());
+        assert_eq!(Vpnv6::max_value().checked_add(&one), None);
+
+        for n in [0, 1, 63, 64, 127, 128, 129, 191].iter() {
+            assert_eq!((one << *n).trailing_zeros(), *n as u32);
+            assert_eq!((one << *n) >> *n, one);
+            assert_eq!(one.rotate_left(*n as u32).rotate_right(*n as u32), one);
+        }
+        assert_eq!(one << 192, Vpnv6::zero());
+        assert_eq!(key(0xff, 0) >> 124, key(0, 0xff0));
+        assert_eq!(key(0xff, 0) >> 132, key(0, 0xf));
+        assert_eq!(key(0, 0xff) << 124, key(0xf, 0xf << 124));
+
+        let n = key(0x1234, 0x5678_9abc);
+        let d = key(0, 0x1_0000_0001);
+        assert_eq!((n / d) * d + n % d, n);
+        assert_eq!(n.checked_mul(&key(1, 0)), None);
+        assert_eq!(Vpnv6::from_str_radix("1234", 10), Ok(key(0, 1234)));
+        assert_eq!(
+            Vpnv6::from_str_radix(&format!("{:x}{:032x}", 0x1234, 0x5678_9abc), 16),
+            Ok(n)
+        );
+        assert!(Vpnv6::from_str_radix("", 10).is_err());
+        assert!(Vpnv6::from_str_radix("12a", 10).is_err());
+        assert_eq!(n.swap_bytes().swap_bytes(), n);
+    }
+
+    fn exercise_vpnv6<S: PrefixStore<Vpnv6, PrefixAs>>(store: &mut S) {
+        let pfx = |s: &str| s.parse::<Prefix<Vpnv6, NoMeta>>().unwrap();
+        for (s, asn) in [
+            ("65000:1:2001:db8::/32", 1),
+            ("65000:1:2001:db8:1::/48", 2),
+            ("65000:2:2001:db8::/32", 3),
+            ("65000:2:::/0", 4),
+            ("65001:1:2001:db8:1::/48", 5),
+        ]
+        .iter()
+        {
+            let p = pfx(s);
+            store.insert(Prefix::new_with_meta(p.net, p.len, PrefixAs(*asn)));
+        }
+
+        let lmp = |store: &S, s: &str| {
+            store
+                .match_longest_prefix(&pfx(s))
+                .map(|p| p.meta.unwrap().0)
+        };
+        assert_eq!(lmp(store, "65000:1:2001:db8:1:2::/64"), Some(2));
+        assert_eq!(lmp(store, "65000:1:2001:db8:2::/64"), Some(1));
+        assert_eq!(lmp(store, "65000:1:2001:db9::/64"), None);
+        assert_eq!(lmp(store, "65000:2:2001:db9::/64"), Some(4));
+        assert_eq!(lmp(store, "65001:1:2001:db8:1:2::/64"), Some(5));
+        assert_eq!(lmp(store, "65001:1:2001:db8:2::/64"), None);
+
+        let found: Vec<String> = store.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            found,
+            [
+                "65000:1:2001:db8::/32",
+                "65000:1:2001:db8:1::/48",
+                "65000:2:::/0",
+                "65000:2:2001:db8::/32",
+                "65001:1:2001:db8:1::/48"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stores_vpnv6() {
+        exercise_vpnv6(&mut Trie::<Vpnv6, PrefixAs>::new());
+        exercise_vpnv6(&mut RadixTrie::<Vpnv6, PrefixAs>::new());
+        exercise_vpnv6(&mut treebitmap::TreeBitMap::<Vpnv6, PrefixAs>::new(vec![4]));
+        exercise_vpnv6(&mut treebitmap::TreeBitMap::<Vpnv6, PrefixAs>::new(vec![8]));
+        exercise_vpnv6(&mut treebitmap_univec::TreeBitMap::<Vpnv6, PrefixAs>::new(
+            vec![4],
+        ));
+        exercise_vpnv6(&mut treebitmap_univec::TreeBitMap::<Vpnv6, PrefixAs>::new(
+            vec![3, 5, 8],
+        ));
+    }
+}
@@ -1,66 +1,133 @@
-use crate::common::{AddressFamily, NoMeta, Prefix};
+use crate::common::{AddressFamily, MatchOptions, NoMeta, Prefix, PrefixStore, QueryResult};
 use num::PrimInt;
-use std::cmp::Ordering;
 use std::fmt::{Binary, Debug};
 
-#[derive(Copy, Clone)]
-pub struct U256(u128, u128);
+// The bitmaps for strides 7 and 8 don't fit in a primitive integer, so
+// they are arrays of u64 words, the most significant word first. Bit n of
+// the bitmap, counted from the right like in an integer, is bit n % 64 of
+// word N - 1 - n / 64.
+#[derive(Copy, Clone, Eq, PartialOrd, Ord)]
+pub struct U256([u64; 4]);
+
+#[derive(Copy, Clone, Eq, PartialOrd, Ord)]
+pub struct U512([u64; 8]);
+
+// Comparing the words one by one instead of the arrays as a whole, which
+// the compiler turns into a call to memcmp, for the `bitmap & bit_pos ==
+// zero` checks in the hot paths.
+impl PartialEq for U256 {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        words_eq(&self.0, &other.0)
+    }
+}
+
+impl PartialEq for U512 {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        words_eq(&self.0, &other.0)
+    }
+}
 
 impl Debug for U256 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!(
-            "{:0128b}\n             {:0128b}",
-            self.0, self.1
-        ))
+        Binary::fmt(self, f)
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct U512(u128, u128, u128, u128);
+impl Debug for U512 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Binary::fmt(self, f)
+    }
+}
 
-pub type Stride3 = u16;
-pub type Stride4 = u32;
-pub type Stride5 = u64;
-pub type Stride6 = u128;
-pub type Stride7 = U256;
-pub type Stride8 = U512;
+impl Binary for U256 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_words(&self.0, f)
+    }
+}
 
-impl PartialOrd for U256 {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        match (self.0, &other.0) {
-            (a, b) if &a > b => Some(self.0.cmp(&other.0)),
-            _ => Some(self.1.cmp(&other.1)),
-        }
+impl Binary for U512 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_words(&self.0, f)
     }
 }
 
-impl Ord for U256 {
-    fn cmp(&self, other: &Self) -> Ordering {
-        match (self.0, &other.0) {
-            (a, b) if &a > b => self.0.cmp(&other.0),
-            _ => self.1.cmp(&other.1),
-        }
+fn fmt_words(words: &[u64], f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    for w in words {
+        write!(f, "{:064b}", w)?;
     }
+    Ok(())
 }
 
-impl PartialOrd for U512 {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        match (self.0, &other.0) {
-            (a, b) if &a > b => Some(self.0.cmp(&other.0)),
-            _ => match (self.1, &other.1) {
-                (a, b) if &a > b => Some(self.1.cmp(&other.1)),
-                _ => match (self.2, &other.2) {
-                    (a, b) if &a > b => Some(self.2.cmp(&other.2)),
-                    _ => Some(self.3.cmp(&other.3)),
-                },
-            },
+#[inline]
+fn words_eq(a: &[u64], b: &[u64]) -> bool {
+    a.iter().zip(b.iter()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// The words with only bit n set.
+#[inline]
+fn words_bit<const N: usize>(n: u16) -> [u64; N] {
+    let mut words = [0; N];
+    words[N - 1 - (n / 64) as usize] = 1 << (n % 64);
+    words
+}
+
+// The number of ones in bit n and all the bits to the left of it. That's
+// the popcount of all the words before the word holding bit n, plus the
+// popcount of that word shifted right up to bit n.
+#[inline]
+fn words_count_ones_from<const N: usize>(words: &[u64; N], n: u16) -> usize {
+    let word = N - 1 - (n / 64) as usize;
+    words[..word]
+        .iter()
+        .map(|w| w.count_ones() as usize)
+        .sum::<usize>()
+        + (words[word] >> (n % 64)).count_ones() as usize
+}
+
+#[inline]
+fn words_shl1<const N: usize>(words: [u64; N]) -> [u64; N] {
+    let mut res = [0; N];
+    for i in 0..N - 1 {
+        res[i] = words[i] << 1 | words[i + 1] >> 63;
+    }
+    res[N - 1] = words[N - 1] << 1;
+    res
+}
+
+#[inline]
+fn words_shr1<const N: usize>(words: [u64; N]) -> [u64; N] {
+    let mut res = [0; N];
+    res[0] = words[0] >> 1;
+    for i in 1..N {
+        res[i] = words[i] >> 1 | words[i - 1] << 63;
+    }
+    res
+}
+
+#[inline]
+fn words_leading_zeros(words: &[u64]) -> u32 {
+    let mut lz = 0;
+    for w in words {
+        lz += w.leading_zeros();
+        if *w != 0 {
+            break;
         }
     }
+    lz
 }
 
+pub type Stride3 = u16;
+pub type Stride4 = u32;
+pub type Stride5 = u64;
+pub type Stride6 = u128;
+pub type Stride7 = U256;
+pub type Stride8 = U512;
+
 pub trait Stride: Sized + Debug + Binary + Eq + PartialOrd + PartialEq + Copy {
     type PtrSize;
-    const BITS: u8;
+    const BITS: u16;
     const STRIDE_LEN: u8;
 
     // Get the bit position of the start of the given nibble.
@@ -152,20 +219,20 @@ impl One for u8 {
 
 impl Stride for Stride3 {
     type PtrSize = u8;
-    const BITS: u8 = 16;
+    const BITS: u16 = 16;
     const STRIDE_LEN: u8 = 3;
 
     fn get_bit_pos(nibble: u32, len: u8) -> Self {
-        1 << (<Self as Stride>::BITS - ((1 << len) - 1) as u8 - nibble as u8 - 1)
+        1 << (<Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1)
     }
 
     fn get_pfx_index(bitmap: Self, nibble: u32, len: u8) -> usize {
-        (bitmap >> ((<Self as Stride>::BITS - ((1 << len) - 1) as u8 - nibble as u8 - 1) as usize))
+        (bitmap >> ((<Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1) as usize))
             .count_ones() as usize
             - 1
     }
     fn get_ptr_index(bitmap: Self::PtrSize, nibble: u32) -> usize {
-        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u8 - 1) as usize).count_ones()
+        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u16 - 1) as usize).count_ones()
             as usize
             - 1
     }
@@ -196,21 +263,21 @@ impl Stride for Stride3 {
 
 impl Stride for Stride4 {
     type PtrSize = u16;
-    const BITS: u8 = 32;
+    const BITS: u16 = 32;
     const STRIDE_LEN: u8 = 4;
 
     fn get_bit_pos(nibble: u32, len: u8) -> u32 {
-        1 << (<Self as Stride>::BITS - ((1 << len) - 1) as u8 - nibble as u8 - 1)
+        1 << (<Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1)
     }
 
     fn get_pfx_index(bitmap: Self, nibble: u32, len: u8) -> usize {
-        (bitmap >> ((<Self as Stride>::BITS - ((1 << len) - 1) as u8 - nibble as u8 - 1) as usize))
+        (bitmap >> ((<Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1) as usize))
             .count_ones() as usize
             - 1
     }
 
     fn get_ptr_index(bitmap: u16, nibble: u32) -> usize {
-        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u8 - 1) as usize).count_ones()
+        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u16 - 1) as usize).count_ones()
             as usize
             - 1
     }
@@ -241,20 +308,20 @@ impl Stride for Stride4 {
 
 impl Stride for Stride5 {
     type PtrSize = u32;
-    const BITS: u8 = 64;
+    const BITS: u16 = 64;
     const STRIDE_LEN: u8 = 5;
 
     fn get_bit_pos(nibble: u32, len: u8) -> u64 {
-        1 << (<Self as Stride>::BITS - ((1 << len) - 1) as u8 - nibble as u8 - 1)
+        1 << (<Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1)
     }
 
     fn get_pfx_index(bitmap: Self, nibble: u32, len: u8) -> usize {
-        (bitmap >> ((<Self as Stride>::BITS - ((1 << len) - 1) as u8 - nibble as u8 - 1) as usize))
+        (bitmap >> ((<Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1) as usize))
             .count_ones() as usize
             - 1
     }
     fn get_ptr_index(bitmap: u32, nibble: u32) -> usize {
-        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u8 - 1) as usize).count_ones()
+        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u16 - 1) as usize).count_ones()
             as usize
             - 1
     }
@@ -285,20 +352,20 @@ impl Stride for Stride5 {
 
 impl Stride for Stride6 {
     type PtrSize = u64;
-    const BITS: u8 = 128;
+    const BITS: u16 = 128;
     const STRIDE_LEN: u8 = 6;
 
     fn get_bit_pos(nibble: u32, len: u8) -> u128 {
-        1 << (<Self as Stride>::BITS - ((1 << len) - 1) as u8 - nibble as u8 - 1)
+        1 << (<Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1)
     }
 
     fn get_pfx_index(bitmap: Self, nibble: u32, len: u8) -> usize {
-        (bitmap >> ((<Self as Stride>::BITS - ((1 << len) - 1) as u8 - nibble as u8 - 1) as usize))
+        (bitmap >> ((<Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1) as usize))
             .count_ones() as usize
             - 1
     }
     fn get_ptr_index(bitmap: u64, nibble: u32) -> usize {
-        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u8 - 1) as usize).count_ones()
+        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u16 - 1) as usize).count_ones()
             as usize
             - 1
     }
@@ -329,462 +396,193 @@ impl Stride for Stride6 {
 
 impl Stride for Stride7 {
     type PtrSize = u128;
-    const BITS: u8 = 255;
+    const BITS: u16 = 256;
     const STRIDE_LEN: u8 = 7;
 
     fn get_bit_pos(nibble: u32, len: u8) -> Self {
-        match 256 - ((1 << len) - 1) as u16 - nibble as u16 - 1 {
-            n if n < 128 => {
-                U256(0, 1 << n)
-            }
-            n => {
-                U256(1 << (n as u16 - 128), 0)
-            }
-        }
+        U256(words_bit(
+            <Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1,
+        ))
     }
 
     fn get_pfx_index(bitmap: Self, nibble: u32, len: u8) -> usize {
-        let n = 256 - ((1 << len) - 1) as u16 - nibble as u16 - 1;
-        match n {
-            // if we move less than 128 bits to the right,
-            // all of bitmap.0 and a part of bitmap.1 will be used for counting zeros
-            // ex.
-            // ...1011_1010... >> 2 => ...0010_111010...
-            //    ____ ====                 -- --====
-            n if n < 128 => {
-                bitmap.0.count_ones() as usize + (bitmap.1 >> n).count_ones() as usize - 1
-            }
-            // if we move more than 128 bits to the right,
-            // all of bitmap.1 wil be shifted out of sight,
-            // so we only have to count bitmap.0 zeroes than (after) shifting of course).
-            n => (bitmap.0 >> n).count_ones() as usize - 1,
-        }
+        words_count_ones_from(
+            &bitmap.0,
+            <Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1,
+        ) - 1
     }
 
     fn get_ptr_index(bitmap: Self::PtrSize, nibble: u32) -> usize {
-        (bitmap >> ((256 >> 1) - nibble as u16 - 1) as usize).count_ones() as usize - 1
+        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u16 - 1) as usize).count_ones()
+            as usize
+            - 1
     }
 
     fn into_stride_size(bitmap: Self::PtrSize) -> Self {
-        // One bit needs to move into the self.0 u128,
-        // since the last bit of the *whole* bitmap isn't used.
-        U256(bitmap >> 127, bitmap << 1)
+        U256(words_shl1([0, 0, (bitmap >> 64) as u64, bitmap as u64]))
     }
 
     fn into_ptrbitarr_size(bitmap: Self) -> Self::PtrSize {
-        // TODO expand:
-        // self.ptrbitarr =
-        // S::into_ptrbitarr_size(bit_pos | S::into_stride_size(self.ptrbitarr));
-        (bitmap.0 << 127 | bitmap.1 >> 1) as u128
+        let words = words_shr1(bitmap.0);
+        (words[2] as u128) << 64 | words[3] as u128
     }
 
     #[inline]
     fn zero() -> Self {
-        U256(0, 0)
+        U256([0; 4])
     }
 
     #[inline]
     fn one() -> Self {
-        U256(0, 1)
+        U256([0, 0, 0, 1])
     }
 
     #[inline]
     fn leading_zeros(self) -> u32 {
-        let lz = self.0.leading_zeros();
-        let r = if lz == 128 {
-            lz + self.1.leading_zeros()
-        } else {
-            lz
-        };
-        r
-    }
-}
-
-impl PartialEq for Stride7 {
-    fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0 && self.1 == other.1
+        words_leading_zeros(&self.0)
     }
 }
 
-impl Eq for Stride7 {}
-
-impl Binary for Stride7 {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        std::fmt::Binary::fmt(&self, f)
+impl std::ops::BitOr<Self> for Stride7 {
+    type Output = Self;
+    fn bitor(mut self, rhs: Self) -> Self::Output {
+        for (w, r) in self.0.iter_mut().zip(rhs.0.iter()) {
+            *w |= r;
+        }
+        self
     }
 }
 
-impl std::ops::BitOr<Self> for Stride7 {
+impl std::ops::BitAnd<Self> for Stride7 {
     type Output = Self;
-    fn bitor(self, rhs: Self) -> Self::Output {
-        Self(self.0 | rhs.0, self.1 | rhs.1)
+    fn bitand(mut self, rhs: Self) -> Self::Output {
+        for (w, r) in self.0.iter_mut().zip(rhs.0.iter()) {
+            *w &= r;
+        }
+        self
     }
 }
 
-impl std::ops::BitAnd<Self> for Stride7 {
+impl std::ops::Not for Stride7 {
     type Output = Self;
-    fn bitand(self, rhs: Self) -> Self::Output
-    where
-        Self: Eq,
-    {
-        Self(self.0 & rhs.0, self.1 & rhs.1)
+    fn not(mut self) -> Self::Output {
+        for w in self.0.iter_mut() {
+            *w = !*w;
+        }
+        self
     }
 }
 
 impl Stride for Stride8 {
     type PtrSize = U256;
-    const BITS: u8 = 255; // bogus
+    const BITS: u16 = 512;
     const STRIDE_LEN: u8 = 8;
 
     fn get_bit_pos(nibble: u32, len: u8) -> Self {
-        match 512 - ((1 << len) - 1) as u16 - nibble as u16 - 1 {
-            n if n < 128 => {
-                // println!("n {}", n);
-                U512(0, 0, 0, 1 << n)
-            }
-            n if n < 256 => U512(0, 0, 1 << (n as u16 - 128), 0),
-            n if n < 384 => {
-                U512(0, 1 << (n as u16 - 256), 0, 0)
-            }
-            n => U512(1 << (n as u16 - 384), 0, 0, 0),
-        }
+        U512(words_bit(
+            <Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1,
+        ))
     }
 
     fn get_pfx_index(bitmap: Self, nibble: u32, len: u8) -> usize {
-        let n = 512 - ((1 << len) - 1) as u16 - nibble as u16 - 1;
-        match n {
-            // if we move less than 128 bits to the right,
-            // all of bitmap.2 and a part of bitmap.3 will be used for counting zeros
-            // ex.
-            // ...1011_1010... >> 2 => ...0010_111010...
-            //    ____ ====                 -- --====
-            n if n < 128 => {
-                bitmap.0.count_ones() as usize
-                    + bitmap.1.count_ones() as usize
-                    + bitmap.2.count_ones() as usize
-                    + (bitmap.3 >> n).count_ones() as usize
-                    - 1
-            }
-
-            n if n < 256 => {
-                bitmap.0.count_ones() as usize
-                    + bitmap.1.count_ones() as usize
-                    + (bitmap.2 >> n).count_ones() as usize
-                    - 1
-            }
-
-            n if n < 384 => {
-                bitmap.0.count_ones() as usize + (bitmap.1 >> n).count_ones() as usize - 1
-            }
-
-            // if we move more than 384 bits to the right,
-            // all of bitmap.[1,2,3] will be shifted out of sight,
-            // so we only have to count bitmap.0 zeroes than (after) shifting of course).
-            n => (bitmap.0 >> n).count_ones() as usize - 1,
-        }
+        words_count_ones_from(
+            &bitmap.0,
+            <Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1,
+        ) - 1
     }
 
     fn get_ptr_index(bitmap: Self::PtrSize, nibble: u32) -> usize {
-        let n = (512 >> 1) - nibble as u16 - 1;
-        match n {
-            // if we move less than 256 bits to the right,
-            // all of bitmap.0 and a part of bitmap.1 will be used for counting zeros
-            // ex.
-            // ...1011_1010... >> 2 => ...0010_111010...
-            //    ____ ====                 -- --====
-            n if n < 128 => {
-                bitmap.0.count_ones() as usize + (bitmap.1 >> n).count_ones() as usize - 1
-            }
-            // if we move more than 256 bits to the right,
-            // all of bitmap.1 wil be shifted out of sight,
-            // so we only have to count bitmap.0 zeroes than (after) shifting of course).
-            n => (bitmap.0 >> n).count_ones() as usize - 1,
-        }
+        words_count_ones_from(
+            &bitmap.0,
+            (<Self as Stride>::BITS >> 1) - nibble as u16 - 1,
+        ) - 1
     }
 
     fn into_stride_size(bitmap: Self::PtrSize) -> Self {
-        // One bit needs to move into the self.0 u128,
-        // since the last bit of the *whole* bitmap isn't used.
-        U512(
-            0,
-            bitmap.0 >> 127,
-            (bitmap.0 << 1) | (bitmap.1 >> 127),
-            bitmap.1 << 1,
-        )
+        let mut words = [0; 8];
+        words[4..].copy_from_slice(&bitmap.0);
+        U512(words_shl1(words))
     }
 
     fn into_ptrbitarr_size(bitmap: Self) -> Self::PtrSize {
-        // TODO expand:
-        // self.ptrbitarr =
-        // S::into_ptrbitarr_size(bit_pos | S::into_stride_size(self.ptrbitarr));
-        U256(
-            (bitmap.1 << 127 | bitmap.2 >> 1) as u128,
-            (bitmap.2 << 127 | bitmap.3 >> 1) as u128,
-        )
+        let words = words_shr1(bitmap.0);
+        U256([words[4], words[5], words[6], words[7]])
     }
 
     #[inline]
     fn zero() -> Self {
-        U512(0, 0, 0, 0)
+        U512([0; 8])
     }
 
     #[inline]
     fn one() -> Self {
-        U512(0, 0, 0, 1)
+        U512([0, 0, 0, 0, 0, 0, 0, 1])
     }
 
     #[inline]
     fn leading_zeros(self) -> u32 {
-        let mut lz = self.0.leading_zeros();
-        if lz == 128 {
-            lz += self.1.leading_zeros();
-            if lz == 256 {
-                lz += self.2.leading_zeros();
-                if lz == 384 {
-                    lz += self.3.leading_zeros();
-                }
-            }
-        }
-        lz
-    }
-}
-
-impl PartialEq for Stride8 {
-    fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0 && self.1 == other.1 && self.2 == other.2 && self.3 == other.3
+        words_leading_zeros(&self.0)
     }
 }
 
-impl Eq for Stride8 {}
-
-impl Binary for Stride8 {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        std::fmt::Binary::fmt(&self, f)
+impl std::ops::BitOr<Self> for Stride8 {
+    type Output = Self;
+    fn bitor(mut self, rhs: Self) -> Self::Output {
+        for (w, r) in self.0.iter_mut().zip(rhs.0.iter()) {
+            *w |= r;
+        }
+        self
     }
 }
 
-impl std::ops::BitOr<Self> for Stride8 {
+impl std::ops::BitAnd<Self> for Stride8 {
     type Output = Self;
-    fn bitor(self, rhs: Self) -> Self::Output {
-        Self(
-            self.0 | rhs.0,
-            self.1 | rhs.1,
-            self.2 | rhs.2,
-            self.3 | rhs.3,
-        )
+    fn bitand(mut self, rhs: Self) -> Self::Output {
+        for (w, r) in self.0.iter_mut().zip(rhs.0.iter()) {
+            *w &= r;
+        }
+        self
     }
 }
 
-impl std::ops::BitAnd<Self> for Stride8 {
+impl std::ops::Not for Stride8 {
     type Output = Self;
-    fn bitand(self, rhs: Self) -> Self::Output
-    where
-        Self: Eq,
-    {
-        Self(
-            self.0 & rhs.0,
-            self.1 & rhs.1,
-            self.2 & rhs.2,
-            self.3 & rhs.3,
-        )
+    fn not(mut self) -> Self::Output {
+        for w in self.0.iter_mut() {
+            *w = !*w;
+        }
+        self
     }
 }
 
 #[derive(Debug)]
-pub enum SizedStrideNode<'a, AF, T>
+pub enum SizedStrideNode<AF, T>
 where
     T: Debug,
     AF: AddressFamily + Debug + PrimInt,
 {
-    Stride3(TreeBitMapNode<'a, AF, T, Stride3>),
-    Stride4(TreeBitMapNode<'a, AF, T, Stride4>),
-    Stride5(TreeBitMapNode<'a, AF, T, Stride5>),
-    Stride6(TreeBitMapNode<'a, AF, T, Stride6>),
-    Stride7(TreeBitMapNode<'a, AF, T, Stride7>),
-    Stride8(TreeBitMapNode<'a, AF, T, Stride8>),
+    Stride3(TreeBitMapNode<AF, T, Stride3>),
+    Stride4(TreeBitMapNode<AF, T, Stride4>),
+    Stride5(TreeBitMapNode<AF, T, Stride5>),
+    Stride6(TreeBitMapNode<AF, T, Stride6>),
+    Stride7(TreeBitMapNode<AF, T, Stride7>),
+    Stride8(TreeBitMapNode<AF, T, Stride8>),
 }
 
-pub struct TreeBitMapNode<'a, AF, T, S>
+pub struct TreeBitMapNode<AF, T, S>
 where
     T: Debug,
     AF: AddressFamily + Debug + PrimInt,
     S: Stride,
     <S as Stride>::PtrSize: Debug + Binary + Copy,
 {
-    bit_id: u16,
     ptrbitarr: <S as Stride>::PtrSize,
     pfxbitarr: S,
-    pfx_vec: Vec<&'a Prefix<AF, T>>,
-    ptr_vec: Vec<SizedStrideNode<'a, AF, T>>,
-}
-
-impl<'a, AF, T> Eq for SizedStrideNode<'a, AF, T>
-where
-    T: Debug,
-    AF: AddressFamily + Debug + PrimInt,
-{
-}
-
-impl<'a, AF, T> Ord for SizedStrideNode<'a, AF, T>
-where
-    T: Debug,
-    AF: AddressFamily + Debug + PrimInt,
-{
-    fn cmp(&self, other: &Self) -> Ordering {
-        match self {
-            SizedStrideNode::Stride3(nn) => {
-                if let SizedStrideNode::Stride3(mm) = other {
-                    nn.ptrbitarr.cmp(&mm.ptrbitarr)
-                } else {
-                    0.cmp(&1)
-                }
-            }
-            SizedStrideNode::Stride4(nn) => {
-                if let SizedStrideNode::Stride4(mm) = other {
-                    nn.ptrbitarr.cmp(&mm.ptrbitarr)
-                } else {
-                    0.cmp(&1)
-                }
-            }
-            SizedStrideNode::Stride5(nn) => {
-                if let SizedStrideNode::Stride5(mm) = other {
-                    nn.ptrbitarr.cmp(&mm.ptrbitarr)
-                } else {
-                    0.cmp(&1)
-                }
-            }
-            SizedStrideNode::Stride6(nn) => {
-                if let SizedStrideNode::Stride6(mm) = other {
-                    nn.ptrbitarr.cmp(&mm.ptrbitarr)
-                } else {
-                    0.cmp(&1)
-                }
-            }
-            SizedStrideNode::Stride7(nn) => {
-                if let SizedStrideNode::Stride7(mm) = other {
-                    nn.ptrbitarr.cmp(&mm.ptrbitarr)
-                } else {
-                    0.cmp(&1)
-                }
-            }
-            SizedStrideNode::Stride8(nn) => {
-                if let SizedStrideNode::Stride8(mm) = other {
-                    nn.ptrbitarr.cmp(&mm.ptrbitarr)
-                } else {
-                    0.cmp(&1)
-                }
-            }
-        }
-    }
+    pfx_vec: Vec<Prefix<AF, T>>,
+    ptr_vec: Vec<SizedStrideNode<AF, T>>,
 }
 
-impl<'a, AF, T> PartialOrd for SizedStrideNode<'a, AF, T>
-where
-    T: Debug,
-    AF: AddressFamily + Debug + PrimInt,
-{
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        match self {
-            SizedStrideNode::Stride3(nn) => {
-                if let SizedStrideNode::Stride3(mm) = other {
-                    Some(nn.bit_id.cmp(&mm.bit_id))
-                } else {
-                    Some(0.cmp(&1))
-                }
-            }
-            SizedStrideNode::Stride4(nn) => {
-                if let SizedStrideNode::Stride4(mm) = other {
-                    Some(nn.bit_id.cmp(&mm.bit_id))
-                } else {
-                    Some(0.cmp(&1))
-                }
-            }
-            SizedStrideNode::Stride5(nn) => {
-                if let SizedStrideNode::Stride5(mm) = other {
-                    Some(nn.bit_id.cmp(&mm.bit_id))
-                } else {
-                    Some(0.cmp(&10))
-                }
-            }
-            SizedStrideNode::Stride6(nn) => {
-                if let SizedStrideNode::Stride6(mm) = other {
-                    Some(nn.bit_id.cmp(&mm.bit_id))
-                } else {
-                    Some(0.cmp(&10))
-                }
-            }
-            SizedStrideNode::Stride7(nn) => {
-                if let SizedStrideNode::Stride7(mm) = other {
-                    Some(nn.bit_id.cmp(&mm.bit_id))
-                } else {
-                    Some(0.cmp(&10))
-                }
-            }
-            SizedStrideNode::Stride8(nn) => {
-                if let SizedStrideNode::Stride8(mm) = other {
-                    Some(nn.bit_id.cmp(&mm.bit_id))
-                } else {
-                    Some(0.cmp(&10))
-                }
-            }
-        }
-    }
-}
-
-impl<'a, AF, T> PartialEq for SizedStrideNode<'a, AF, T>
-where
-    T: Debug,
-    AF: AddressFamily + Debug + PrimInt,
-{
-    fn eq(&self, other: &Self) -> bool {
-        match self {
-            SizedStrideNode::Stride3(n) => {
-                if let SizedStrideNode::Stride3(m) = other {
-                    n.bit_id == m.bit_id
-                } else {
-                    true
-                }
-            }
-            SizedStrideNode::Stride4(n) => {
-                if let SizedStrideNode::Stride4(m) = other {
-                    n.bit_id == m.bit_id
-                } else {
-                    true
-                }
-            }
-            SizedStrideNode::Stride5(n) => {
-                if let SizedStrideNode::Stride5(m) = other {
-                    n.bit_id == m.bit_id
-                } else {
-                    true
-                }
-            }
-            SizedStrideNode::Stride6(n) => {
-                if let SizedStrideNode::Stride6(m) = other {
-                    n.bit_id == m.bit_id
-                } else {
-                    true
-                }
-            }
-            SizedStrideNode::Stride7(n) => {
-                if let SizedStrideNode::Stride7(m) = other {
-                    n.bit_id == m.bit_id
-                } else {
-                    true
-                }
-            }
-            SizedStrideNode::Stride8(n) => {
-                if let SizedStrideNode::Stride8(m) = other {
-                    n.bit_id == m.bit_id
-                } else {
-                    true
-                }
-            }
-        }
-    }
-}
-
-impl<'a, AF, T, S> Debug for TreeBitMapNode<'a, AF, T, S>
+impl<AF, T, S> Debug for TreeBitMapNode<AF, T, S>
 where
     T: Debug,
     AF: AddressFamily + Debug + PrimInt,
@@ -793,7 +591,6 @@ where
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("TreeBitMapNode")
-            .field("id", &self.bit_id)
             .field("ptrbitarr", &self.ptrbitarr)
             .field("pfxbitarr", &self.pfxbitarr)
             .field("ptr_vec", &self.ptr_vec)
@@ -801,11 +598,14 @@ where
     }
 }
 
-impl<'a, AF, T, S> TreeBitMapNode<'a, AF, T, S>
+impl<AF, T, S> TreeBitMapNode<AF, T, S>
 where
     T: Debug,
     AF: AddressFamily + Debug + PrimInt,
-    S: Stride + std::ops::BitAnd<Output = S> + std::ops::BitOr<Output = S>,
+    S: Stride
+        + std::ops::BitAnd<Output = S>
+        + std::ops::BitOr<Output = S>
+        + std::ops::Not<Output = S>,
     <S as Stride>::PtrSize: Debug + Binary + Copy,
 {
     // Inspects the stride (nibble, nibble_len) to see it there's
@@ -813,15 +613,17 @@ where
     // Returns
     // - A pointer to the child node if it exists.
     // - A pointer to the newly created child node if it didn't exist.
-    // - None if this is the last stride.
+    // - None if this is the last stride. The prefix is taken out of `pfx`
+    //   and stored in this node. If it existed already, it is swapped with
+    //   the stored one, so that `pfx` holds the old prefix.
     fn traverse(
         self: &mut Self,
         nibble: u32,
         nibble_len: u8,
-        pfx: &'a Prefix<AF, T>,
+        pfx: &mut Option<Prefix<AF, T>>,
         next_stride: Option<&&u8>,
         is_last_stride: bool,
-    ) -> (Option<(&mut SizedStrideNode<'a, AF, T>, bool)>, bool) {
+    ) -> (Option<(&mut SizedStrideNode<AF, T>, bool)>, bool) {
         let bit_pos = S::get_bit_pos(nibble, nibble_len);
         let mut has_created_node = false;
         let mut has_created_pfx = false;
@@ -848,7 +650,6 @@ where
                 match next_stride.unwrap() {
                     3_u8 => {
                         new_node = SizedStrideNode::Stride3(TreeBitMapNode {
-                            bit_id: bit_pos.leading_zeros() as u16,
                             ptrbitarr: <Stride3 as Stride>::PtrSize::zero(),
                             pfxbitarr: Stride3::zero(),
                             pfx_vec: vec![],
@@ -857,7 +658,6 @@ where
                     }
                     4_u8 => {
                         new_node = SizedStrideNode::Stride4(TreeBitMapNode {
-                            bit_id: bit_pos.leading_zeros() as u16,
                             ptrbitarr: <Stride4 as Stride>::PtrSize::zero(),
                             pfxbitarr: Stride4::zero(),
                             pfx_vec: vec![],
@@ -866,7 +666,6 @@ where
                     }
                     5_u8 => {
                         new_node = SizedStrideNode::Stride5(TreeBitMapNode {
-                            bit_id: bit_pos.leading_zeros() as u16,
                             ptrbitarr: <Stride5 as Stride>::PtrSize::zero(),
                             pfxbitarr: Stride5::zero(),
                             pfx_vec: vec![],
@@ -875,7 +674,6 @@ where
                     }
                     6_u8 => {
                         new_node = SizedStrideNode::Stride6(TreeBitMapNode {
-                            bit_id: bit_pos.leading_zeros() as u16,
                             ptrbitarr: <Stride6 as Stride>::PtrSize::zero(),
                             pfxbitarr: Stride6::zero(),
                             pfx_vec: vec![],
@@ -884,18 +682,16 @@ where
                     }
                     7_u8 => {
                         new_node = SizedStrideNode::Stride7(TreeBitMapNode {
-                            bit_id: bit_pos.leading_zeros() as u16,
                             ptrbitarr: 0_u128,
-                            pfxbitarr: U256(0_u128, 0_u128),
+                            pfxbitarr: U256([0; 4]),
                             pfx_vec: vec![],
                             ptr_vec: vec![],
                         });
                     }
                     8_u8 => {
                         new_node = SizedStrideNode::Stride8(TreeBitMapNode {
-                            bit_id: bit_pos.leading_zeros() as u16,
-                            ptrbitarr: U256(0_u128, 0_u128),
-                            pfxbitarr: U512(0_u128, 0_u128, 0_u128, 0_u128),
+                            ptrbitarr: U256([0; 4]),
+                            pfxbitarr: U512([0; 8]),
                             pfx_vec: vec![],
                             ptr_vec: vec![],
                         });
@@ -905,8 +701,10 @@ where
                     }
                 };
 
-                self.ptr_vec.push(new_node);
-                self.ptr_vec.sort();
+                // The bit is set already, so its index is where the node
+                // goes.
+                let index = S::get_ptr_index(self.ptrbitarr, nibble);
+                self.ptr_vec.insert(index, new_node);
                 has_created_node = true;
             }
         } else {
@@ -914,9 +712,12 @@ where
             // and only if it doesn't exist already
             if self.pfxbitarr & bit_pos == <S as std::ops::BitAnd>::Output::zero() {
                 self.pfxbitarr = bit_pos | self.pfxbitarr;
-                self.pfx_vec.push(pfx);
+                let index = S::get_pfx_index(self.pfxbitarr, nibble, nibble_len);
+                self.pfx_vec.insert(index, pfx.take().unwrap());
                 has_created_pfx = true;
-                self.pfx_vec.sort();
+            } else {
+                let index = S::get_pfx_index(self.pfxbitarr, nibble, nibble_len);
+                std::mem::swap(&mut self.pfx_vec[index], pfx.as_mut().unwrap());
             }
             return (None, has_created_pfx);
         }
@@ -930,17 +731,96 @@ where
         )
     }
 
+    // Clears the prefix bit for (nibble, nibble_len) and takes the
+    // prefix out of the pfx_vec. Returns None if the bit wasn't set.
+    fn remove_prefix(&mut self, nibble: u32, nibble_len: u8) -> Option<Prefix<AF, T>> {
+        let bit_pos = S::get_bit_pos(nibble, nibble_len);
+        if self.pfxbitarr & bit_pos == S::zero() {
+            return None;
+        }
+        let index = S::get_pfx_index(self.pfxbitarr, nibble, nibble_len);
+        self.pfxbitarr = self.pfxbitarr & !bit_pos;
+        Some(self.pfx_vec.remove(index))
+    }
+
+    // Returns the prefix for (nibble, nibble_len), if its bit is set.
+    fn prefix_at(&self, nibble: u32, nibble_len: u8) -> Option<&Prefix<AF, T>> {
+        if self.pfxbitarr & S::get_bit_pos(nibble, nibble_len) == S::zero() {
+            return None;
+        }
+        Some(&self.pfx_vec[S::get_pfx_index(self.pfxbitarr, nibble, nibble_len)])
+    }
+
+    fn prefix_at_mut(&mut self, nibble: u32, nibble_len: u8) -> Option<&mut Prefix<AF, T>> {
+        if self.pfxbitarr & S::get_bit_pos(nibble, nibble_len) == S::zero() {
+            return None;
+        }
+        Some(&mut self.pfx_vec[S::get_pfx_index(self.pfxbitarr, nibble, nibble_len)])
+    }
+
+    // Collects the prefix at (nibble, nibble_len) and everything below it,
+    // in this node and in its child nodes, in canonical order. The bits of
+    // pfxbitarr are visited as if this node were a binary trie: first the
+    // prefix itself, then the half with the next bit 0, then the half with
+    // the next bit 1. At the full nibble length the child node for that
+    // nibble takes over.
+    fn collect_prefixes<'b>(
+        &'b self,
+        nibble: u32,
+        nibble_len: u8,
+        pfxs: &mut Vec<&'b Prefix<AF, T>>,
+    ) {
+        if let Some(pfx) = self.prefix_at(nibble, nibble_len) {
+            pfxs.push(pfx);
+        }
+        self.more_specifics(nibble, nibble_len, pfxs);
+    }
+
+    // Like `collect_prefixes`, but leaves out the prefix at
+    // (nibble, nibble_len) itself.
+    fn more_specifics<'b>(&'b self, nibble: u32, nibble_len: u8, pfxs: &mut Vec<&'b Prefix<AF, T>>) {
+        if nibble_len < S::STRIDE_LEN {
+            self.collect_prefixes(nibble << 1, nibble_len + 1, pfxs);
+            self.collect_prefixes((nibble << 1) + 1, nibble_len + 1, pfxs);
+        } else if let Some(child) = self.child(nibble) {
+            TreeBitMap::collect_prefixes(child, pfxs);
+        }
+    }
+
+    // Returns the child node for a full-length nibble, if it exists.
+    fn child(&self, nibble: u32) -> Option<&SizedStrideNode<AF, T>> {
+        let bit_pos = S::get_bit_pos(nibble, S::STRIDE_LEN);
+        if (S::into_stride_size(self.ptrbitarr) & bit_pos) == S::zero() {
+            return None;
+        }
+        Some(&self.ptr_vec[S::get_ptr_index(self.ptrbitarr, nibble)])
+    }
+
+    fn child_mut(&mut self, nibble: u32) -> Option<&mut SizedStrideNode<AF, T>> {
+        let bit_pos = S::get_bit_pos(nibble, S::STRIDE_LEN);
+        if (S::into_stride_size(self.ptrbitarr) & bit_pos) == S::zero() {
+            return None;
+        }
+        Some(&mut self.ptr_vec[S::get_ptr_index(self.ptrbitarr, nibble)])
+    }
+
     #[inline]
     fn search<'b>(
-        self: &Self,
+        self: &'b Self,
         search_pfx: &Prefix<AF, NoMeta>,
         mut nibble: u32,
         nibble_len: u8,
         start_bit: u8,
-        found_pfx: &'b mut Vec<&'a Prefix<AF, T>>,
-    ) -> Option<&SizedStrideNode<'a, AF, T>> {
+        found_pfx: &mut Vec<&'b Prefix<AF, T>>,
+    ) -> Option<&'b SizedStrideNode<AF, T>> {
         let mut bit_pos = S::get_bit_pos(nibble, nibble_len);
 
+        // The bit for length 0 is only ever set in the root node, for the
+        // default route, which matches everything.
+        if self.pfxbitarr & S::get_bit_pos(0, 0) > S::zero() {
+            found_pfx.push(&self.pfx_vec[S::get_pfx_index(self.pfxbitarr, 0, 0)]);
+        }
+
         for n_l in 1..(nibble_len + 1) {
             // Move the bit in the right position.
             nibble = AddressFamily::get_nibble(search_pfx.net, start_bit, n_l);
@@ -951,7 +831,7 @@ where
 
             // Check it there's an prefix matching in this bitmap for this nibble
             if self.pfxbitarr & bit_pos > S::zero() {
-                found_pfx.push(self.pfx_vec[S::get_pfx_index(self.pfxbitarr, nibble, n_l)]);
+                found_pfx.push(&self.pfx_vec[S::get_pfx_index(self.pfxbitarr, nibble, n_l)]);
                 // println!("vec: {:?}", self.pfx_vec);
                 // println!("found: {:?}", found_pfx);
             }
@@ -963,7 +843,9 @@ where
         // Check if this the last stride, or if they're no more children to go to,
         // if so return what we found up until now.
         // let SizedStrideNode::Stride4(current_node) = node;
-        if search_pfx.len < start_bit
+        // The prefix ending exactly at the end of this stride counts as the
+        // last stride too, the child nodes only hold more-specifics.
+        if search_pfx.len <= start_bit + nibble_len
             || (S::into_stride_size(self.ptrbitarr) & bit_pos)
                 == <S as std::ops::BitAnd>::Output::zero()
         {
@@ -974,34 +856,48 @@ where
     }
 }
 
-pub struct TreeBitMap<'a, AF, T>
+pub struct TreeBitMap<AF, T>
 where
     T: Debug,
     AF: AddressFamily + Debug + PrimInt,
 {
     pub strides: Vec<u8>,
-    root: SizedStrideNode<'a, AF, T>,
+    root: SizedStrideNode<AF, T>,
     pub stats: Vec<StrideStats>,
 }
 
-impl<'a, AF, T> TreeBitMap<'a, AF, T>
+impl<AF, T> TreeBitMap<AF, T>
 where
     T: Debug,
     AF: AddressFamily + Debug + PrimInt,
 {
 
-    pub fn new(strides_vec: Vec<u8>) -> TreeBitMap<'a, AF, T> {
-        // Check if the strides division makes sense
+    pub fn new(strides_vec: Vec<u8>) -> TreeBitMap<AF, T> {
+        // Check if the strides division makes sense, the stride sizes have
+        // to be valid before cycling through them, otherwise the sum might
+        // never reach the bits of the address family.
+        assert!(!strides_vec.is_empty(), "no strides given");
+        assert!(
+            strides_vec.iter().all(|s| (3..=8).contains(s)),
+            "strides {:?} contain a stride size other than 3 to 8",
+            strides_vec
+        );
         let mut strides: Vec<u8> = vec![];
         let mut strides_sum = 0;
         for s in strides_vec.iter().cycle().enumerate() {
             strides.push(s.1.to_owned());
             strides_sum += s.1;
-            if strides_sum >= AF::BITS - 1 {
+            if strides_sum >= AF::BITS {
                 break;
             }
         }
-        assert_eq!(strides.iter().fold(0, |acc, s| { acc + s }), AF::BITS);
+        assert_eq!(
+            strides_sum,
+            AF::BITS,
+            "strides {:?} don't add up to the {} bits of the address family",
+            strides,
+            AF::BITS
+        );
 
         let mut stride_stats: Vec<StrideStats> = vec![
             StrideStats::new(SizedStride::Stride3, strides.len() as u8), // 0
@@ -1012,12 +908,11 @@ where
             StrideStats::new(SizedStride::Stride8, strides.len() as u8), // 5
         ];
 
-        let node: SizedStrideNode<'a, AF, T>;
+        let node: SizedStrideNode<AF, T>;
 
         match strides[0] {
             3 => {
                 node = SizedStrideNode::Stride3(TreeBitMapNode {
-                    bit_id: 0,
                     ptrbitarr: 0,
                     pfxbitarr: 0,
                     ptr_vec: vec![],
@@ -1027,7 +922,6 @@ where
             }
             4 => {
                 node = SizedStrideNode::Stride4(TreeBitMapNode {
-                    bit_id: 0,
                     ptrbitarr: 0,
                     pfxbitarr: 0,
                     ptr_vec: vec![],
@@ -1037,7 +931,6 @@ where
             }
             5 => {
                 node = SizedStrideNode::Stride5(TreeBitMapNode {
-                    bit_id: 0,
                     ptrbitarr: 0,
                     pfxbitarr: 0,
                     ptr_vec: vec![],
@@ -1047,7 +940,6 @@ where
             }
             6 => {
                 node = SizedStrideNode::Stride6(TreeBitMapNode {
-                    bit_id: 0,
                     ptrbitarr: 0,
                     pfxbitarr: 0,
                     ptr_vec: vec![],
@@ -1057,9 +949,8 @@ where
             }
             7 => {
                 node = SizedStrideNode::Stride7(TreeBitMapNode {
-                    bit_id: 0,
                     ptrbitarr: 0,
-                    pfxbitarr: U256(0, 0),
+                    pfxbitarr: U256([0; 4]),
                     ptr_vec: vec![],
                     pfx_vec: vec![],
                 });
@@ -1067,9 +958,8 @@ where
             }
             8 => {
                 node = SizedStrideNode::Stride8(TreeBitMapNode {
-                    bit_id: 0,
-                    ptrbitarr: U256(0, 0),
-                    pfxbitarr: U512(0, 0, 0, 0),
+                    ptrbitarr: U256([0; 4]),
+                    pfxbitarr: U512([0; 8]),
                     ptr_vec: vec![],
                     pfx_vec: vec![],
                 });
@@ -1118,30 +1008,37 @@ where
     // 5 - 5 - 5 - 4 - 4 - [4] - 5
     // startpos (2 ^ nibble length) - 1 + nibble as usize
 
-    pub fn insert(&mut self, pfx: &'a Prefix<AF, T>) {
- 
+    // Stores `pfx`, replacing the prefix with the same network and length
+    // if there is one. Hands back the replaced prefix.
+    pub fn insert(&mut self, pfx: Prefix<AF, T>) -> Option<Prefix<AF, T>> {
         let mut stride_end: u8 = 0;
         let mut node = &mut self.root;
         let mut level: u8 = 0;
         let mut strides = self.strides.iter().peekable();
+        let pfx_len = pfx.len;
+        let pfx_net = pfx.net;
+        // Handed over to the node at the last stride, which leaves the
+        // replaced prefix in here, if any.
+        let mut pfx = Some(pfx);
+
         while let Some(stride) = strides.next() {
             stride_end += stride;
 
-            let nibble_len = if pfx.len < stride_end {
-                stride + pfx.len - stride_end
+            let nibble_len = if pfx_len < stride_end {
+                stride + pfx_len - stride_end
             } else {
                 *stride
             };
 
-            let nibble = AF::get_nibble(pfx.net, stride_end - stride, nibble_len);
+            let nibble = AF::get_nibble(pfx_net, stride_end - stride, nibble_len);
 
             node = match node {
                 SizedStrideNode::Stride3(current_node) => match current_node.traverse(
                     nibble,
                     nibble_len,
-                    pfx,
+                    &mut pfx,
                     strides.peek(),
-                    pfx.len <= stride_end,
+                    pfx_len <= stride_end,
                 ) {
                     (Some((n, has_created_node)), has_created_pfx) => {
                         if has_created_node {
@@ -1156,15 +1053,15 @@ where
                         if has_created_pfx {
                             self.stats[0].inc_prefix_count(level);
                         }
-                        return;
+                        return pfx;
                     }
                 },
                 SizedStrideNode::Stride4(current_node) => match current_node.traverse(
                     nibble,
                     nibble_len,
-                    pfx,
+                    &mut pfx,
                     strides.peek(),
-                    pfx.len <= stride_end,
+                    pfx_len <= stride_end,
                 ) {
                     (Some((n, has_created_node)), has_created_pfx) => {
                         if has_created_node {
@@ -1179,15 +1076,15 @@ where
                         if has_created_pfx {
                             self.stats[1].inc_prefix_count(level);
                         }
-                        return;
+                        return pfx;
                     }
                 },
                 SizedStrideNode::Stride5(current_node) => match current_node.traverse(
                     nibble,
                     nibble_len,
-                    pfx,
+                    &mut pfx,
                     strides.peek(),
-                    pfx.len <= stride_end,
+                    pfx_len <= stride_end,
                 ) {
                     (Some((n, has_created_node)), has_created_pfx) => {
                         if has_created_node {
@@ -1202,15 +1099,15 @@ where
                         if has_created_pfx {
                             self.stats[2].inc_prefix_count(level);
                         }
-                        return;
+                        return pfx;
                     }
                 },
                 SizedStrideNode::Stride6(current_node) => match current_node.traverse(
                     nibble,
                     nibble_len,
-                    pfx,
+                    &mut pfx,
                     strides.peek(),
-                    pfx.len <= stride_end,
+                    pfx_len <= stride_end,
                 ) {
                     (Some((n, has_created_node)), has_created_pfx) => {
                         if has_created_node {
@@ -1225,15 +1122,15 @@ where
                         if has_created_pfx {
                             self.stats[3].inc_prefix_count(level);
                         }
-                        return;
+                        return pfx;
                     }
                 },
                 SizedStrideNode::Stride7(current_node) => match current_node.traverse(
                     nibble,
                     nibble_len,
-                    pfx,
+                    &mut pfx,
                     strides.peek(),
-                    pfx.len <= stride_end,
+                    pfx_len <= stride_end,
                 ) {
                     (Some((n, has_created_node)), has_created_pfx) => {
                         if has_created_node {
@@ -1248,15 +1145,15 @@ where
                         if has_created_pfx {
                             self.stats[4].inc_prefix_count(level);
                         }
-                        return;
+                        return pfx;
                     }
                 },
                 SizedStrideNode::Stride8(current_node) => match current_node.traverse(
                     nibble,
                     nibble_len,
-                    pfx,
+                    &mut pfx,
                     strides.peek(),
-                    pfx.len <= stride_end,
+                    pfx_len <= stride_end,
                 ) {
                     (Some((n, has_created_node)), has_created_pfx) => {
                         if has_created_node {
@@ -1271,21 +1168,22 @@ where
                         if has_created_pfx {
                             self.stats[5].inc_prefix_count(level);
                         }
-                        return;
+                        return pfx;
                     }
                 },
             };
 
             level += 1;
         }
+        None
     }
 
     pub fn match_longest_prefix(
         &self,
         search_pfx: &Prefix<AF, NoMeta>,
-    ) -> Vec<&'a Prefix<AF, T>> {
+    ) -> Vec<&Prefix<AF, T>> {
         let mut stride_end = 0;
-        let mut found_pfx: Vec<&'a Prefix<AF, T>> = vec![];
+        let mut found_pfx: Vec<&Prefix<AF, T>> = vec![];
         let mut node = &self.root;
 
         for stride in self.strides.iter() {
@@ -1305,8 +1203,8 @@ where
             // until the value of the actual nibble length were looking for (until we reach
             // stride length for all strides that aren't the last) and see if the
             // prefix bit in that posision is set.
-            // Note that this does not search for prefixes with length 0 (which would always
-            // match).
+            // A prefix with length 0 (the default route) can only live in the root
+            // node and always matches.
             // So for matching a nibble 1010, we have to search for 1, 10, 101 and 1010 on
             // resp. position 1, 5, 12 and 25:
             //                       ↓          ↓                         ↓                                                              ↓
@@ -1414,6 +1312,314 @@ where
         }
         found_pfx
     }
+
+    // Answers a query for `search_pfx` in one go, `options` selects whether
+    // the less-specifics and more-specifics are collected as well.
+    pub fn match_prefix(
+        &self,
+        search_pfx: &Prefix<AF, NoMeta>,
+        options: &MatchOptions,
+    ) -> QueryResult<'_, AF, T> {
+        let mut less_specifics = self.match_longest_prefix(search_pfx);
+        let longest_match = less_specifics.last().copied();
+        let exact_match = longest_match.filter(|pfx| pfx.len == search_pfx.len);
+        if exact_match.is_some() {
+            less_specifics.pop();
+        }
+
+        QueryResult {
+            exact_match,
+            longest_match,
+            less_specifics: if options.include_less_specifics {
+                Some(less_specifics)
+            } else {
+                None
+            },
+            more_specifics: if options.include_more_specifics {
+                Some(self.more_specifics(search_pfx).collect())
+            } else {
+                None
+            },
+        }
+    }
+
+    // Walks down to the node at the last stride for `search_pfx` and
+    // returns it together with the nibble and nibble length of the prefix
+    // inside that node. Returns None if the path breaks off before that.
+    fn node_for_prefix(
+        &self,
+        search_pfx: &Prefix<AF, NoMeta>,
+    ) -> Option<(&SizedStrideNode<AF, T>, u32, u8)> {
+        let mut stride_end: u8 = 0;
+        let mut node = &self.root;
+
+        for stride in self.strides.iter() {
+            stride_end += stride;
+
+            let nibble_len = if search_pfx.len < stride_end {
+                stride + search_pfx.len - stride_end
+            } else {
+                *stride
+            };
+
+            let nibble = AF::get_nibble(search_pfx.net, stride_end - stride, nibble_len);
+
+            if search_pfx.len <= stride_end {
+                return Some((node, nibble, nibble_len));
+            }
+
+            node = match node {
+                SizedStrideNode::Stride3(current_node) => current_node.child(nibble)?,
+                SizedStrideNode::Stride4(current_node) => current_node.child(nibble)?,
+                SizedStrideNode::Stride5(current_node) => current_node.child(nibble)?,
+                SizedStrideNode::Stride6(current_node) => current_node.child(nibble)?,
+                SizedStrideNode::Stride7(current_node) => current_node.child(nibble)?,
+                SizedStrideNode::Stride8(current_node) => current_node.child(nibble)?,
+            };
+        }
+        None
+    }
+
+    // Looks up the prefix with exactly the network and length of
+    // `search_pfx`. Only the bit for the prefix in the node at its last
+    // stride is inspected, the strides before that are merely used to
+    // find that node.
+    pub fn get(&self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&Prefix<AF, T>> {
+        let (node, nibble, nibble_len) = self.node_for_prefix(search_pfx)?;
+        match node {
+            SizedStrideNode::Stride3(current_node) => current_node.prefix_at(nibble, nibble_len),
+            SizedStrideNode::Stride4(current_node) => current_node.prefix_at(nibble, nibble_len),
+            SizedStrideNode::Stride5(current_node) => current_node.prefix_at(nibble, nibble_len),
+            SizedStrideNode::Stride6(current_node) => current_node.prefix_at(nibble, nibble_len),
+            SizedStrideNode::Stride7(current_node) => current_node.prefix_at(nibble, nibble_len),
+            SizedStrideNode::Stride8(current_node) => current_node.prefix_at(nibble, nibble_len),
+        }
+    }
+
+    // Stores `pfx`, or merges its meta data into the stored prefix with the
+    // same network and length, see `Prefix::merge_meta`.
+    pub fn upsert<F>(&mut self, pfx: Prefix<AF, T>, merge: F)
+    where
+        F: FnOnce(&mut T, T),
+    {
+        match self.get_mut(&pfx.strip_meta()) {
+            Some(stored) => stored.merge_meta(pfx.meta, merge),
+            None => {
+                self.insert(pfx);
+            }
+        }
+    }
+
+    pub fn get_mut(&mut self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&mut Prefix<AF, T>> {
+        let mut stride_end: u8 = 0;
+        let mut node = &mut self.root;
+
+        for stride in self.strides.iter() {
+            stride_end += stride;
+
+            let nibble_len = if search_pfx.len < stride_end {
+                stride + search_pfx.len - stride_end
+            } else {
+                *stride
+            };
+
+            let nibble = AF::get_nibble(search_pfx.net, stride_end - stride, nibble_len);
+
+            if search_pfx.len <= stride_end {
+                return match node {
+                    SizedStrideNode::Stride3(current_node) => {
+                        current_node.prefix_at_mut(nibble, nibble_len)
+                    }
+                    SizedStrideNode::Stride4(current_node) => {
+                        current_node.prefix_at_mut(nibble, nibble_len)
+                    }
+                    SizedStrideNode::Stride5(current_node) => {
+                        current_node.prefix_at_mut(nibble, nibble_len)
+                    }
+                    SizedStrideNode::Stride6(current_node) => {
+                        current_node.prefix_at_mut(nibble, nibble_len)
+                    }
+                    SizedStrideNode::Stride7(current_node) => {
+                        current_node.prefix_at_mut(nibble, nibble_len)
+                    }
+                    SizedStrideNode::Stride8(current_node) => {
+                        current_node.prefix_at_mut(nibble, nibble_len)
+                    }
+                };
+            }
+
+            node = match node {
+                SizedStrideNode::Stride3(current_node) => current_node.child_mut(nibble)?,
+                SizedStrideNode::Stride4(current_node) => current_node.child_mut(nibble)?,
+                SizedStrideNode::Stride5(current_node) => current_node.child_mut(nibble)?,
+                SizedStrideNode::Stride6(current_node) => current_node.child_mut(nibble)?,
+                SizedStrideNode::Stride7(current_node) => current_node.child_mut(nibble)?,
+                SizedStrideNode::Stride8(current_node) => current_node.child_mut(nibble)?,
+            };
+        }
+        None
+    }
+
+    // Returns all the prefixes that are strictly more specific than
+    // `search_pfx`, i.e. the ones covered by it, but not `search_pfx`
+    // itself, in canonical order. Only the part of the node at the last
+    // stride for `search_pfx` below its nibble and the child nodes hanging
+    // off there are visited.
+    pub fn more_specifics(
+        &self,
+        search_pfx: &Prefix<AF, NoMeta>,
+    ) -> impl Iterator<Item = &Prefix<AF, T>> {
+        let mut pfxs = vec![];
+
+        if let Some((node, nibble, nibble_len)) = self.node_for_prefix(search_pfx) {
+            match node {
+                SizedStrideNode::Stride3(current_node) => {
+                    current_node.more_specifics(nibble, nibble_len, &mut pfxs)
+                }
+                SizedStrideNode::Stride4(current_node) => {
+                    current_node.more_specifics(nibble, nibble_len, &mut pfxs)
+                }
+                SizedStrideNode::Stride5(current_node) => {
+                    current_node.more_specifics(nibble, nibble_len, &mut pfxs)
+                }
+                SizedStrideNode::Stride6(current_node) => {
+                    current_node.more_specifics(nibble, nibble_len, &mut pfxs)
+                }
+                SizedStrideNode::Stride7(current_node) => {
+                    current_node.more_specifics(nibble, nibble_len, &mut pfxs)
+                }
+                SizedStrideNode::Stride8(current_node) => {
+                    current_node.more_specifics(nibble, nibble_len, &mut pfxs)
+                }
+            };
+        }
+        pfxs.into_iter()
+    }
+
+    // Clears the prefix bit in the node at the last stride for this prefix
+    // and takes the prefix out of that node. Nodes are never removed.
+    pub fn remove(&mut self, pfx: &Prefix<AF, NoMeta>) -> Option<Prefix<AF, T>> {
+        let mut stride_end: u8 = 0;
+        let mut node = &mut self.root;
+
+        for (level, stride) in self.strides.iter().enumerate() {
+            stride_end += stride;
+
+            let nibble_len = if pfx.len < stride_end {
+                stride + pfx.len - stride_end
+            } else {
+                *stride
+            };
+
+            let nibble = AF::get_nibble(pfx.net, stride_end - stride, nibble_len);
+
+            if pfx.len <= stride_end {
+                let (removed, stats_index) = match node {
+                    SizedStrideNode::Stride3(current_node) => {
+                        (current_node.remove_prefix(nibble, nibble_len), 0)
+                    }
+                    SizedStrideNode::Stride4(current_node) => {
+                        (current_node.remove_prefix(nibble, nibble_len), 1)
+                    }
+                    SizedStrideNode::Stride5(current_node) => {
+                        (current_node.remove_prefix(nibble, nibble_len), 2)
+                    }
+                    SizedStrideNode::Stride6(current_node) => {
+                        (current_node.remove_prefix(nibble, nibble_len), 3)
+                    }
+                    SizedStrideNode::Stride7(current_node) => {
+                        (current_node.remove_prefix(nibble, nibble_len), 4)
+                    }
+                    SizedStrideNode::Stride8(current_node) => {
+                        (current_node.remove_prefix(nibble, nibble_len), 5)
+                    }
+                };
+                if removed.is_some() {
+                    self.stats[stats_index].dec_prefix_count(level as u8);
+                }
+                return removed;
+            }
+
+            node = match node {
+                SizedStrideNode::Stride3(current_node) => current_node.child_mut(nibble)?,
+                SizedStrideNode::Stride4(current_node) => current_node.child_mut(nibble)?,
+                SizedStrideNode::Stride5(current_node) => current_node.child_mut(nibble)?,
+                SizedStrideNode::Stride6(current_node) => current_node.child_mut(nibble)?,
+                SizedStrideNode::Stride7(current_node) => current_node.child_mut(nibble)?,
+                SizedStrideNode::Stride8(current_node) => current_node.child_mut(nibble)?,
+            };
+        }
+        None
+    }
+
+    fn collect_prefixes<'b>(node: &'b SizedStrideNode<AF, T>, pfxs: &mut Vec<&'b Prefix<AF, T>>) {
+        match node {
+            SizedStrideNode::Stride3(current_node) => current_node.collect_prefixes(0, 0, pfxs),
+            SizedStrideNode::Stride4(current_node) => current_node.collect_prefixes(0, 0, pfxs),
+            SizedStrideNode::Stride5(current_node) => current_node.collect_prefixes(0, 0, pfxs),
+            SizedStrideNode::Stride6(current_node) => current_node.collect_prefixes(0, 0, pfxs),
+            SizedStrideNode::Stride7(current_node) => current_node.collect_prefixes(0, 0, pfxs),
+            SizedStrideNode::Stride8(current_node) => current_node.collect_prefixes(0, 0, pfxs),
+        }
+    }
+
+    // Iterates over all the stored prefixes in canonical order, i.e.
+    // ordered by network address first and prefix length second.
+    pub fn iter(&self) -> std::vec::IntoIter<&Prefix<AF, T>> {
+        let mut pfxs = vec![];
+        Self::collect_prefixes(&self.root, &mut pfxs);
+        pfxs.into_iter()
+    }
+}
+
+impl<AF, T> PrefixStore<AF, T> for TreeBitMap<AF, T>
+where
+    T: Debug,
+    AF: AddressFamily + Debug + PrimInt,
+{
+    fn insert(&mut self, pfx: Prefix<AF, T>) -> Option<Prefix<AF, T>> {
+        TreeBitMap::insert(self, pfx)
+    }
+
+    fn upsert<F>(&mut self, pfx: Prefix<AF, T>, merge: F)
+    where
+        F: FnOnce(&mut T, T),
+    {
+        TreeBitMap::upsert(self, pfx, merge)
+    }
+
+    fn get(&self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&Prefix<AF, T>> {
+        TreeBitMap::get(self, search_pfx)
+    }
+
+    fn get_mut(&mut self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&mut Prefix<AF, T>> {
+        TreeBitMap::get_mut(self, search_pfx)
+    }
+
+    fn match_longest_prefix(&self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&Prefix<AF, T>> {
+        TreeBitMap::match_longest_prefix(self, search_pfx).pop()
+    }
+
+    fn remove(&mut self, pfx: &Prefix<AF, NoMeta>) -> Option<Prefix<AF, T>> {
+        TreeBitMap::remove(self, pfx)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Prefix<AF, T>> + 'a> {
+        Box::new(TreeBitMap::iter(self))
+    }
+}
+
+impl<'a, AF, T> IntoIterator for &'a TreeBitMap<AF, T>
+where
+    T: Debug,
+    AF: AddressFamily + Debug + PrimInt,
+{
+    type Item = &'a Prefix<AF, T>;
+    type IntoIter = std::vec::IntoIter<&'a Prefix<AF, T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -1514,6 +1720,10 @@ impl StrideStats {
     fn inc_prefix_count(&mut self, depth_level: u8) {
         self.prefixes_num[depth_level as usize].count += 1;
     }
+
+    fn dec_prefix_count(&mut self, depth_level: u8) {
+        self.prefixes_num[depth_level as usize].count -= 1;
+    }
 }
 
 impl Debug for StrideStats {
@@ -0,0 +1,281 @@
+use crate::common::{AddressFamily, NoMeta, Prefix};
+use crate::treebitmap::Stride;
+use num::PrimInt;
+use std::fmt::{Binary, Debug};
+use std::marker::PhantomData;
+
+// A TreeBitMap like `treebitmap::TreeBitMap`, with prefixes stored in the
+// nodes, but with the strides fixed at compile time. The stride layout is
+// a type-level list: `Level<Stride4, Level<Stride4, ... End>>`, most easily
+// written with the `stride_layout!` macro, e.g. `stride_layout![4, 4, 8, 8,
+// 8]`. Every level of the tree has its own node type, whose children are
+// nodes of the next level, so there is no SizedStrideNode to match on at
+// every level: insert and match_longest_prefix get monomorphised into one
+// function per layout and the levels can be inlined into each other.
+//
+// This only covers building the tree and looking prefixes up, for
+// benchmarking the layouts against the runtime-configured TreeBitMap.
+
+// A level in the layout with a node of stride S, whose child nodes are laid
+// out by Next.
+pub struct Level<S, Next>(PhantomData<(S, Next)>);
+
+// Terminates the layout, there are no nodes below the last level.
+pub struct End;
+
+pub trait StrideLayout<AF, T>
+where
+    T: Debug,
+    AF: AddressFamily + Debug + PrimInt,
+{
+    type Node;
+    // The number of bits covered by this level and all the levels below it.
+    const BITS: u8;
+
+    fn new_node() -> Self::Node;
+
+    fn strides(strides: &mut Vec<u8>);
+
+    // Stores `pfx` in `node` or below it, returns the prefix it replaced,
+    // if any.
+    fn insert(node: &mut Self::Node, pfx: Prefix<AF, T>) -> Option<Prefix<AF, T>>;
+
+    // Pushes all the prefixes in `node` and below it that cover
+    // `search_pfx` on `found_pfx`, shortest first.
+    fn search<'a>(
+        node: &'a Self::Node,
+        search_pfx: &Prefix<AF, NoMeta>,
+        found_pfx: &mut Vec<&'a Prefix<AF, T>>,
+    );
+}
+
+pub struct StaticNode<AF, T, S, C>
+where
+    T: Debug,
+    AF: AddressFamily + Debug + PrimInt,
+    S: Stride,
+{
+    ptrbitarr: <S as Stride>::PtrSize,
+    pfxbitarr: S,
+    pfx_vec: Vec<Prefix<AF, T>>,
+    ptr_vec: Vec<C>,
+}
+
+impl<AF, T, S, Next> StrideLayout<AF, T> for Level<S, Next>
+where
+    T: Debug,
+    AF: AddressFamily + Debug + PrimInt,
+    S: Stride
+        + std::ops::BitAnd<Output = S>
+        + std::ops::BitOr<Output = S>
+        + std::ops::Not<Output = S>,
+    <S as Stride>::PtrSize: Debug + Binary + Copy,
+    Next: StrideLayout<AF, T>,
+{
+    type Node = StaticNode<AF, T, S, Next::Node>;
+    const BITS: u8 = S::STRIDE_LEN + Next::BITS;
+
+    fn new_node() -> Self::Node {
+        StaticNode {
+            ptrbitarr: S::into_ptrbitarr_size(S::zero()),
+            pfxbitarr: S::zero(),
+            pfx_vec: vec![],
+            ptr_vec: vec![],
+        }
+    }
+
+    fn strides(strides: &mut Vec<u8>) {
+        strides.push(S::STRIDE_LEN);
+        Next::strides(strides);
+    }
+
+    #[inline]
+    fn insert(node: &mut Self::Node, pfx: Prefix<AF, T>) -> Option<Prefix<AF, T>> {
+        let start_bit = AF::BITS - Self::BITS;
+
+        // The prefix ends in this node, the child nodes only hold the
+        // more-specifics.
+        if pfx.len <= start_bit + S::STRIDE_LEN {
+            let nibble_len = pfx.len - start_bit;
+            let nibble = AF::get_nibble(pfx.net, start_bit, nibble_len);
+            let bit_pos = S::get_bit_pos(nibble, nibble_len);
+            if node.pfxbitarr & bit_pos == S::zero() {
+                node.pfxbitarr = node.pfxbitarr | bit_pos;
+                let index = S::get_pfx_index(node.pfxbitarr, nibble, nibble_len);
+                node.pfx_vec.insert(index, pfx);
+                return None;
+            }
+            let index = S::get_pfx_index(node.pfxbitarr, nibble, nibble_len);
+            return Some(std::mem::replace(&mut node.pfx_vec[index], pfx));
+        }
+
+        let nibble = AF::get_nibble(pfx.net, start_bit, S::STRIDE_LEN);
+        let bit_pos = S::get_bit_pos(nibble, S::STRIDE_LEN);
+        if S::into_stride_size(node.ptrbitarr) & bit_pos == S::zero() {
+            node.ptrbitarr = S::into_ptrbitarr_size(S::into_stride_size(node.ptrbitarr) | bit_pos);
+            let index = S::get_ptr_index(node.ptrbitarr, nibble);
+            node.ptr_vec.insert(index, Next::new_node());
+        }
+        let index = S::get_ptr_index(node.ptrbitarr, nibble);
+        Next::insert(&mut node.ptr_vec[index], pfx)
+    }
+
+    // See `TreeBitMapNode::search` in `treebitmap`, this is the same walk
+    // over the nibble lengths in this node, followed by the descent into
+    // the child node.
+    #[inline]
+    fn search<'a>(
+        node: &'a Self::Node,
+        search_pfx: &Prefix<AF, NoMeta>,
+        found_pfx: &mut Vec<&'a Prefix<AF, T>>,
+    ) {
+        let start_bit = AF::BITS - Self::BITS;
+        let stride_end = start_bit + S::STRIDE_LEN;
+        let nibble_len = if search_pfx.len < stride_end {
+            search_pfx.len - start_bit
+        } else {
+            S::STRIDE_LEN
+        };
+
+        // The default route can only live in the root node.
+        if start_bit == 0 && node.pfxbitarr & S::get_bit_pos(0, 0) != S::zero() {
+            found_pfx.push(&node.pfx_vec[S::get_pfx_index(node.pfxbitarr, 0, 0)]);
+        }
+
+        let mut nibble = 0;
+        let mut bit_pos = S::zero();
+        for n_l in 1..(nibble_len + 1) {
+            nibble = AF::get_nibble(search_pfx.net, start_bit, n_l);
+            bit_pos = S::get_bit_pos(nibble, n_l);
+            if node.pfxbitarr & bit_pos != S::zero() {
+                found_pfx.push(&node.pfx_vec[S::get_pfx_index(node.pfxbitarr, nibble, n_l)]);
+            }
+        }
+
+        if search_pfx.len <= stride_end
+            || S::into_stride_size(node.ptrbitarr) & bit_pos == S::zero()
+        {
+            return;
+        }
+        Next::search(
+            &node.ptr_vec[S::get_ptr_index(node.ptrbitarr, nibble)],
+            search_pfx,
+            found_pfx,
+        );
+    }
+}
+
+// The levels above `End` cover all the bits of the address family (checked
+// in `TreeBitMap::new`), so neither an insert nor a search ever gets here.
+impl<AF, T> StrideLayout<AF, T> for End
+where
+    T: Debug,
+    AF: AddressFamily + Debug + PrimInt,
+{
+    type Node = ();
+    const BITS: u8 = 0;
+
+    fn new_node() {}
+
+    fn strides(_strides: &mut Vec<u8>) {}
+
+    fn insert(_node: &mut (), _pfx: Prefix<AF, T>) -> Option<Prefix<AF, T>> {
+        unreachable!()
+    }
+
+    fn search<'a>(
+        _node: &'a (),
+        _search_pfx: &Prefix<AF, NoMeta>,
+        _found_pfx: &mut Vec<&'a Prefix<AF, T>>,
+    ) {
+        unreachable!()
+    }
+}
+
+// Builds a stride layout type from a list of stride sizes, e.g.
+// `stride_layout![3, 4, 5, 6, 5, 5, 4]` for IPv4.
+#[macro_export]
+macro_rules! stride_layout {
+    () => { $crate::treebitmap_static::End };
+    (3 $(, $s:tt)*) => {
+        $crate::treebitmap_static::Level<$crate::treebitmap::Stride3, $crate::stride_layout!($($s),*)>
+    };
+    (4 $(, $s:tt)*) => {
+        $crate::treebitmap_static::Level<$crate::treebitmap::Stride4, $crate::stride_layout!($($s),*)>
+    };
+    (5 $(, $s:tt)*) => {
+        $crate::treebitmap_static::Level<$crate::treebitmap::Stride5, $crate::stride_layout!($($s),*)>
+    };
+    (6 $(, $s:tt)*) => {
+        $crate::treebitmap_static::Level<$crate::treebitmap::Stride6, $crate::stride_layout!($($s),*)>
+    };
+    (7 $(, $s:tt)*) => {
+        $crate::treebitmap_static::Level<$crate::treebitmap::Stride7, $crate::stride_layout!($($s),*)>
+    };
+    (8 $(, $s:tt)*) => {
+        $crate::treebitmap_static::Level<$crate::treebitmap::Stride8, $crate::stride_layout!($($s),*)>
+    };
+}
+
+pub struct TreeBitMap<AF, T, L>
+where
+    T: Debug,
+    AF: AddressFamily + Debug + PrimInt,
+    L: StrideLayout<AF, T>,
+{
+    root: L::Node,
+}
+
+impl<AF, T, L> TreeBitMap<AF, T, L>
+where
+    T: Debug,
+    AF: AddressFamily + Debug + PrimInt,
+    L: StrideLayout<AF, T>,
+{
+    pub fn new() -> TreeBitMap<AF, T, L> {
+        let mut strides = vec![];
+        L::strides(&mut strides);
+        assert!(!strides.is_empty(), "the stride layout is empty");
+        assert_eq!(
+            L::BITS,
+            AF::BITS,
+            "strides {:?} don't add up to the {} bits of the address family",
+            strides,
+            AF::BITS
+        );
+        TreeBitMap {
+            root: L::new_node(),
+        }
+    }
+
+    pub fn strides(&self) -> Vec<u8> {
+        let mut strides = vec![];
+        L::strides(&mut strides);
+        strides
+    }
+
+    // Stores `pfx`, replacing the prefix with the same network and length
+    // if there is one. Hands back the replaced prefix.
+    pub fn insert(&mut self, pfx: Prefix<AF, T>) -> Option<Prefix<AF, T>> {
+        L::insert(&mut self.root, pfx)
+    }
+
+    // Returns all the prefixes that cover `search_pfx`, the longest match
+    // last, like `treebitmap::TreeBitMap::match_longest_prefix`.
+    pub fn match_longest_prefix(&self, search_pfx: &Prefix<AF, NoMeta>) -> Vec<&Prefix<AF, T>> {
+        let mut found_pfx = vec![];
+        L::search(&self.root, search_pfx, &mut found_pfx);
+        found_pfx
+    }
+}
+
+impl<AF, T, L> Default for TreeBitMap<AF, T, L>
+where
+    T: Debug,
+    AF: AddressFamily + Debug + PrimInt,
+    L: StrideLayout<AF, T>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,65 +1,135 @@
-use crate::common::{AddressFamily, NoMeta, Prefix};
-use std::cmp::Ordering;
+use crate::common::{
+    AddressFamily, MatchOptions, MultiMeta, NoMeta, Prefix, PrefixStore, QueryResult,
+};
 use std::fmt::{Binary, Debug};
+use std::marker::PhantomData;
+
+// The bitmaps for strides 7 and 8 don't fit in a primitive integer, so
+// they are arrays of u64 words, the most significant word first. Bit n of
+// the bitmap, counted from the right like in an integer, is bit n % 64 of
+// word N - 1 - n / 64.
+#[derive(Copy, Clone, Eq, PartialOrd, Ord)]
+pub struct U256([u64; 4]);
+
+#[derive(Copy, Clone, Eq, PartialOrd, Ord)]
+pub struct U512([u64; 8]);
+
+// Comparing the words one by one instead of the arrays as a whole, which
+// the compiler turns into a call to memcmp, for the `bitmap & bit_pos ==
+// zero` checks in the hot paths.
+impl PartialEq for U256 {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        words_eq(&self.0, &other.0)
+    }
+}
 
-#[derive(Copy, Clone)]
-pub struct U256(u128, u128);
+impl PartialEq for U512 {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        words_eq(&self.0, &other.0)
+    }
+}
 
 impl Debug for U256 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!(
-            "{:0128b}\n             {:0128b}",
-            self.0, self.1
-        ))
+        Binary::fmt(self, f)
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct U512(u128, u128, u128, u128);
+impl Debug for U512 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Binary::fmt(self, f)
+    }
+}
 
-pub type Stride3 = u16;
-pub type Stride4 = u32;
-pub type Stride5 = u64;
-pub type Stride6 = u128;
-pub type Stride7 = U256;
-pub type Stride8 = U512;
+impl Binary for U256 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_words(&self.0, f)
+    }
+}
 
-impl PartialOrd for U256 {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        match (self.0, &other.0) {
-            (a, b) if &a > b => Some(self.0.cmp(&other.0)),
-            _ => Some(self.1.cmp(&other.1)),
-        }
+impl Binary for U512 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_words(&self.0, f)
     }
 }
 
-impl Ord for U256 {
-    fn cmp(&self, other: &Self) -> Ordering {
-        match (self.0, &other.0) {
-            (a, b) if &a > b => self.0.cmp(&other.0),
-            _ => self.1.cmp(&other.1),
-        }
+fn fmt_words(words: &[u64], f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    for w in words {
+        write!(f, "{:064b}", w)?;
     }
+    Ok(())
 }
 
-impl PartialOrd for U512 {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        match (self.0, &other.0) {
-            (a, b) if &a > b => Some(self.0.cmp(&other.0)),
-            _ => match (self.1, &other.1) {
-                (a, b) if &a > b => Some(self.1.cmp(&other.1)),
-                _ => match (self.2, &other.2) {
-                    (a, b) if &a > b => Some(self.2.cmp(&other.2)),
-                    _ => Some(self.3.cmp(&other.3)),
-                },
-            },
+#[inline]
+fn words_eq(a: &[u64], b: &[u64]) -> bool {
+    a.iter().zip(b.iter()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// The words with only bit n set.
+#[inline]
+fn words_bit<const N: usize>(n: u16) -> [u64; N] {
+    let mut words = [0; N];
+    words[N - 1 - (n / 64) as usize] = 1 << (n % 64);
+    words
+}
+
+// The number of ones in bit n and all the bits to the left of it. That's
+// the popcount of all the words before the word holding bit n, plus the
+// popcount of that word shifted right up to bit n.
+#[inline]
+fn words_count_ones_from<const N: usize>(words: &[u64; N], n: u16) -> usize {
+    let word = N - 1 - (n / 64) as usize;
+    words[..word]
+        .iter()
+        .map(|w| w.count_ones() as usize)
+        .sum::<usize>()
+        + (words[word] >> (n % 64)).count_ones() as usize
+}
+
+#[inline]
+fn words_shl1<const N: usize>(words: [u64; N]) -> [u64; N] {
+    let mut res = [0; N];
+    for i in 0..N - 1 {
+        res[i] = words[i] << 1 | words[i + 1] >> 63;
+    }
+    res[N - 1] = words[N - 1] << 1;
+    res
+}
+
+#[inline]
+fn words_shr1<const N: usize>(words: [u64; N]) -> [u64; N] {
+    let mut res = [0; N];
+    res[0] = words[0] >> 1;
+    for i in 1..N {
+        res[i] = words[i] >> 1 | words[i - 1] << 63;
+    }
+    res
+}
+
+#[inline]
+fn words_leading_zeros(words: &[u64]) -> u32 {
+    let mut lz = 0;
+    for w in words {
+        lz += w.leading_zeros();
+        if *w != 0 {
+            break;
         }
     }
+    lz
 }
 
+pub type Stride3 = u16;
+pub type Stride4 = u32;
+pub type Stride5 = u64;
+pub type Stride6 = u128;
+pub type Stride7 = U256;
+pub type Stride8 = U512;
+
 pub trait Stride: Sized + Debug + Binary + Eq + PartialOrd + PartialEq + Copy {
     type PtrSize;
-    const BITS: u8;
+    const BITS: u16;
     const STRIDE_LEN: u8;
 
     // Get the bit position of the start of the given nibble.
@@ -151,20 +221,20 @@ impl One for u8 {
 
 impl Stride for Stride3 {
     type PtrSize = u8;
-    const BITS: u8 = 16;
+    const BITS: u16 = 16;
     const STRIDE_LEN: u8 = 3;
 
     fn get_bit_pos(nibble: u32, len: u8) -> Self {
-        1 << (<Self as Stride>::BITS - ((1 << len) - 1) as u8 - nibble as u8 - 1)
+        1 << (<Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1)
     }
 
     fn get_pfx_index(bitmap: Self, nibble: u32, len: u8) -> usize {
-        (bitmap >> ((<Self as Stride>::BITS - ((1 << len) - 1) as u8 - nibble as u8 - 1) as usize))
+        (bitmap >> ((<Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1) as usize))
             .count_ones() as usize
             - 1
     }
     fn get_ptr_index(bitmap: Self::PtrSize, nibble: u32) -> usize {
-        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u8 - 1) as usize).count_ones()
+        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u16 - 1) as usize).count_ones()
             as usize
             - 1
     }
@@ -195,21 +265,21 @@ impl Stride for Stride3 {
 
 impl Stride for Stride4 {
     type PtrSize = u16;
-    const BITS: u8 = 32;
+    const BITS: u16 = 32;
     const STRIDE_LEN: u8 = 4;
 
     fn get_bit_pos(nibble: u32, len: u8) -> u32 {
-        1 << (<Self as Stride>::BITS - ((1 << len) - 1) as u8 - nibble as u8 - 1)
+        1 << (<Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1)
     }
 
     fn get_pfx_index(bitmap: Self, nibble: u32, len: u8) -> usize {
-        (bitmap >> ((<Self as Stride>::BITS - ((1 << len) - 1) as u8 - nibble as u8 - 1) as usize))
+        (bitmap >> ((<Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1) as usize))
             .count_ones() as usize
             - 1
     }
 
     fn get_ptr_index(bitmap: u16, nibble: u32) -> usize {
-        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u8 - 1) as usize).count_ones()
+        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u16 - 1) as usize).count_ones()
             as usize
             - 1
     }
@@ -240,20 +310,20 @@ impl Stride for Stride4 {
 
 impl Stride for Stride5 {
     type PtrSize = u32;
-    const BITS: u8 = 64;
+    const BITS: u16 = 64;
     const STRIDE_LEN: u8 = 5;
 
     fn get_bit_pos(nibble: u32, len: u8) -> u64 {
-        1 << (<Self as Stride>::BITS - ((1 << len) - 1) as u8 - nibble as u8 - 1)
+        1 << (<Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1)
     }
 
     fn get_pfx_index(bitmap: Self, nibble: u32, len: u8) -> usize {
-        (bitmap >> ((<Self as Stride>::BITS - ((1 << len) - 1) as u8 - nibble as u8 - 1) as usize))
+        (bitmap >> ((<Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1) as usize))
             .count_ones() as usize
             - 1
     }
     fn get_ptr_index(bitmap: u32, nibble: u32) -> usize {
-        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u8 - 1) as usize).count_ones()
+        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u16 - 1) as usize).count_ones()
             as usize
             - 1
     }
@@ -284,20 +354,20 @@ impl Stride for Stride5 {
 
 impl Stride for Stride6 {
     type PtrSize = u64;
-    const BITS: u8 = 128;
+    const BITS: u16 = 128;
     const STRIDE_LEN: u8 = 6;
 
     fn get_bit_pos(nibble: u32, len: u8) -> u128 {
-        1 << (<Self as Stride>::BITS - ((1 << len) - 1) as u8 - nibble as u8 - 1)
+        1 << (<Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1)
     }
 
     fn get_pfx_index(bitmap: Self, nibble: u32, len: u8) -> usize {
-        (bitmap >> ((<Self as Stride>::BITS - ((1 << len) - 1) as u8 - nibble as u8 - 1) as usize))
+        (bitmap >> ((<Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1) as usize))
             .count_ones() as usize
             - 1
     }
     fn get_ptr_index(bitmap: u64, nibble: u32) -> usize {
-        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u8 - 1) as usize).count_ones()
+        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u16 - 1) as usize).count_ones()
             as usize
             - 1
     }
@@ -328,266 +398,162 @@ impl Stride for Stride6 {
 
 impl Stride for Stride7 {
     type PtrSize = u128;
-    const BITS: u8 = 255;
+    const BITS: u16 = 256;
     const STRIDE_LEN: u8 = 7;
 
     fn get_bit_pos(nibble: u32, len: u8) -> Self {
-        match 256 - ((1 << len) - 1) as u16 - nibble as u16 - 1 {
-            n if n < 128 => {
-                U256(0, 1 << n)
-            }
-            n => {
-                U256(1 << (n as u16 - 128), 0)
-            }
-        }
+        U256(words_bit(
+            <Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1,
+        ))
     }
 
     fn get_pfx_index(bitmap: Self, nibble: u32, len: u8) -> usize {
-        let n = 256 - ((1 << len) - 1) as u16 - nibble as u16 - 1;
-        match n {
-            // if we move less than 128 bits to the right,
-            // all of bitmap.0 and a part of bitmap.1 will be used for counting zeros
-            // ex.
-            // ...1011_1010... >> 2 => ...0010_111010...
-            //    ____ ====                 -- --====
-            n if n < 128 => {
-                bitmap.0.count_ones() as usize + (bitmap.1 >> n).count_ones() as usize - 1
-            }
-            // if we move more than 128 bits to the right,
-            // all of bitmap.1 wil be shifted out of sight,
-            // so we only have to count bitmap.0 zeroes than (after) shifting of course).
-            n => (bitmap.0 >> (n - 128)).count_ones() as usize - 1,
-        }
+        words_count_ones_from(
+            &bitmap.0,
+            <Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1,
+        ) - 1
     }
 
     fn get_ptr_index(bitmap: Self::PtrSize, nibble: u32) -> usize {
-        (bitmap >> ((256 >> 1) - nibble as u16 - 1) as usize).count_ones() as usize - 1
+        (bitmap >> ((<Self as Stride>::BITS >> 1) - nibble as u16 - 1) as usize).count_ones()
+            as usize
+            - 1
     }
 
     fn into_stride_size(bitmap: Self::PtrSize) -> Self {
-        // One bit needs to move into the self.0 u128,
-        // since the last bit of the *whole* bitmap isn't used.
-        U256(bitmap >> 127, bitmap << 1)
+        U256(words_shl1([0, 0, (bitmap >> 64) as u64, bitmap as u64]))
     }
 
     fn into_ptrbitarr_size(bitmap: Self) -> Self::PtrSize {
-        // TODO expand:
-        // self.ptrbitarr =
-        // S::into_ptrbitarr_size(bit_pos | S::into_stride_size(self.ptrbitarr));
-        (bitmap.0 << 127 | bitmap.1 >> 1) as u128
+        let words = words_shr1(bitmap.0);
+        (words[2] as u128) << 64 | words[3] as u128
     }
 
     #[inline]
     fn zero() -> Self {
-        U256(0, 0)
+        U256([0; 4])
     }
 
     #[inline]
     fn one() -> Self {
-        U256(0, 1)
+        U256([0, 0, 0, 1])
     }
 
     #[inline]
     fn leading_zeros(self) -> u32 {
-        let lz = self.0.leading_zeros();
-        let r = if lz == 128 {
-            lz + self.1.leading_zeros()
-        } else {
-            lz
-        };
-        r
+        words_leading_zeros(&self.0)
     }
 }
 
-impl PartialEq for Stride7 {
-    fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0 && self.1 == other.1
-    }
-}
-
-impl Eq for Stride7 {}
-
-impl Binary for Stride7 {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        std::fmt::Binary::fmt(&self, f)
+impl std::ops::BitOr<Self> for Stride7 {
+    type Output = Self;
+    fn bitor(mut self, rhs: Self) -> Self::Output {
+        for (w, r) in self.0.iter_mut().zip(rhs.0.iter()) {
+            *w |= r;
+        }
+        self
     }
 }
 
-impl std::ops::BitOr<Self> for Stride7 {
+impl std::ops::BitAnd<Self> for Stride7 {
     type Output = Self;
-    fn bitor(self, rhs: Self) -> Self::Output {
-        Self(self.0 | rhs.0, self.1 | rhs.1)
+    fn bitand(mut self, rhs: Self) -> Self::Output {
+        for (w, r) in self.0.iter_mut().zip(rhs.0.iter()) {
+            *w &= r;
+        }
+        self
     }
 }
 
-impl std::ops::BitAnd<Self> for Stride7 {
+impl std::ops::Not for Stride7 {
     type Output = Self;
-    fn bitand(self, rhs: Self) -> Self::Output
-    where
-        Self: Eq,
-    {
-        Self(self.0 & rhs.0, self.1 & rhs.1)
+    fn not(mut self) -> Self::Output {
+        for w in self.0.iter_mut() {
+            *w = !*w;
+        }
+        self
     }
 }
 
 impl Stride for Stride8 {
     type PtrSize = U256;
-    const BITS: u8 = 255; // bogus
+    const BITS: u16 = 512;
     const STRIDE_LEN: u8 = 8;
 
     fn get_bit_pos(nibble: u32, len: u8) -> Self {
-        match 512 - ((1 << len) - 1) as u16 - nibble as u16 - 1 {
-            n if n < 128 => {
-                U512(0, 0, 0, 1 << n)
-            }
-            n if n < 256 => U512(0, 0, 1 << (n as u16 - 128), 0),
-            n if n < 384 => {
-                U512(0, 1 << (n as u16 - 256), 0, 0)
-            }
-            n => U512(1 << (n as u16 - 384), 0, 0, 0),
-        }
+        U512(words_bit(
+            <Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1,
+        ))
     }
 
     fn get_pfx_index(bitmap: Self, nibble: u32, len: u8) -> usize {
-        let n = 512 - ((1 << len) - 1) as u16 - nibble as u16 - 1;
-        match n {
-            // if we move less than 128 bits to the right,
-            // all of bitmap.2 and a part of bitmap.3 will be used for counting zeros
-            // ex.
-            // ...1011_1010... >> 2 => ...0010_111010...
-            //    ____ ====                 -- --====
-            n if n < 128 => {
-                bitmap.0.count_ones() as usize
-                    + bitmap.1.count_ones() as usize
-                    + bitmap.2.count_ones() as usize
-                    + (bitmap.3 >> n).count_ones() as usize
-                    - 1
-            }
-
-            n if n < 256 => {
-                bitmap.0.count_ones() as usize
-                    + bitmap.1.count_ones() as usize
-                    + (bitmap.2 >> (n - 128)).count_ones() as usize
-                    - 1
-            }
-
-            n if n < 384 => {
-                bitmap.0.count_ones() as usize + (bitmap.1 >> (n - 256)).count_ones() as usize - 1
-            }
-
-            // if we move more than 384 bits to the right,
-            // all of bitmap.[1,2,3] will be shifted out of sight,
-            // so we only have to count bitmap.0 zeroes then (after shifting of course).
-            n => {
-                (bitmap.0 >> (n - 384)).count_ones() as usize - 1
-            }
-        }
+        words_count_ones_from(
+            &bitmap.0,
+            <Self as Stride>::BITS - ((1 << len) - 1) as u16 - nibble as u16 - 1,
+        ) - 1
     }
 
     fn get_ptr_index(bitmap: Self::PtrSize, nibble: u32) -> usize {
-        let n = (512 >> 1) - nibble as u16 - 1;
-        match n {
-            // if we move less than 256 bits to the right,
-            // all of bitmap.0 and a part of bitmap.1 will be used for counting zeros
-            // ex.
-            // ...1011_1010... >> 2 => ...0010_111010...
-            //    ____ ====                 -- --====
-            n if n < 128 => {
-                bitmap.0.count_ones() as usize + (bitmap.1 >> n).count_ones() as usize - 1
-            }
-            // if we move more than 256 bits to the right,
-            // all of bitmap.1 wil be shifted out of sight,
-            // so we only have to count bitmap.0 zeroes than (after) shifting of course).
-            n => (bitmap.0 >> (n - 128)).count_ones() as usize - 1,
-        }
+        words_count_ones_from(
+            &bitmap.0,
+            (<Self as Stride>::BITS >> 1) - nibble as u16 - 1,
+        ) - 1
     }
 
     fn into_stride_size(bitmap: Self::PtrSize) -> Self {
-        // One bit needs to move into the self.0 u128,
-        // since the last bit of the *whole* bitmap isn't used.
-        U512(
-            0,
-            bitmap.0 >> 127,
-            (bitmap.0 << 1) | (bitmap.1 >> 127),
-            bitmap.1 << 1,
-        )
-        // U256(bitmap >> 127, bitmap << 1)
+        let mut words = [0; 8];
+        words[4..].copy_from_slice(&bitmap.0);
+        U512(words_shl1(words))
     }
 
     fn into_ptrbitarr_size(bitmap: Self) -> Self::PtrSize {
-        // TODO expand:
-        // self.ptrbitarr =
-        // S::into_ptrbitarr_size(bit_pos | S::into_stride_size(self.ptrbitarr));
-        U256(
-            (bitmap.1 << 127 | bitmap.2 >> 1) as u128,
-            (bitmap.2 << 127 | bitmap.3 >> 1) as u128,
-        )
+        let words = words_shr1(bitmap.0);
+        U256([words[4], words[5], words[6], words[7]])
     }
 
     #[inline]
     fn zero() -> Self {
-        U512(0, 0, 0, 0)
+        U512([0; 8])
     }
 
     #[inline]
     fn one() -> Self {
-        U512(0, 0, 0, 1)
+        U512([0, 0, 0, 0, 0, 0, 0, 1])
     }
 
     #[inline]
     fn leading_zeros(self) -> u32 {
-        let mut lz = self.0.leading_zeros();
-        if lz == 128 {
-            lz += self.1.leading_zeros();
-            if lz == 256 {
-                lz += self.2.leading_zeros();
-                if lz == 384 {
-                    lz += self.3.leading_zeros();
-                }
-            }
-        }
-        lz
-    }
-}
-
-impl PartialEq for Stride8 {
-    fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0 && self.1 == other.1 && self.2 == other.2 && self.3 == other.3
+        words_leading_zeros(&self.0)
     }
 }
 
-impl Eq for Stride8 {}
-
-impl Binary for Stride8 {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        std::fmt::Binary::fmt(&self, f)
+impl std::ops::BitOr<Self> for Stride8 {
+    type Output = Self;
+    fn bitor(mut self, rhs: Self) -> Self::Output {
+        for (w, r) in self.0.iter_mut().zip(rhs.0.iter()) {
+            *w |= r;
+        }
+        self
     }
 }
 
-impl std::ops::BitOr<Self> for Stride8 {
+impl std::ops::BitAnd<Self> for Stride8 {
     type Output = Self;
-    fn bitor(self, rhs: Self) -> Self::Output {
-        Self(
-            self.0 | rhs.0,
-            self.1 | rhs.1,
-            self.2 | rhs.2,
-            self.3 | rhs.3,
-        )
+    fn bitand(mut self, rhs: Self) -> Self::Output {
+        for (w, r) in self.0.iter_mut().zip(rhs.0.iter()) {
+            *w &= r;
+        }
+        self
     }
 }
 
-impl std::ops::BitAnd<Self> for Stride8 {
+impl std::ops::Not for Stride8 {
     type Output = Self;
-    fn bitand(self, rhs: Self) -> Self::Output
-    where
-        Self: Eq,
-    {
-        Self(
-            self.0 & rhs.0,
-            self.1 & rhs.1,
-            self.2 & rhs.2,
-            self.3 & rhs.3,
-        )
+    fn not(mut self) -> Self::Output {
+        for w in self.0.iter_mut() {
+            *w = !*w;
+        }
+        self
     }
 }
 
@@ -601,6 +567,14 @@ pub enum SizedStrideNode<AF: AddressFamily> {
     Stride8(TreeBitMapNode<AF, Stride8>),
 }
 
+// An entry of a node, either a prefix or a child node, referenced by their
+// index in the global `prefixes` and `nodes` vecs respectively.
+#[derive(Debug, Copy, Clone)]
+enum NodeEntry {
+    Prefix(u32),
+    Child(u32),
+}
+
 pub struct TreeBitMapNode<AF, S>
 where
     S: Stride,
@@ -609,16 +583,20 @@ where
 {
     ptrbitarr: <S as Stride>::PtrSize,
     pfxbitarr: S,
-    // The vec of prefixes hosted by this node,
-    // referenced by (bit_id, global prefix index)
-    // We need the AF typed value to sort the vec
-    // that is stored in the node.
-    pfx_vec: Vec<(AF, u32)>,
-    // The vec of child nodes hosted by this
-    // node, referenced by (ptrbitarr_index, global vec index)
-    // We need the u16 (ptrbitarr_index) to sort the
-    // vec that's stored in the node.
-    ptr_vec: Vec<(u16, u32)>,
+    // The global indexes of the prefixes hosted by this node, in the order
+    // of their bits in pfxbitarr.
+    pfx_vec: Vec<u32>,
+    // The global indexes of the child nodes of this node, in the order of
+    // their bits in ptrbitarr.
+    ptr_vec: Vec<u32>,
+    // Set by `TreeBitMap::compact`, which stores the children of a node
+    // next to each other in the global vec, and its prefixes too. The
+    // global index of a child is then `ptr_base` plus its index in the
+    // ptrbitarr, likewise for the prefixes, and the vecs above are left
+    // empty. NO_INDEX if the vec is in use.
+    ptr_base: u32,
+    pfx_base: u32,
+    af: PhantomData<AF>,
 }
 
 impl<AF> Default for SizedStrideNode<AF>
@@ -631,6 +609,9 @@ where
             pfxbitarr: 0,
             pfx_vec: vec![],
             ptr_vec: vec![],
+            ptr_base: NO_INDEX,
+            pfx_base: NO_INDEX,
+            af: PhantomData,
         })
     }
 }
@@ -651,16 +632,19 @@ where
 }
 
 enum NewNodeOrIndex<AF: AddressFamily> {
-    NewNode(SizedStrideNode<AF>, u16), // New Node and bit_id of the new node
+    NewNode(SizedStrideNode<AF>),
     ExistingNode(u32),
     NewPrefix,
-    ExistingPrefix,
+    ExistingPrefix(u32), // Index of the stored prefix
 }
 
 impl<AF, S> TreeBitMapNode<AF, S>
 where
     AF: AddressFamily,
-    S: Stride + std::ops::BitAnd<Output = S> + std::ops::BitOr<Output = S>,
+    S: Stride
+        + std::ops::BitAnd<Output = S>
+        + std::ops::BitOr<Output = S>
+        + std::ops::Not<Output = S>,
     <S as Stride>::PtrSize: Debug + Binary + Copy,
 {
     // Inspects the stride (nibble, nibble_len) to see it there's
@@ -697,6 +681,7 @@ where
                 == <S as std::ops::BitAnd>::Output::zero()
             {
                 // Nope, set it and create a child node
+                self.unpack();
                 self.ptrbitarr =
                     S::into_ptrbitarr_size(bit_pos | S::into_stride_size(self.ptrbitarr));
 
@@ -707,6 +692,9 @@ where
                             pfxbitarr: Stride3::zero(),
                             pfx_vec: vec![],
                             ptr_vec: vec![],
+                            ptr_base: NO_INDEX,
+                            pfx_base: NO_INDEX,
+                            af: PhantomData,
                         });
                     }
                     4_u8 => {
@@ -715,6 +703,9 @@ where
                             pfxbitarr: Stride4::zero(),
                             pfx_vec: vec![],
                             ptr_vec: vec![],
+                            ptr_base: NO_INDEX,
+                            pfx_base: NO_INDEX,
+                            af: PhantomData,
                         });
                     }
                     5_u8 => {
@@ -723,6 +714,9 @@ where
                             pfxbitarr: Stride5::zero(),
                             pfx_vec: vec![],
                             ptr_vec: vec![],
+                            ptr_base: NO_INDEX,
+                            pfx_base: NO_INDEX,
+                            af: PhantomData,
                         });
                     }
                     6_u8 => {
@@ -731,22 +725,31 @@ where
                             pfxbitarr: Stride6::zero(),
                             pfx_vec: vec![],
                             ptr_vec: vec![],
+                            ptr_base: NO_INDEX,
+                            pfx_base: NO_INDEX,
+                            af: PhantomData,
                         });
                     }
                     7_u8 => {
                         new_node = SizedStrideNode::Stride7(TreeBitMapNode {
                             ptrbitarr: 0_u128,
-                            pfxbitarr: U256(0_u128, 0_u128),
+                            pfxbitarr: U256([0; 4]),
                             pfx_vec: vec![],
                             ptr_vec: vec![],
+                            ptr_base: NO_INDEX,
+                            pfx_base: NO_INDEX,
+                            af: PhantomData,
                         });
                     }
                     8_u8 => {
                         new_node = SizedStrideNode::Stride8(TreeBitMapNode {
-                            ptrbitarr: U256(0_u128, 0_u128),
-                            pfxbitarr: U512(0_u128, 0_u128, 0_u128, 0_u128),
+                            ptrbitarr: U256([0; 4]),
+                            pfxbitarr: U512([0; 8]),
                             pfx_vec: vec![],
                             ptr_vec: vec![],
+                            ptr_base: NO_INDEX,
+                            pfx_base: NO_INDEX,
+                            af: PhantomData,
                         });
                     }
                     _ => {
@@ -754,28 +757,200 @@ where
                     }
                 };
 
-                // we can return bit_pos.leading_zeros() since bit_pos is the bitmap that
-                // points to the current bit in ptrbitarr (it's **not** the prefix of the node!),
-                // so the number of zeros in front of it should always be unique and describes
-                // the index of this node in the ptrbitarr.
-                // ex.:
-                // In a stride3 (ptrbitarr lenght is 8):
-                // bit_pos 0001 0000
-                // so this is the fourth bit, so points to index = 3
-                return NewNodeOrIndex::NewNode(new_node, bit_pos.leading_zeros() as u16);
+                return NewNodeOrIndex::NewNode(new_node);
             }
         } else {
             // only at the last stride do we create the bit in the prefix bitmap,
             // and only if it doesn't exist already
             if self.pfxbitarr & bit_pos == <S as std::ops::BitAnd>::Output::zero() {
+                self.unpack();
                 self.pfxbitarr = bit_pos | self.pfxbitarr;
 
                 return NewNodeOrIndex::NewPrefix;
             }
-            return NewNodeOrIndex::ExistingPrefix;
+            return NewNodeOrIndex::ExistingPrefix(
+                self.prefix_at(S::get_pfx_index(self.pfxbitarr, nibble, nibble_len)),
+            );
+        }
+
+        NewNodeOrIndex::ExistingNode(self.child_at(S::get_ptr_index(self.ptrbitarr, nibble)))
+    }
+
+    // Clears the prefix bit for (nibble, nibble_len) and takes the entry out
+    // of the pfx_vec. Returns the index of the prefix in the global
+    // `prefixes` vec, or None if the bit wasn't set.
+    fn remove_prefix_at(&mut self, nibble: u32, nibble_len: u8) -> Option<u32> {
+        let bit_pos = S::get_bit_pos(nibble, nibble_len);
+        if self.pfxbitarr & bit_pos == S::zero() {
+            return None;
+        }
+        self.unpack();
+        let index = S::get_pfx_index(self.pfxbitarr, nibble, nibble_len);
+        self.pfxbitarr = self.pfxbitarr & !bit_pos;
+        Some(self.pfx_vec.remove(index))
+    }
+
+    // The global index of the prefix at `index` in the pfxbitarr.
+    #[inline]
+    fn prefix_at(&self, index: usize) -> u32 {
+        if self.pfx_base != NO_INDEX {
+            self.pfx_base + index as u32
+        } else {
+            self.pfx_vec[index]
+        }
+    }
+
+    // The global index of the child node at `index` in the ptrbitarr.
+    #[inline]
+    fn child_at(&self, index: usize) -> u32 {
+        if self.ptr_base != NO_INDEX {
+            self.ptr_base + index as u32
+        } else {
+            self.ptr_vec[index]
+        }
+    }
+
+    // The global indexes of the prefixes in this node, in pfxbitarr order.
+    fn prefix_indexes(&self) -> Vec<u32> {
+        let mut pfxs = vec![];
+        for nibble_len in 0..=S::STRIDE_LEN {
+            for nibble in 0..(1 << nibble_len) {
+                if let Some(pfx_idx) = self.prefix_index_at(nibble, nibble_len) {
+                    pfxs.push(pfx_idx);
+                }
+            }
         }
+        pfxs
+    }
 
-        NewNodeOrIndex::ExistingNode(self.ptr_vec[S::get_ptr_index(self.ptrbitarr, nibble)].1)
+    // The global indexes of the child nodes, in ptrbitarr order.
+    fn child_indexes(&self) -> Vec<u32> {
+        (0..(1 << S::STRIDE_LEN))
+            .filter_map(|nibble| self.child_index_at(nibble))
+            .collect()
+    }
+
+    // Stores the global index of a new child node, whose bit has been set
+    // already, at its place in the ptr_vec.
+    fn insert_child_at(&mut self, nibble: u32, child_idx: u32) {
+        let index = S::get_ptr_index(self.ptrbitarr, nibble);
+        self.ptr_vec.insert(index, child_idx);
+    }
+
+    // Likewise for a new prefix in the pfx_vec.
+    fn insert_prefix_at(&mut self, nibble: u32, nibble_len: u8, pfx_idx: u32) {
+        let index = S::get_pfx_index(self.pfxbitarr, nibble, nibble_len);
+        self.pfx_vec.insert(index, pfx_idx);
+    }
+
+    // Builds a node in one go from the prefixes stored in it, as (nibble,
+    // nibble_len, global index), and its child nodes, as (nibble, global
+    // index) in nibble order. Both vecs are allocated at their final size.
+    fn with_entries(pfxs: &[(u32, u8, u32)], children: &[(u32, u32)]) -> Self {
+        let mut pfxbitarr = S::zero();
+        for (nibble, nibble_len, _) in pfxs.iter() {
+            pfxbitarr = pfxbitarr | S::get_bit_pos(*nibble, *nibble_len);
+        }
+        let mut pfx_vec = vec![0; pfxs.len()];
+        for (nibble, nibble_len, pfx_idx) in pfxs.iter() {
+            pfx_vec[S::get_pfx_index(pfxbitarr, *nibble, *nibble_len)] = *pfx_idx;
+        }
+        let mut ptrbitarr = S::zero();
+        for (nibble, _) in children.iter() {
+            ptrbitarr = ptrbitarr | S::get_bit_pos(*nibble, S::STRIDE_LEN);
+        }
+        TreeBitMapNode {
+            ptrbitarr: S::into_ptrbitarr_size(ptrbitarr),
+            pfxbitarr,
+            pfx_vec,
+            ptr_vec: children.iter().map(|(_, child_idx)| *child_idx).collect(),
+            ptr_base: NO_INDEX,
+            pfx_base: NO_INDEX,
+            af: PhantomData,
+        }
+    }
+
+    // Points the node at its children and prefixes laid out from
+    // `ptr_base` and `pfx_base` by `TreeBitMap::compact`.
+    fn pack(&mut self, ptr_base: u32, pfx_base: u32) {
+        self.ptr_base = ptr_base;
+        self.pfx_base = pfx_base;
+        self.ptr_vec = vec![];
+        self.pfx_vec = vec![];
+    }
+
+    // Moves a packed node back to its vecs before it gets changed, after
+    // that its children and prefixes can live anywhere again.
+    fn unpack(&mut self) {
+        if self.ptr_base != NO_INDEX {
+            self.ptr_vec = self.child_indexes();
+            self.ptr_base = NO_INDEX;
+        }
+        if self.pfx_base != NO_INDEX {
+            self.pfx_vec = self.prefix_indexes();
+            self.pfx_base = NO_INDEX;
+        }
+    }
+
+    // Returns the index in the global `prefixes` vec of the prefix for
+    // (nibble, nibble_len), if its bit is set.
+    fn prefix_index_at(&self, nibble: u32, nibble_len: u8) -> Option<u32> {
+        if self.pfxbitarr & S::get_bit_pos(nibble, nibble_len) == S::zero() {
+            return None;
+        }
+        Some(self.prefix_at(S::get_pfx_index(self.pfxbitarr, nibble, nibble_len)))
+    }
+
+    // Collects the prefix at (nibble, nibble_len) and all the entries below
+    // it in canonical order. The bits of pfxbitarr are visited as if this
+    // node were a binary trie: first the prefix itself, then the half with
+    // the next bit 0, then the half with the next bit 1. At the full nibble
+    // length the child node for that nibble takes over, its own entries
+    // are left for the caller to expand.
+    fn collect_entries(&self, nibble: u32, nibble_len: u8, entries: &mut Vec<NodeEntry>) {
+        if let Some(pfx_idx) = self.prefix_index_at(nibble, nibble_len) {
+            entries.push(NodeEntry::Prefix(pfx_idx));
+        }
+        self.more_specific_entries(nibble, nibble_len, entries);
+    }
+
+    // Like `collect_entries`, but leaves out the prefix at
+    // (nibble, nibble_len) itself.
+    fn more_specific_entries(&self, nibble: u32, nibble_len: u8, entries: &mut Vec<NodeEntry>) {
+        if nibble_len < S::STRIDE_LEN {
+            self.collect_entries(nibble << 1, nibble_len + 1, entries);
+            self.collect_entries((nibble << 1) + 1, nibble_len + 1, entries);
+        } else if let Some(child_idx) = self.child_index_at(nibble) {
+            entries.push(NodeEntry::Child(child_idx));
+        }
+    }
+
+    // Clears the ptr bit for a full-length nibble and drops the child node
+    // from the ptr_vec. The child node itself isn't touched.
+    fn remove_child_at(&mut self, nibble: u32) {
+        let bit_pos = S::get_bit_pos(nibble, S::STRIDE_LEN);
+        if (S::into_stride_size(self.ptrbitarr) & bit_pos) == S::zero() {
+            return;
+        }
+        self.unpack();
+        let index = S::get_ptr_index(self.ptrbitarr, nibble);
+        self.ptrbitarr = S::into_ptrbitarr_size(S::into_stride_size(self.ptrbitarr) & !bit_pos);
+        self.ptr_vec.remove(index);
+    }
+
+    // A node without prefixes and without children can be released.
+    fn is_empty(&self) -> bool {
+        self.pfxbitarr == S::zero() && S::into_stride_size(self.ptrbitarr) == S::zero()
+    }
+
+    // Returns the index in the global `nodes` vec of the child node for a
+    // full-length nibble, if it exists.
+    fn child_index_at(&self, nibble: u32) -> Option<u32> {
+        let bit_pos = S::get_bit_pos(nibble, S::STRIDE_LEN);
+        if (S::into_stride_size(self.ptrbitarr) & bit_pos) == S::zero() {
+            return None;
+        }
+        Some(self.child_at(S::get_ptr_index(self.ptrbitarr, nibble)))
     }
 
     fn search_stride_at<'b>(
@@ -785,10 +960,16 @@ where
         nibble_len: u8,
         start_bit: u8,
         // found_pfx: &'b mut Vec<&'a Prefix<AF, T>>,
-        found_pfx: &'b mut Vec<(AF, u32)>,
+        found_pfx: &'b mut Vec<u32>,
     ) -> Option<u32> {
         let mut bit_pos = S::get_bit_pos(nibble, nibble_len);
 
+        // The bit for length 0 is only ever set in the root node, for the
+        // default route, which matches everything.
+        if self.pfxbitarr & S::get_bit_pos(0, 0) > S::zero() {
+            found_pfx.push(self.prefix_at(S::get_pfx_index(self.pfxbitarr, 0, 0)));
+        }
+
         for n_l in 1..(nibble_len + 1) {
             // Move the bit in the right position.
             nibble = AddressFamily::get_nibble(search_pfx.net, start_bit, n_l);
@@ -796,21 +977,23 @@ where
 
             // Check it there's an prefix matching in this bitmap for this nibble
             if self.pfxbitarr & bit_pos > S::zero() {
-                found_pfx.push(self.pfx_vec[S::get_pfx_index(self.pfxbitarr, nibble, n_l)]);
+                found_pfx.push(self.prefix_at(S::get_pfx_index(self.pfxbitarr, nibble, n_l)));
             }
         }
 
         // Check if this the last stride, or if they're no more children to go to,
         // if so return what we found up until now.
         // let SizedStrideNode::Stride4(current_node) = node;
-        if search_pfx.len < start_bit
+        // The prefix ending exactly at the end of this stride counts as the
+        // last stride too, the child nodes only hold more-specifics.
+        if search_pfx.len <= start_bit + nibble_len
             || (S::into_stride_size(self.ptrbitarr) & bit_pos)
                 == <S as std::ops::BitAnd>::Output::zero()
         {
             return None;
         }
 
-        Some(self.ptr_vec[S::get_ptr_index(self.ptrbitarr, nibble)].1)
+        Some(self.child_at(S::get_ptr_index(self.ptrbitarr, nibble)))
     }
 
     fn search_stride_at_lmp_only<'b>(
@@ -819,10 +1002,14 @@ where
         mut nibble: u32,
         nibble_len: u8,
         start_bit: u8,
-    ) -> (Option<u32>, Option<(AF, u32)>) {
+    ) -> (Option<u32>, Option<u32>) {
         let mut bit_pos = S::get_bit_pos(nibble, nibble_len);
         let mut found_pfx = None;
 
+        if self.pfxbitarr & S::get_bit_pos(0, 0) > S::zero() {
+            found_pfx = Some(self.prefix_at(S::get_pfx_index(self.pfxbitarr, 0, 0)));
+        }
+
         for n_l in 1..(nibble_len + 1) {
             // Move the bit in the right position.
             nibble = AddressFamily::get_nibble(search_pfx.net, start_bit, n_l);
@@ -833,13 +1020,15 @@ where
 
             // Check it there's an prefix matching in this bitmap for this nibble
             if self.pfxbitarr & bit_pos > S::zero() {
-                found_pfx = Some(self.pfx_vec[S::get_pfx_index(self.pfxbitarr, nibble, n_l)]);
+                found_pfx = Some(self.prefix_at(S::get_pfx_index(self.pfxbitarr, nibble, n_l)));
             }
         }
 
         // Check if this the last stride, or if they're no more children to go to,
         // if so return what we found up until now.
-        if search_pfx.len < start_bit
+        // The prefix ending exactly at the end of this stride counts as the
+        // last stride too, the child nodes only hold more-specifics.
+        if search_pfx.len <= start_bit + nibble_len
             || (S::into_stride_size(self.ptrbitarr) & bit_pos)
                 == <S as std::ops::BitAnd>::Output::zero()
         {
@@ -847,11 +1036,60 @@ where
         }
 
         (
-            Some(self.ptr_vec[S::get_ptr_index(self.ptrbitarr, nibble)].1),
+            Some(self.child_at(S::get_ptr_index(self.ptrbitarr, nibble))),
             found_pfx,
         )
     }
 }
+
+// An index into the global vecs that points nowhere. It marks an empty half
+// of a `RootTable` entry, the `ptr_base` and `pfx_base` of a node that isn't
+// packed by `compact` (yet), and a batch lookup lane that is done.
+const NO_INDEX: u32 = u32::MAX;
+
+// The number of lookups `match_longest_prefix_batch` walks down the tree
+// side by side.
+const BATCH_LANES: usize = 8;
+
+// The state of one lookup in `match_longest_prefix_batch`: the node it's
+// going to visit next (NO_INDEX once it's done), at which level and start
+// bit, and the longest match so far.
+#[derive(Copy, Clone)]
+struct Lane {
+    node_idx: u32,
+    level: usize,
+    start_bit: u8,
+    found_pfx_idx: Option<u32>,
+}
+
+// Asks the CPU to pull `item` into the cache, so that it's there by the
+// time it gets read. A no-op where there's no prefetch instruction.
+#[inline]
+fn prefetch<U>(item: &U) {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        std::arch::x86_64::_mm_prefetch(
+            item as *const U as *const i8,
+            std::arch::x86_64::_MM_HINT_T0,
+        );
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = item;
+}
+
+// A flat table indexed by the first `bits` bits of the address, in front of
+// the nodes that start at that bit. Every entry holds the index of that
+// node (if any) and the index of the longest prefix with a length of at
+// most `bits` covering the entry (leaf-pushed), so that a lookup for a
+// full-table IPv4 address starts right at the node for its /16 or /24.
+// `bits` has to fall on a stride boundary.
+pub struct RootTable {
+    bits: u8,
+    // The number of strides covered by the table.
+    level: usize,
+    entries: Vec<(u32, u32)>,
+}
+
 pub struct TreeBitMap<AF, T>
 where
     T: Debug,
@@ -861,7 +1099,13 @@ where
     pub strides: Vec<u8>,
     pub stats: Vec<StrideStats>,
     pub nodes: Vec<SizedStrideNode<AF>>,
-    pub prefixes: Vec<Prefix<AF, T>>,
+    // The slots vacated by `remove` are None.
+    pub prefixes: Vec<Option<Prefix<AF, T>>>,
+    // Indexes of the slots in `nodes` and `prefixes` that were vacated by
+    // `remove`, these get handed out again before the vecs grow.
+    free_nodes: Vec<u32>,
+    free_prefixes: Vec<u32>,
+    root_table: Option<RootTable>,
 }
 
 impl<'a, AF, T> TreeBitMap<AF, T>
@@ -870,17 +1114,31 @@ where
     AF: AddressFamily + Debug + From::<u32>,
 {
     pub fn new(_strides_vec: Vec<u8>) -> TreeBitMap<AF, T> {
-        // Check if the strides division makes sense
+        // Check if the strides division makes sense, the stride sizes have
+        // to be valid before cycling through them, otherwise the sum might
+        // never reach the bits of the address family.
+        assert!(!_strides_vec.is_empty(), "no strides given");
+        assert!(
+            _strides_vec.iter().all(|s| (3..=8).contains(s)),
+            "strides {:?} contain a stride size other than 3 to 8",
+            _strides_vec
+        );
         let mut strides = vec![];
         let mut strides_sum = 0;
         for s in _strides_vec.iter().cycle() {
             strides.push(*s);
             strides_sum += s;
-            if strides_sum >= AF::BITS - 1 {
+            if strides_sum >= AF::BITS {
                 break;
             }
         }
-        assert_eq!(strides.iter().fold(0, |acc, s| { acc + s }), AF::BITS);
+        assert_eq!(
+            strides_sum,
+            AF::BITS,
+            "strides {:?} don't add up to the {} bits of the address family",
+            strides,
+            AF::BITS
+        );
 
         let mut stride_stats: Vec<StrideStats> = vec![
             StrideStats::new(SizedStride::Stride3, strides.len() as u8), // 0
@@ -900,6 +1158,9 @@ where
                     pfxbitarr: 0,
                     ptr_vec: vec![],
                     pfx_vec: vec![],
+                    ptr_base: NO_INDEX,
+                    pfx_base: NO_INDEX,
+                    af: PhantomData,
                 });
                 stride_stats[0].inc(0);
             }
@@ -909,6 +1170,9 @@ where
                     pfxbitarr: 0,
                     ptr_vec: vec![],
                     pfx_vec: vec![],
+                    ptr_base: NO_INDEX,
+                    pfx_base: NO_INDEX,
+                    af: PhantomData,
                 });
                 stride_stats[1].inc(0);
             }
@@ -918,6 +1182,9 @@ where
                     pfxbitarr: 0,
                     ptr_vec: vec![],
                     pfx_vec: vec![],
+                    ptr_base: NO_INDEX,
+                    pfx_base: NO_INDEX,
+                    af: PhantomData,
                 });
                 stride_stats[2].inc(0);
             }
@@ -927,24 +1194,33 @@ where
                     pfxbitarr: 0,
                     ptr_vec: vec![],
                     pfx_vec: vec![],
+                    ptr_base: NO_INDEX,
+                    pfx_base: NO_INDEX,
+                    af: PhantomData,
                 });
                 stride_stats[3].inc(0);
             }
             7 => {
                 node = SizedStrideNode::Stride7(TreeBitMapNode {
                     ptrbitarr: 0,
-                    pfxbitarr: U256(0, 0),
+                    pfxbitarr: U256([0; 4]),
                     ptr_vec: vec![],
                     pfx_vec: vec![],
+                    ptr_base: NO_INDEX,
+                    pfx_base: NO_INDEX,
+                    af: PhantomData,
                 });
                 stride_stats[4].inc(0);
             }
             8 => {
                 node = SizedStrideNode::Stride8(TreeBitMapNode {
-                    ptrbitarr: U256(0, 0),
-                    pfxbitarr: U512(0, 0, 0, 0),
+                    ptrbitarr: U256([0; 4]),
+                    pfxbitarr: U512([0; 8]),
                     ptr_vec: vec![],
                     pfx_vec: vec![],
+                    ptr_base: NO_INDEX,
+                    pfx_base: NO_INDEX,
+                    af: PhantomData,
                 });
                 stride_stats[5].inc(0);
             }
@@ -958,7 +1234,138 @@ where
             stats: stride_stats,
             nodes: vec![node],
             prefixes: vec![],
+            free_nodes: vec![],
+            free_prefixes: vec![],
+            root_table: None,
+        }
+    }
+
+    // Like `new`, with a `RootTable` over the first `bits` (16 or 24) bits
+    // in front of the tree. The strides have to add up to `bits` exactly
+    // somewhere along the way.
+    pub fn with_root_table(strides: Vec<u8>, bits: u8) -> TreeBitMap<AF, T> {
+        assert!(
+            bits == 16 || bits == 24,
+            "a root table covers 16 or 24 bits, not {}",
+            bits
+        );
+        let mut tree = Self::new(strides);
+        let mut stride_end = 0;
+        let level = tree
+            .strides
+            .iter()
+            .position(|s| {
+                stride_end += s;
+                stride_end >= bits
+            })
+            .unwrap()
+            + 1;
+        assert_eq!(
+            stride_end, bits,
+            "strides {:?} don't end on bit {} of the root table",
+            tree.strides, bits
+        );
+        tree.root_table = Some(RootTable {
+            bits,
+            level,
+            entries: vec![(NO_INDEX, NO_INDEX); 1 << bits],
+        });
+        tree
+    }
+
+    // Builds a tree from prefixes in canonical order (by network first and
+    // length second) bottom-up: every node is created once with all of its
+    // prefixes and children, instead of walking down from the root for
+    // every prefix. A prefix that comes up twice in a row replaces the
+    // first one. The result, including the stats and the layout of the
+    // global vecs, is the same as inserting the prefixes one by one in
+    // canonical order; inserting them in another order only ends up with
+    // the same prefixes and lookups.
+    pub fn from_sorted_iter<I>(strides: Vec<u8>, pfxs: I) -> TreeBitMap<AF, T>
+    where
+        I: IntoIterator<Item = Prefix<AF, T>>,
+    {
+        let mut tree = Self::new(strides);
+        let mut sorted: Vec<Prefix<AF, T>> = vec![];
+        for pfx in pfxs {
+            match sorted.last_mut() {
+                Some(last) if *last == pfx => {
+                    *last = pfx;
+                    continue;
+                }
+                Some(last) => assert!(
+                    *last < pfx,
+                    "prefix {} comes after {}, the prefixes aren't sorted",
+                    pfx,
+                    last
+                ),
+                None => {}
+            }
+            sorted.push(pfx);
         }
+        tree.prefixes = sorted.into_iter().map(Some).collect();
+        let prefixes_num = tree.prefixes.len();
+        tree.build_node(0, 0..prefixes_num, 0);
+        tree
+    }
+
+    // Like `from_sorted_iter`, for prefixes in any order. They're sorted
+    // first, of the prefixes that come up more than once the last one
+    // stays, like with `insert`.
+    pub fn bulk_load(strides: Vec<u8>, mut pfxs: Vec<Prefix<AF, T>>) -> TreeBitMap<AF, T> {
+        // A stable sort, so that the duplicates stay in their order.
+        pfxs.sort();
+        Self::from_sorted_iter(strides, pfxs)
+    }
+
+    // Builds the node with index `node_idx` at `level` for the prefixes in
+    // `pfx_range` of the `prefixes` vec, and its child nodes before it,
+    // depth-first and in nibble order, which is the order in which inserting
+    // the prefixes one by one in canonical order creates them. All the prefixes for a child
+    // node sit next to each other, the ones stored in this node sort before
+    // the ones for the child node they cover.
+    fn build_node(&mut self, node_idx: u32, pfx_range: std::ops::Range<usize>, level: usize) {
+        let start_bit: u8 = self.strides[..level].iter().sum();
+        let stride = self.strides[level];
+        let stride_end = start_bit + stride;
+        let mut node_pfxs = vec![];
+        let mut children = vec![];
+
+        let mut i = pfx_range.start;
+        while i < pfx_range.end {
+            let pfx = self.retrieve_prefix(i as u32).unwrap();
+            let (net, len) = (pfx.net, pfx.len);
+            if len <= stride_end {
+                let nibble_len = len - start_bit;
+                let nibble = AF::get_nibble(net, start_bit, nibble_len);
+                node_pfxs.push((nibble, nibble_len, i as u32));
+                self.stats[(stride - 3) as usize].inc_prefix_count(level as u8);
+                i += 1;
+                continue;
+            }
+
+            let nibble = AF::get_nibble(net, start_bit, stride);
+            let end = i + self.prefixes[i..pfx_range.end]
+                .iter()
+                .flatten()
+                .take_while(|p| p.len > stride_end && AF::get_nibble(p.net, start_bit, stride) == nibble)
+                .count();
+            self.stats[(self.strides[level + 1] - 3) as usize].inc(level as u8 + 1);
+            let child_idx = self.store_node(SizedStrideNode::default());
+            self.build_node(child_idx, i..end, level + 1);
+            children.push((nibble, child_idx));
+            i = end;
+        }
+
+        self.nodes[node_idx as usize] = match stride {
+            3 => SizedStrideNode::Stride3(TreeBitMapNode::with_entries(&node_pfxs, &children)),
+            4 => SizedStrideNode::Stride4(TreeBitMapNode::with_entries(&node_pfxs, &children)),
+            5 => SizedStrideNode::Stride5(TreeBitMapNode::with_entries(&node_pfxs, &children)),
+            6 => SizedStrideNode::Stride6(TreeBitMapNode::with_entries(&node_pfxs, &children)),
+            7 => SizedStrideNode::Stride7(TreeBitMapNode::with_entries(&node_pfxs, &children)),
+            8 => SizedStrideNode::Stride8(TreeBitMapNode::with_entries(&node_pfxs, &children)),
+            _ => panic!("unknown stride size encountered in STRIDES array"),
+        };
     }
 
     // Partition for stride 4
@@ -991,8 +1398,8 @@ where
     // bit_pos        (1 << nibble length) - 1 + nibble = (1 << 2) - 1 + 2 = 5  0000_0010_0000_0000_0000_0000_0000_0000
     // 5 - 5 - 5 - 4 - 4 - [4] - 5
     // startpos (2 ^ nibble length) - 1 + nibble as usize
-
-    pub fn insert(&mut self, pfx: Prefix<AF, T>) {
+    //
+    fn insert_in_tree(&mut self, pfx: Prefix<AF, T>) -> Option<Prefix<AF, T>> {
         let mut stride_end: u8 = 0;
         let mut cur_i = 0;
         let mut node = std::mem::take(self.retrieve_node_mut(cur_i).unwrap());
@@ -1017,15 +1424,17 @@ where
 
             let is_last_stride = pfx_len <= stride_end;
 
+            // A newly created child node is counted in the stats for its own
+            // stride size and level (i.e. the next ones), like the root node.
+
             let (next_node_idx, cur_node) = match node {
                 SizedStrideNode::Stride3(mut current_node) => match current_node
                     .eval_node_or_prefix_at(nibble, nibble_len, next_stride, is_last_stride)
                 {
-                    NewNodeOrIndex::NewNode(n, bit_id) => {
-                        self.stats[0].inc(level);
+                    NewNodeOrIndex::NewNode(n) => {
+                        self.stats[(next_stride.unwrap() - 3) as usize].inc(level + 1);
                         let i = self.store_node(n);
-                        current_node.ptr_vec.push((bit_id, i));
-                        current_node.ptr_vec.sort();
+                        current_node.insert_child_at(nibble, i);
                         (Some(i), SizedStrideNode::Stride3(current_node))
                     }
                     NewNodeOrIndex::ExistingNode(i) => {
@@ -1034,18 +1443,22 @@ where
                     NewNodeOrIndex::NewPrefix => {
                         let i = self.store_prefix(pfx);
                         self.stats[0].inc_prefix_count(level);
-                        current_node
-                            .pfx_vec
-                            .push((((1 << nibble_len) + nibble).into(), i));
-                        current_node.pfx_vec.sort();
+                        current_node.insert_prefix_at(nibble, nibble_len, i);
                         let _default_val = std::mem::replace(
                             self.retrieve_node_mut(cur_i).unwrap(),
                             SizedStrideNode::Stride3(current_node),
                         );
-                        return;
+                        return None;
                     }
-                    NewNodeOrIndex::ExistingPrefix => {
-                        (None, SizedStrideNode::Stride3(current_node))
+                    NewNodeOrIndex::ExistingPrefix(pfx_idx) => {
+                        let _default_val = std::mem::replace(
+                            self.retrieve_node_mut(cur_i).unwrap(),
+                            SizedStrideNode::Stride3(current_node),
+                        );
+                        return Some(std::mem::replace(
+                            self.retrieve_prefix_mut(pfx_idx).unwrap(),
+                            pfx,
+                        ));
                     }
                 },
                 SizedStrideNode::Stride4(mut current_node) => match current_node
@@ -1061,11 +1474,10 @@ where
                         next_stride,
                         pfx_len <= stride_end,
                     ) {
-                    NewNodeOrIndex::NewNode(n, bit_id) => {
-                        self.stats[1].inc(level);
+                    NewNodeOrIndex::NewNode(n) => {
+                        self.stats[(next_stride.unwrap() - 3) as usize].inc(level + 1);
                         let i = self.store_node(n);
-                        current_node.ptr_vec.push((bit_id, i));
-                        current_node.ptr_vec.sort();
+                        current_node.insert_child_at(nibble, i);
                         (Some(i), SizedStrideNode::Stride4(current_node))
                     }
                     NewNodeOrIndex::ExistingNode(i) => {
@@ -1074,18 +1486,22 @@ where
                     NewNodeOrIndex::NewPrefix => {
                         let i = self.store_prefix(pfx);
                         self.stats[1].inc_prefix_count(level);
-                        current_node
-                            .pfx_vec
-                            .push((((1 << nibble_len) + nibble).into(), i));
-                        current_node.pfx_vec.sort();
+                        current_node.insert_prefix_at(nibble, nibble_len, i);
                         let _default_val = std::mem::replace(
                             self.retrieve_node_mut(cur_i).unwrap(),
                             SizedStrideNode::Stride4(current_node),
                         );
-                        return;
+                        return None;
                     }
-                    NewNodeOrIndex::ExistingPrefix => {
-                        (None, SizedStrideNode::Stride4(current_node))
+                    NewNodeOrIndex::ExistingPrefix(pfx_idx) => {
+                        let _default_val = std::mem::replace(
+                            self.retrieve_node_mut(cur_i).unwrap(),
+                            SizedStrideNode::Stride4(current_node),
+                        );
+                        return Some(std::mem::replace(
+                            self.retrieve_prefix_mut(pfx_idx).unwrap(),
+                            pfx,
+                        ));
                     }
                 },
                 SizedStrideNode::Stride5(mut current_node) => match current_node
@@ -1095,11 +1511,10 @@ where
                         next_stride,
                         pfx_len <= stride_end,
                     ) {
-                    NewNodeOrIndex::NewNode(n, bit_id) => {
-                        self.stats[2].inc(level);
+                    NewNodeOrIndex::NewNode(n) => {
+                        self.stats[(next_stride.unwrap() - 3) as usize].inc(level + 1);
                         let i = self.store_node(n);
-                        current_node.ptr_vec.push((bit_id, i));
-                        current_node.ptr_vec.sort();
+                        current_node.insert_child_at(nibble, i);
                         (Some(i), SizedStrideNode::Stride5(current_node))
                     }
                     NewNodeOrIndex::ExistingNode(i) => {
@@ -1108,18 +1523,22 @@ where
                     NewNodeOrIndex::NewPrefix => {
                         let i = self.store_prefix(pfx);
                         self.stats[2].inc_prefix_count(level);
-                        current_node
-                            .pfx_vec
-                            .push((((1 << nibble_len) + nibble).into(), i));
-                        current_node.pfx_vec.sort();
+                        current_node.insert_prefix_at(nibble, nibble_len, i);
                         let _default_val = std::mem::replace(
                             self.retrieve_node_mut(cur_i).unwrap(),
                             SizedStrideNode::Stride5(current_node),
                         );
-                        return;
+                        return None;
                     }
-                    NewNodeOrIndex::ExistingPrefix => {
-                        (None, SizedStrideNode::Stride5(current_node))
+                    NewNodeOrIndex::ExistingPrefix(pfx_idx) => {
+                        let _default_val = std::mem::replace(
+                            self.retrieve_node_mut(cur_i).unwrap(),
+                            SizedStrideNode::Stride5(current_node),
+                        );
+                        return Some(std::mem::replace(
+                            self.retrieve_prefix_mut(pfx_idx).unwrap(),
+                            pfx,
+                        ));
                     }
                 },
                 SizedStrideNode::Stride6(mut current_node) => match current_node
@@ -1129,11 +1548,10 @@ where
                         next_stride,
                         pfx_len <= stride_end,
                     ) {
-                    NewNodeOrIndex::NewNode(n, bit_id) => {
-                        self.stats[3].inc(level);
+                    NewNodeOrIndex::NewNode(n) => {
+                        self.stats[(next_stride.unwrap() - 3) as usize].inc(level + 1);
                         let i = self.store_node(n);
-                        current_node.ptr_vec.push((bit_id, i));
-                        current_node.ptr_vec.sort();
+                        current_node.insert_child_at(nibble, i);
                         (Some(i), SizedStrideNode::Stride6(current_node))
                     }
                     NewNodeOrIndex::ExistingNode(i) => {
@@ -1142,18 +1560,22 @@ where
                     NewNodeOrIndex::NewPrefix => {
                         let i = self.store_prefix(pfx);
                         self.stats[3].inc_prefix_count(level);
-                        current_node
-                            .pfx_vec
-                            .push((((1 << nibble_len) + nibble).into(), i));
-                        current_node.pfx_vec.sort_unstable();
+                        current_node.insert_prefix_at(nibble, nibble_len, i);
                         let _default_val = std::mem::replace(
                             self.retrieve_node_mut(cur_i).unwrap(),
                             SizedStrideNode::Stride6(current_node),
                         );
-                        return;
+                        return None;
                     }
-                    NewNodeOrIndex::ExistingPrefix => {
-                        (None, SizedStrideNode::Stride6(current_node))
+                    NewNodeOrIndex::ExistingPrefix(pfx_idx) => {
+                        let _default_val = std::mem::replace(
+                            self.retrieve_node_mut(cur_i).unwrap(),
+                            SizedStrideNode::Stride6(current_node),
+                        );
+                        return Some(std::mem::replace(
+                            self.retrieve_prefix_mut(pfx_idx).unwrap(),
+                            pfx,
+                        ));
                     }
                 },
                 SizedStrideNode::Stride7(mut current_node) => match current_node
@@ -1163,11 +1585,10 @@ where
                         next_stride,
                         pfx_len <= stride_end,
                     ) {
-                    NewNodeOrIndex::NewNode(n, bit_id) => {
-                        self.stats[4].inc(level);
+                    NewNodeOrIndex::NewNode(n) => {
+                        self.stats[(next_stride.unwrap() - 3) as usize].inc(level + 1);
                         let i = self.store_node(n);
-                        current_node.ptr_vec.push((bit_id, i));
-                        current_node.ptr_vec.sort();
+                        current_node.insert_child_at(nibble, i);
                         (Some(i), SizedStrideNode::Stride7(current_node))
                     }
                     NewNodeOrIndex::ExistingNode(i) => {
@@ -1176,18 +1597,22 @@ where
                     NewNodeOrIndex::NewPrefix => {
                         let i = self.store_prefix(pfx);
                         self.stats[4].inc_prefix_count(level);
-                        current_node
-                            .pfx_vec
-                            .push((((1 << nibble_len) + nibble).into(), i));
-                        current_node.pfx_vec.sort();
+                        current_node.insert_prefix_at(nibble, nibble_len, i);
                         let _default_val = std::mem::replace(
                             self.retrieve_node_mut(cur_i).unwrap(),
                             SizedStrideNode::Stride7(current_node),
                         );
-                        return;
+                        return None;
                     }
-                    NewNodeOrIndex::ExistingPrefix => {
-                        (None, SizedStrideNode::Stride7(current_node))
+                    NewNodeOrIndex::ExistingPrefix(pfx_idx) => {
+                        let _default_val = std::mem::replace(
+                            self.retrieve_node_mut(cur_i).unwrap(),
+                            SizedStrideNode::Stride7(current_node),
+                        );
+                        return Some(std::mem::replace(
+                            self.retrieve_prefix_mut(pfx_idx).unwrap(),
+                            pfx,
+                        ));
                     }
                 },
                 SizedStrideNode::Stride8(mut current_node) => match current_node
@@ -1197,33 +1622,34 @@ where
                         next_stride,
                         pfx_len <= stride_end,
                     ) {
-                    NewNodeOrIndex::NewNode(n, bit_id) => {
-                        self.stats[5].inc(level);
+                    NewNodeOrIndex::NewNode(n) => {
+                        self.stats[(next_stride.unwrap() - 3) as usize].inc(level + 1);
                         let i = self.store_node(n);
-                        current_node.ptr_vec.push((bit_id, i));
-                        current_node.ptr_vec.sort();
-
+                        current_node.insert_child_at(nibble, i);
                         (Some(i), SizedStrideNode::Stride8(current_node))
                     }
                     NewNodeOrIndex::ExistingNode(i) => {
                         (Some(i), SizedStrideNode::Stride8(current_node))
                     }
-                    NewNodeOrIndex::ExistingPrefix => {
-                        (None, SizedStrideNode::Stride8(current_node))
+                    NewNodeOrIndex::ExistingPrefix(pfx_idx) => {
+                        let _default_val = std::mem::replace(
+                            self.retrieve_node_mut(cur_i).unwrap(),
+                            SizedStrideNode::Stride8(current_node),
+                        );
+                        return Some(std::mem::replace(
+                            self.retrieve_prefix_mut(pfx_idx).unwrap(),
+                            pfx,
+                        ));
                     }
                     NewNodeOrIndex::NewPrefix => {
                         let i = self.store_prefix(pfx);
                         self.stats[5].inc_prefix_count(level);
-                        current_node
-                            .pfx_vec
-                            .push((((1 << nibble_len) + nibble).into(), i));
-                        current_node.pfx_vec.sort();
-                        println!("pfx_vec {:?}", current_node.pfx_vec);
+                        current_node.insert_prefix_at(nibble, nibble_len, i);
                         let _default_val = std::mem::replace(
                             self.retrieve_node_mut(cur_i).unwrap(),
                             SizedStrideNode::Stride8(current_node),
                         );
-                        return;
+                        return None;
                     }
                 },
             };
@@ -1235,12 +1661,84 @@ where
                 cur_i = i;
                 level += 1;
             } else {
-                return;
+                return None;
+            }
+        }
+    }
+
+    // A prefix that is already stored is replaced in the prefixes vec, the
+    // replaced prefix is handed back.
+    pub fn insert(&mut self, pfx: Prefix<AF, T>) -> Option<Prefix<AF, T>> {
+        let (net, len) = (pfx.net, pfx.len);
+        let replaced = self.insert_in_tree(pfx);
+        // A replaced prefix keeps its index, so the root table still holds.
+        if replaced.is_none() && self.root_table.is_some() {
+            self.root_table_insert(net, len);
+        }
+        replaced
+    }
+
+    // Points the root table at a newly stored prefix: a prefix that ends
+    // below the table only needs the node it's stored under, a shorter one
+    // is pushed into all the entries it covers, unless they already hold a
+    // longer prefix.
+    fn root_table_insert(&mut self, net: AF, len: u8) {
+        let bits = self.root_table.as_ref().unwrap().bits;
+        let slot = AF::get_nibble(net, 0, bits) as usize;
+        if len > bits {
+            let node_idx = self.root_node_index(net);
+            self.root_table.as_mut().unwrap().entries[slot].0 = node_idx;
+            return;
+        }
+        let pfx_idx = self.get_prefix_index(&Prefix::new(net, len)).unwrap();
+        let prefixes = &self.prefixes;
+        let table = self.root_table.as_mut().unwrap();
+        for entry in table.entries[slot..slot + (1 << (bits - len))].iter_mut() {
+            if entry.1 == NO_INDEX || prefixes[entry.1 as usize].as_ref().unwrap().len < len {
+                entry.1 = pfx_idx;
             }
         }
     }
 
+    // Brings the root table in line with the removal of the prefix with
+    // index `pfx_idx`: the entry for a prefix below the table loses its node
+    // if that got pruned, the entries that held a shorter one fall back on
+    // the next less-specific prefix.
+    fn root_table_remove(&mut self, net: AF, len: u8, pfx_idx: u32) {
+        let bits = self.root_table.as_ref().unwrap().bits;
+        let slot = AF::get_nibble(net, 0, bits) as usize;
+        if len > bits {
+            let node_idx = self.root_node_index(net);
+            self.root_table.as_mut().unwrap().entries[slot].0 = node_idx;
+            return;
+        }
+        let less_specific = self
+            .longest_prefix_index_from(&Prefix::new(net, len), 0, 0, 0, None)
+            .unwrap_or(NO_INDEX);
+        let table = self.root_table.as_mut().unwrap();
+        for entry in table.entries[slot..slot + (1 << (bits - len))].iter_mut() {
+            if entry.1 == pfx_idx {
+                entry.1 = less_specific;
+            }
+        }
+    }
+
+    // The index of the node that starts right after the root table on the
+    // path to `net`, or NO_INDEX if there isn't one. That is the node a
+    // prefix one bit longer than the table would be stored in.
+    fn root_node_index(&self, net: AF) -> u32 {
+        let bits = self.root_table.as_ref().unwrap().bits;
+        match self.node_index_for_prefix(&Prefix::new(net, bits + 1)) {
+            Some((node_idx, _, _)) => node_idx,
+            None => NO_INDEX,
+        }
+    }
+
     pub fn store_node(&mut self, next_node: SizedStrideNode<AF>) -> u32 {
+        if let Some(id) = self.free_nodes.pop() {
+            self.nodes[id as usize] = next_node;
+            return id;
+        }
         let id = self.nodes.len() as u32;
         self.nodes.push(next_node);
         id
@@ -1257,19 +1755,23 @@ where
     }
 
     pub fn store_prefix(&mut self, next_node: Prefix<AF, T>) -> u32 {
+        if let Some(id) = self.free_prefixes.pop() {
+            self.prefixes[id as usize] = Some(next_node);
+            return id;
+        }
         let id = self.prefixes.len() as u32;
-        self.prefixes.push(next_node);
+        self.prefixes.push(Some(next_node));
         id
     }
 
     #[inline]
     pub fn retrieve_prefix(&'a self, index: u32) -> Option<&'a Prefix<AF, T>> {
-        self.prefixes.get(index as usize)
+        self.prefixes.get(index as usize)?.as_ref()
     }
 
     #[inline]
     pub fn retrieve_prefix_mut(&mut self, index: u32) -> Option<&mut Prefix<AF, T>> {
-        self.prefixes.get_mut(index as usize)
+        self.prefixes.get_mut(index as usize)?.as_mut()
     }
 
     pub fn match_longest_prefix(
@@ -1277,7 +1779,7 @@ where
         search_pfx: &Prefix<AF, NoMeta>,
     ) -> Vec<&'a Prefix<AF, T>> {
         let mut stride_end = 0;
-        let mut found_pfx_idxs: Vec<(AF, u32)> = vec![];
+        let mut found_pfx_idxs: Vec<u32> = vec![];
         let mut node = self.retrieve_node(0).unwrap();
 
         for stride in self.strides.iter() {
@@ -1301,8 +1803,8 @@ where
             // until the value of the actual nibble length were looking for (until we reach
             // stride length for all strides that aren't the last) and see if the
             // prefix bit in that posision is set.
-            // Note that this does not search for prefixes with length 0 (which would always
-            // match).
+            // A prefix with length 0 (the default route) can only live in the root
+            // node and always matches.
             // So for matching a nibble 1010, we have to search for 1, 10, 101 and 1010 on
             // resp. position 1, 5, 12 and 25:
             //                       ↓          ↓                         ↓                                                              ↓
@@ -1325,7 +1827,7 @@ where
                         None => {
                             return found_pfx_idxs
                                 .into_iter()
-                                .map(|i| self.retrieve_prefix(i.1).unwrap())
+                                .map(|i| self.retrieve_prefix(i).unwrap())
                                 .collect();
                         }
                     }
@@ -1344,7 +1846,7 @@ where
                         None => {
                             return found_pfx_idxs
                                 .iter()
-                                .map(|i| self.retrieve_prefix(i.1).unwrap())
+                                .map(|i| self.retrieve_prefix(*i).unwrap())
                                 .collect();
                         }
                     }
@@ -1363,7 +1865,7 @@ where
                         None => {
                             return found_pfx_idxs
                                 .iter()
-                                .map(|i| self.retrieve_prefix(i.1).unwrap())
+                                .map(|i| self.retrieve_prefix(*i).unwrap())
                                 .collect();
                         }
                     }
@@ -1382,7 +1884,7 @@ where
                         None => {
                             return found_pfx_idxs
                                 .iter()
-                                .map(|i| self.retrieve_prefix(i.1).unwrap())
+                                .map(|i| self.retrieve_prefix(*i).unwrap())
                                 .collect();
                         }
                     }
@@ -1401,7 +1903,7 @@ where
                         None => {
                             return found_pfx_idxs
                                 .iter()
-                                .map(|i| self.retrieve_prefix(i.1).unwrap())
+                                .map(|i| self.retrieve_prefix(*i).unwrap())
                                 .collect();
                         }
                     }
@@ -1420,7 +1922,7 @@ where
                         None => {
                             return found_pfx_idxs
                                 .iter()
-                                .map(|i| self.retrieve_prefix(i.1).unwrap())
+                                .map(|i| self.retrieve_prefix(*i).unwrap())
                                 .collect();
                         }
                     }
@@ -1430,7 +1932,7 @@ where
 
         found_pfx_idxs
             .iter()
-            .map(|i| self.retrieve_prefix(i.1).unwrap())
+            .map(|i| self.retrieve_prefix(*i).unwrap())
             .collect()
     }
 
@@ -1438,11 +1940,45 @@ where
         &'a self,
         search_pfx: &Prefix<AF, NoMeta>,
     ) -> Option<&'a Prefix<AF, T>> {
-        let mut stride_end = 0;
-        let mut found_pfx_idx: Option<u32> = None;
-        let mut node = self.retrieve_node(0).unwrap();
-  
-        for stride in self.strides.iter() {
+        let pfx_idx = match &self.root_table {
+            // The root table entry holds the longest match up to its bits,
+            // the walk only has to cover the ones after that.
+            Some(table) if search_pfx.len >= table.bits => {
+                let (node_idx, pfx_idx) =
+                    table.entries[AF::get_nibble(search_pfx.net, 0, table.bits) as usize];
+                let pfx_idx = Some(pfx_idx).filter(|i| *i != NO_INDEX);
+                if search_pfx.len == table.bits || node_idx == NO_INDEX {
+                    pfx_idx
+                } else {
+                    self.longest_prefix_index_from(
+                        search_pfx,
+                        node_idx,
+                        table.level,
+                        table.bits,
+                        pfx_idx,
+                    )
+                }
+            }
+            _ => self.longest_prefix_index_from(search_pfx, 0, 0, 0, None),
+        };
+        pfx_idx.map(|i| self.retrieve_prefix(i).unwrap())
+    }
+
+    // The longest match walk from the node with index `node_idx`, which
+    // sits at `level` in the tree and starts at bit `stride_end`.
+    // `found_pfx_idx` is the longest match found above it, if any. Returns
+    // the index of the longest matching prefix.
+    fn longest_prefix_index_from(
+        &self,
+        search_pfx: &Prefix<AF, NoMeta>,
+        node_idx: u32,
+        level: usize,
+        mut stride_end: u8,
+        mut found_pfx_idx: Option<u32>,
+    ) -> Option<u32> {
+        let mut node = self.retrieve_node(node_idx).unwrap();
+
+        for stride in self.strides[level..].iter() {
             stride_end += stride;
 
             let nibble_len = if search_pfx.len < stride_end {
@@ -1463,8 +1999,8 @@ where
             // until the value of the actual nibble length were looking for (until we reach
             // stride length for all strides that aren't the last) and see if the
             // prefix bit in that posision is set.
-            // Note that this does not search for prefixes with length 0 (which would always
-            // match).
+            // A prefix with length 0 (the default route) can only live in the root
+            // node and always matches.
             // So for matching a nibble 1010, we have to search for 1, 10, 101 and 1010 on
             // resp. position 1, 5, 12 and 25:
             //                       ↓          ↓                         ↓                                                              ↓
@@ -1480,15 +2016,13 @@ where
                         stride_end - stride,
                     ) {
                         (Some(n), Some(pfx_idx)) => {
-                            found_pfx_idx = Some(pfx_idx.1);
+                            found_pfx_idx = Some(pfx_idx);
                             node = self.retrieve_node(n).unwrap();
                         }
                         (Some(n), None) => {
                             node = self.retrieve_node(n).unwrap();
                         }
-                        (None, Some(pfx_idx)) => {
-                            return Some(self.retrieve_prefix(pfx_idx.1).unwrap())
-                        }
+                        (None, Some(pfx_idx)) => return Some(pfx_idx),
                         (None, None) => {
                             break;
                         }
@@ -1502,15 +2036,13 @@ where
                         stride_end - stride,
                     ) {
                         (Some(n), Some(pfx_idx)) => {
-                            found_pfx_idx = Some(pfx_idx.1);
+                            found_pfx_idx = Some(pfx_idx);
                             node = self.retrieve_node(n).unwrap();
                         }
                         (Some(n), None) => {
                             node = self.retrieve_node(n).unwrap();
                         }
-                        (None, Some(pfx_idx)) => {
-                            return Some(self.retrieve_prefix(pfx_idx.1).unwrap())
-                        }
+                        (None, Some(pfx_idx)) => return Some(pfx_idx),
                         (None, None) => {
                             break;
                         }
@@ -1524,15 +2056,13 @@ where
                         stride_end - stride,
                     ) {
                         (Some(n), Some(pfx_idx)) => {
-                            found_pfx_idx = Some(pfx_idx.1);
+                            found_pfx_idx = Some(pfx_idx);
                             node = self.retrieve_node(n).unwrap();
                         }
                         (Some(n), None) => {
                             node = self.retrieve_node(n).unwrap();
                         }
-                        (None, Some(pfx_idx)) => {
-                            return Some(self.retrieve_prefix(pfx_idx.1).unwrap())
-                        }
+                        (None, Some(pfx_idx)) => return Some(pfx_idx),
                         (None, None) => {
                             break;
                         }
@@ -1546,15 +2076,13 @@ where
                         stride_end - stride,
                     ) {
                         (Some(n), Some(pfx_idx)) => {
-                            found_pfx_idx = Some(pfx_idx.1);
+                            found_pfx_idx = Some(pfx_idx);
                             node = self.retrieve_node(n).unwrap();
                         }
                         (Some(n), None) => {
                             node = self.retrieve_node(n).unwrap();
                         }
-                        (None, Some(pfx_idx)) => {
-                            return Some(self.retrieve_prefix(pfx_idx.1).unwrap())
-                        }
+                        (None, Some(pfx_idx)) => return Some(pfx_idx),
                         (None, None) => {
                             break;
                         }
@@ -1568,15 +2096,13 @@ where
                         stride_end - stride,
                     ) {
                         (Some(n), Some(pfx_idx)) => {
-                            found_pfx_idx = Some(pfx_idx.1);
+                            found_pfx_idx = Some(pfx_idx);
                             node = self.retrieve_node(n).unwrap();
                         }
                         (Some(n), None) => {
                             node = self.retrieve_node(n).unwrap();
                         }
-                        (None, Some(pfx_idx)) => {
-                            return Some(self.retrieve_prefix(pfx_idx.1).unwrap())
-                        }
+                        (None, Some(pfx_idx)) => return Some(pfx_idx),
                         (None, None) => {
                             break;
                         }
@@ -1590,15 +2116,13 @@ where
                         stride_end - stride,
                     ) {
                         (Some(n), Some(pfx_idx)) => {
-                            found_pfx_idx = Some(pfx_idx.1);
+                            found_pfx_idx = Some(pfx_idx);
                             node = self.retrieve_node(n).unwrap();
                         }
                         (Some(n), None) => {
                             node = self.retrieve_node(n).unwrap();
                         }
-                        (None, Some(pfx_idx)) => {
-                            return Some(self.retrieve_prefix(pfx_idx.1).unwrap())
-                        }
+                        (None, Some(pfx_idx)) => return Some(pfx_idx),
                         (None, None) => {
                             break;
                         }
@@ -1607,11 +2131,556 @@ where
             };
         }
 
-        if let Some(pfx_idx) = found_pfx_idx {
-            Some(self.retrieve_prefix(pfx_idx).unwrap())
+        found_pfx_idx
+    }
+
+    // Looks up the longest match for every address in `addrs` (as a host
+    // prefix) into the same position in `results`, like calling
+    // `match_longest_prefix_only` for each of them. The walks of
+    // BATCH_LANES addresses take turns one stride at a time, and the next
+    // node of a walk is prefetched when its turn ends, so that the other
+    // walks hide the latency of fetching it.
+    pub fn match_longest_prefix_batch(
+        &'a self,
+        addrs: &[AF],
+        results: &mut [Option<&'a Prefix<AF, T>>],
+    ) {
+        assert_eq!(
+            addrs.len(),
+            results.len(),
+            "the results don't line up with the addresses"
+        );
+        for (addrs, results) in addrs
+            .chunks(BATCH_LANES)
+            .zip(results.chunks_mut(BATCH_LANES))
+        {
+            self.match_longest_prefix_lanes(addrs, results);
+        }
+    }
+
+    fn match_longest_prefix_lanes(
+        &'a self,
+        addrs: &[AF],
+        results: &mut [Option<&'a Prefix<AF, T>>],
+    ) {
+        let start = Lane {
+            node_idx: 0,
+            level: 0,
+            start_bit: 0,
+            found_pfx_idx: None,
+        };
+        let mut lanes = [start; BATCH_LANES];
+        let lanes = &mut lanes[..addrs.len()];
+
+        for (lane, addr) in lanes.iter_mut().zip(addrs.iter()) {
+            if let Some(table) = &self.root_table {
+                let (node_idx, pfx_idx) =
+                    table.entries[AF::get_nibble(*addr, 0, table.bits) as usize];
+                *lane = Lane {
+                    node_idx,
+                    level: table.level,
+                    start_bit: table.bits,
+                    found_pfx_idx: Some(pfx_idx).filter(|i| *i != NO_INDEX),
+                };
+            }
+            if lane.node_idx != NO_INDEX {
+                prefetch(&self.nodes[lane.node_idx as usize]);
+            }
+        }
+
+        let mut active = lanes.iter().filter(|l| l.node_idx != NO_INDEX).count();
+        while active > 0 {
+            for (lane, addr) in lanes.iter_mut().zip(addrs.iter()) {
+                if lane.node_idx == NO_INDEX {
+                    continue;
+                }
+                let search_pfx = Prefix::<AF, NoMeta>::new(*addr, AF::BITS);
+                let stride = self.strides[lane.level];
+                let nibble = AF::get_nibble(*addr, lane.start_bit, stride);
+                let (next_node_idx, pfx_idx) = match &self.nodes[lane.node_idx as usize] {
+                    SizedStrideNode::Stride3(current_node) => current_node
+                        .search_stride_at_lmp_only(&search_pfx, nibble, stride, lane.start_bit),
+                    SizedStrideNode::Stride4(current_node) => current_node
+                        .search_stride_at_lmp_only(&search_pfx, nibble, stride, lane.start_bit),
+                    SizedStrideNode::Stride5(current_node) => current_node
+                        .search_stride_at_lmp_only(&search_pfx, nibble, stride, lane.start_bit),
+                    SizedStrideNode::Stride6(current_node) => current_node
+                        .search_stride_at_lmp_only(&search_pfx, nibble, stride, lane.start_bit),
+                    SizedStrideNode::Stride7(current_node) => current_node
+                        .search_stride_at_lmp_only(&search_pfx, nibble, stride, lane.start_bit),
+                    SizedStrideNode::Stride8(current_node) => current_node
+                        .search_stride_at_lmp_only(&search_pfx, nibble, stride, lane.start_bit),
+                };
+                if let Some(pfx_idx) = pfx_idx {
+                    lane.found_pfx_idx = Some(pfx_idx);
+                }
+                match next_node_idx {
+                    Some(node_idx) => {
+                        prefetch(&self.nodes[node_idx as usize]);
+                        lane.node_idx = node_idx;
+                        lane.level += 1;
+                        lane.start_bit += stride;
+                    }
+                    None => {
+                        lane.node_idx = NO_INDEX;
+                        active -= 1;
+                    }
+                }
+            }
+        }
+
+        for (result, lane) in results.iter_mut().zip(lanes.iter()) {
+            *result = lane
+                .found_pfx_idx
+                .map(|i| self.retrieve_prefix(i).unwrap());
+        }
+    }
+
+    // Looks up the prefix with exactly the network and length of
+    // `search_pfx`. Only the bit for the prefix in the node at its last
+    // stride is inspected, the strides before that are merely used to
+    // find that node.
+    pub fn get(&'a self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&'a Prefix<AF, T>> {
+        let pfx_idx = self.get_prefix_index(search_pfx)?;
+        self.retrieve_prefix(pfx_idx)
+    }
+
+    // Stores `pfx`, or merges its meta data into the stored prefix with the
+    // same network and length, see `Prefix::merge_meta`.
+    pub fn upsert<F>(&mut self, pfx: Prefix<AF, T>, merge: F)
+    where
+        F: FnOnce(&mut T, T),
+    {
+        match self.get_mut(&pfx.strip_meta()) {
+            Some(stored) => stored.merge_meta(pfx.meta, merge),
+            None => {
+                self.insert(pfx);
+            }
+        }
+    }
+
+    pub fn get_mut(&mut self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&mut Prefix<AF, T>> {
+        let pfx_idx = self.get_prefix_index(search_pfx)?;
+        self.retrieve_prefix_mut(pfx_idx)
+    }
+
+    // Answers a query for `search_pfx` in one go, `options` selects whether
+    // the less-specifics and more-specifics are collected as well. Without
+    // the less-specifics only the longest match is tracked on the way down.
+    pub fn match_prefix(
+        &'a self,
+        search_pfx: &Prefix<AF, NoMeta>,
+        options: &MatchOptions,
+    ) -> QueryResult<'a, AF, T> {
+        let (longest_match, less_specifics) = if options.include_less_specifics {
+            let mut less_specifics = self.match_longest_prefix(search_pfx);
+            let longest_match = less_specifics.last().copied();
+            if longest_match.is_some_and(|pfx| pfx.len == search_pfx.len) {
+                less_specifics.pop();
+            }
+            (longest_match, Some(less_specifics))
         } else {
-            None
+            (self.match_longest_prefix_only(search_pfx), None)
+        };
+
+        QueryResult {
+            exact_match: longest_match.filter(|pfx| pfx.len == search_pfx.len),
+            longest_match,
+            less_specifics,
+            more_specifics: if options.include_more_specifics {
+                Some(self.more_specifics(search_pfx).collect())
+            } else {
+                None
+            },
+        }
+    }
+
+    // Walks down to the node at the last stride for `search_pfx` and
+    // returns its index in the global `nodes` vec together with the nibble
+    // and nibble length of the prefix inside that node. Returns None if the
+    // path breaks off before that.
+    fn node_index_for_prefix(&self, search_pfx: &Prefix<AF, NoMeta>) -> Option<(u32, u32, u8)> {
+        let mut stride_end: u8 = 0;
+        let mut cur_i = 0;
+
+        for stride in self.strides.iter() {
+            stride_end += stride;
+
+            let nibble_len = if search_pfx.len < stride_end {
+                stride + search_pfx.len - stride_end
+            } else {
+                *stride
+            };
+
+            let nibble = AF::get_nibble(search_pfx.net, stride_end - stride, nibble_len);
+
+            if search_pfx.len <= stride_end {
+                return Some((cur_i, nibble, nibble_len));
+            }
+
+            cur_i = match self.retrieve_node(cur_i).unwrap() {
+                SizedStrideNode::Stride3(current_node) => current_node.child_index_at(nibble)?,
+                SizedStrideNode::Stride4(current_node) => current_node.child_index_at(nibble)?,
+                SizedStrideNode::Stride5(current_node) => current_node.child_index_at(nibble)?,
+                SizedStrideNode::Stride6(current_node) => current_node.child_index_at(nibble)?,
+                SizedStrideNode::Stride7(current_node) => current_node.child_index_at(nibble)?,
+                SizedStrideNode::Stride8(current_node) => current_node.child_index_at(nibble)?,
+            };
+        }
+        None
+    }
+
+    fn get_prefix_index(&self, search_pfx: &Prefix<AF, NoMeta>) -> Option<u32> {
+        let (node_idx, nibble, nibble_len) = self.node_index_for_prefix(search_pfx)?;
+        match self.retrieve_node(node_idx).unwrap() {
+            SizedStrideNode::Stride3(current_node) => current_node.prefix_index_at(nibble, nibble_len),
+            SizedStrideNode::Stride4(current_node) => current_node.prefix_index_at(nibble, nibble_len),
+            SizedStrideNode::Stride5(current_node) => current_node.prefix_index_at(nibble, nibble_len),
+            SizedStrideNode::Stride6(current_node) => current_node.prefix_index_at(nibble, nibble_len),
+            SizedStrideNode::Stride7(current_node) => current_node.prefix_index_at(nibble, nibble_len),
+            SizedStrideNode::Stride8(current_node) => current_node.prefix_index_at(nibble, nibble_len),
+        }
+    }
+
+    // Returns all the prefixes that are strictly more specific than
+    // `search_pfx`, i.e. the ones covered by it, but not `search_pfx`
+    // itself, in canonical order. Only the part of the node at the last
+    // stride for `search_pfx` below its nibble and the child nodes hanging
+    // off there are visited.
+    pub fn more_specifics(
+        &'a self,
+        search_pfx: &Prefix<AF, NoMeta>,
+    ) -> impl Iterator<Item = &'a Prefix<AF, T>> {
+        let mut entries = vec![];
+
+        if let Some((node_idx, nibble, nibble_len)) = self.node_index_for_prefix(search_pfx) {
+            match self.retrieve_node(node_idx).unwrap() {
+                SizedStrideNode::Stride3(current_node) => {
+                    current_node.more_specific_entries(nibble, nibble_len, &mut entries)
+                }
+                SizedStrideNode::Stride4(current_node) => {
+                    current_node.more_specific_entries(nibble, nibble_len, &mut entries)
+                }
+                SizedStrideNode::Stride5(current_node) => {
+                    current_node.more_specific_entries(nibble, nibble_len, &mut entries)
+                }
+                SizedStrideNode::Stride6(current_node) => {
+                    current_node.more_specific_entries(nibble, nibble_len, &mut entries)
+                }
+                SizedStrideNode::Stride7(current_node) => {
+                    current_node.more_specific_entries(nibble, nibble_len, &mut entries)
+                }
+                SizedStrideNode::Stride8(current_node) => {
+                    current_node.more_specific_entries(nibble, nibble_len, &mut entries)
+                }
+            };
         }
+
+        let mut pfxs = vec![];
+        self.expand_entries(entries, &mut pfxs);
+        pfxs.into_iter()
+    }
+
+    // Clears the prefix bit in the node at the last stride for this prefix,
+    // takes the prefix out of the global `prefixes` vec and hands it back.
+    // Nodes on the path that are left without prefixes and children are
+    // released. The vacated slots in `prefixes` and `nodes` are reused by
+    // subsequent inserts.
+    pub fn remove(&mut self, pfx: &Prefix<AF, NoMeta>) -> Option<Prefix<AF, T>> {
+        if self.root_table.is_none() {
+            return self.remove_from_tree(pfx);
+        }
+        let pfx_idx = self.get_prefix_index(pfx)?;
+        let removed = self.remove_from_tree(pfx);
+        self.root_table_remove(pfx.net, pfx.len, pfx_idx);
+        removed
+    }
+
+    fn remove_from_tree(&mut self, pfx: &Prefix<AF, NoMeta>) -> Option<Prefix<AF, T>> {
+        let mut stride_end: u8 = 0;
+        let mut cur_i = 0;
+        // The (node index, nibble) pairs we've descended through, so that we
+        // can unset the children that turn out empty on the way back up.
+        let mut path: Vec<(u32, u32)> = vec![];
+
+        for (level, stride) in self.strides.iter().enumerate() {
+            stride_end += stride;
+
+            let nibble_len = if pfx.len < stride_end {
+                stride + pfx.len - stride_end
+            } else {
+                *stride
+            };
+
+            let nibble = AF::get_nibble(pfx.net, stride_end - stride, nibble_len);
+
+            if pfx.len <= stride_end {
+                let (pfx_idx, stats_index) = match &mut self.nodes[cur_i as usize] {
+                    SizedStrideNode::Stride3(current_node) => {
+                        (current_node.remove_prefix_at(nibble, nibble_len), 0)
+                    }
+                    SizedStrideNode::Stride4(current_node) => {
+                        (current_node.remove_prefix_at(nibble, nibble_len), 1)
+                    }
+                    SizedStrideNode::Stride5(current_node) => {
+                        (current_node.remove_prefix_at(nibble, nibble_len), 2)
+                    }
+                    SizedStrideNode::Stride6(current_node) => {
+                        (current_node.remove_prefix_at(nibble, nibble_len), 3)
+                    }
+                    SizedStrideNode::Stride7(current_node) => {
+                        (current_node.remove_prefix_at(nibble, nibble_len), 4)
+                    }
+                    SizedStrideNode::Stride8(current_node) => {
+                        (current_node.remove_prefix_at(nibble, nibble_len), 5)
+                    }
+                };
+                let pfx_idx = pfx_idx?;
+                self.stats[stats_index].dec_prefix_count(level as u8);
+                let removed_pfx = self.prefixes[pfx_idx as usize].take().unwrap();
+                self.free_prefixes.push(pfx_idx);
+                self.prune_path(cur_i, path);
+                return Some(removed_pfx);
+            }
+
+            let child_i = match &self.nodes[cur_i as usize] {
+                SizedStrideNode::Stride3(current_node) => current_node.child_index_at(nibble)?,
+                SizedStrideNode::Stride4(current_node) => current_node.child_index_at(nibble)?,
+                SizedStrideNode::Stride5(current_node) => current_node.child_index_at(nibble)?,
+                SizedStrideNode::Stride6(current_node) => current_node.child_index_at(nibble)?,
+                SizedStrideNode::Stride7(current_node) => current_node.child_index_at(nibble)?,
+                SizedStrideNode::Stride8(current_node) => current_node.child_index_at(nibble)?,
+            };
+            path.push((cur_i, nibble));
+            cur_i = child_i;
+        }
+        None
+    }
+
+    // Walks back up from `node_idx` along `path`, releasing every node that
+    // has neither prefixes nor children and unsetting it in its parent.
+    // Stops at the first node that's still in use. The root node (the
+    // first node in `path`) is never released.
+    fn prune_path(&mut self, mut node_idx: u32, mut path: Vec<(u32, u32)>) {
+        while let Some((parent_idx, nibble)) = path.pop() {
+            // The level of `node_idx` is one deeper than its parent's.
+            let level = path.len() as u8 + 1;
+            let stats_index = match &self.nodes[node_idx as usize] {
+                SizedStrideNode::Stride3(current_node) if current_node.is_empty() => 0,
+                SizedStrideNode::Stride4(current_node) if current_node.is_empty() => 1,
+                SizedStrideNode::Stride5(current_node) if current_node.is_empty() => 2,
+                SizedStrideNode::Stride6(current_node) if current_node.is_empty() => 3,
+                SizedStrideNode::Stride7(current_node) if current_node.is_empty() => 4,
+                SizedStrideNode::Stride8(current_node) if current_node.is_empty() => 5,
+                _ => return,
+            };
+            self.stats[stats_index].dec(level);
+            self.nodes[node_idx as usize] = SizedStrideNode::default();
+            self.free_nodes.push(node_idx);
+
+            match &mut self.nodes[parent_idx as usize] {
+                SizedStrideNode::Stride3(current_node) => current_node.remove_child_at(nibble),
+                SizedStrideNode::Stride4(current_node) => current_node.remove_child_at(nibble),
+                SizedStrideNode::Stride5(current_node) => current_node.remove_child_at(nibble),
+                SizedStrideNode::Stride6(current_node) => current_node.remove_child_at(nibble),
+                SizedStrideNode::Stride7(current_node) => current_node.remove_child_at(nibble),
+                SizedStrideNode::Stride8(current_node) => current_node.remove_child_at(nibble),
+            };
+            node_idx = parent_idx;
+        }
+    }
+
+    // Lays the `nodes` and `prefixes` vecs out again breadth-first, so that
+    // the children of a node sit next to each other in `nodes`, just like
+    // its prefixes in `prefixes`. The nodes then find them from a base index
+    // plus the index in their bitmap and their own vecs are dropped, which
+    // saves a memory access per level on a lookup. The vacated slots are
+    // dropped as well. A node that gets changed afterwards moves back to its
+    // vecs, compacting again brings it back in line.
+    pub fn compact(&mut self) {
+        let mut old_nodes = std::mem::take(&mut self.nodes);
+        let mut old_prefixes = std::mem::take(&mut self.prefixes);
+        let mut new_node_idxs = vec![NO_INDEX; old_nodes.len()];
+        let mut new_pfx_idxs = vec![NO_INDEX; old_prefixes.len()];
+        let mut prefixes = Vec::with_capacity(old_prefixes.len() - self.free_prefixes.len());
+
+        // The old indexes of the nodes in their new order, which is also the
+        // queue for the breadth-first walk.
+        let mut order: Vec<u32> = vec![0];
+        new_node_idxs[0] = 0;
+        let mut i = 0;
+        while i < order.len() {
+            let node = &mut old_nodes[order[i] as usize];
+            let (children, pfxs) = match node {
+                SizedStrideNode::Stride3(n) => (n.child_indexes(), n.prefix_indexes()),
+                SizedStrideNode::Stride4(n) => (n.child_indexes(), n.prefix_indexes()),
+                SizedStrideNode::Stride5(n) => (n.child_indexes(), n.prefix_indexes()),
+                SizedStrideNode::Stride6(n) => (n.child_indexes(), n.prefix_indexes()),
+                SizedStrideNode::Stride7(n) => (n.child_indexes(), n.prefix_indexes()),
+                SizedStrideNode::Stride8(n) => (n.child_indexes(), n.prefix_indexes()),
+            };
+            let ptr_base = order.len() as u32;
+            let pfx_base = prefixes.len() as u32;
+            for child_idx in children {
+                new_node_idxs[child_idx as usize] = order.len() as u32;
+                order.push(child_idx);
+            }
+            for pfx_idx in pfxs {
+                new_pfx_idxs[pfx_idx as usize] = prefixes.len() as u32;
+                prefixes.push(old_prefixes[pfx_idx as usize].take());
+            }
+            match node {
+                SizedStrideNode::Stride3(n) => n.pack(ptr_base, pfx_base),
+                SizedStrideNode::Stride4(n) => n.pack(ptr_base, pfx_base),
+                SizedStrideNode::Stride5(n) => n.pack(ptr_base, pfx_base),
+                SizedStrideNode::Stride6(n) => n.pack(ptr_base, pfx_base),
+                SizedStrideNode::Stride7(n) => n.pack(ptr_base, pfx_base),
+                SizedStrideNode::Stride8(n) => n.pack(ptr_base, pfx_base),
+            };
+            i += 1;
+        }
+
+        self.nodes = order
+            .iter()
+            .map(|i| std::mem::take(&mut old_nodes[*i as usize]))
+            .collect();
+        self.prefixes = prefixes;
+        self.free_nodes.clear();
+        self.free_prefixes.clear();
+
+        if let Some(table) = &mut self.root_table {
+            for entry in table.entries.iter_mut() {
+                if entry.0 != NO_INDEX {
+                    entry.0 = new_node_idxs[entry.0 as usize];
+                }
+                if entry.1 != NO_INDEX {
+                    entry.1 = new_pfx_idxs[entry.1 as usize];
+                }
+            }
+        }
+    }
+
+    fn collect_prefixes(&'a self, node_idx: u32, pfxs: &mut Vec<&'a Prefix<AF, T>>) {
+        let mut entries = vec![];
+        match self.retrieve_node(node_idx).unwrap() {
+            SizedStrideNode::Stride3(current_node) => current_node.collect_entries(0, 0, &mut entries),
+            SizedStrideNode::Stride4(current_node) => current_node.collect_entries(0, 0, &mut entries),
+            SizedStrideNode::Stride5(current_node) => current_node.collect_entries(0, 0, &mut entries),
+            SizedStrideNode::Stride6(current_node) => current_node.collect_entries(0, 0, &mut entries),
+            SizedStrideNode::Stride7(current_node) => current_node.collect_entries(0, 0, &mut entries),
+            SizedStrideNode::Stride8(current_node) => current_node.collect_entries(0, 0, &mut entries),
+        }
+        self.expand_entries(entries, pfxs);
+    }
+
+    // Turns the entries of a node into prefixes, child nodes are replaced
+    // by all of their prefixes in place, so the order is preserved.
+    fn expand_entries(&'a self, entries: Vec<NodeEntry>, pfxs: &mut Vec<&'a Prefix<AF, T>>) {
+        for entry in entries {
+            match entry {
+                NodeEntry::Prefix(pfx_idx) => pfxs.push(self.retrieve_prefix(pfx_idx).unwrap()),
+                NodeEntry::Child(child_idx) => self.collect_prefixes(child_idx, pfxs),
+            }
+        }
+    }
+
+    // Iterates over all the stored prefixes in canonical order, i.e.
+    // ordered by network address first and prefix length second. Unlike
+    // the `prefixes` vec this skips vacated slots.
+    pub fn iter(&'a self) -> std::vec::IntoIter<&'a Prefix<AF, T>> {
+        let mut pfxs = vec![];
+        self.collect_prefixes(0, &mut pfxs);
+        pfxs.into_iter()
+    }
+}
+
+// The multi-value mode: every prefix holds a value per source, which are
+// announced and withdrawn one source at a time. A prefix is stored as long
+// as at least one source has a value for it.
+impl<AF, K, V> TreeBitMap<AF, MultiMeta<K, V>>
+where
+    K: Ord + Debug,
+    V: Debug,
+    AF: AddressFamily + Debug + From<u32>,
+{
+    // Sets the value of `source` for `pfx`, leaving the values of the other
+    // sources alone, and hands back the previous value of `source`.
+    pub fn add_value(&mut self, pfx: &Prefix<AF, NoMeta>, source: K, value: V) -> Option<V> {
+        if let Some(stored) = self.get_mut(pfx) {
+            return stored
+                .meta
+                .get_or_insert_with(MultiMeta::new)
+                .insert(source, value);
+        }
+        let mut meta = MultiMeta::new();
+        meta.insert(source, value);
+        self.insert(Prefix::new_with_meta(pfx.net, pfx.len, meta));
+        None
+    }
+
+    // Takes the value of `source` out of `pfx`, leaving the values of the
+    // other sources alone. Removes the prefix once no values are left.
+    pub fn withdraw_value(&mut self, pfx: &Prefix<AF, NoMeta>, source: &K) -> Option<V> {
+        let stored = self.get_mut(pfx)?;
+        let value = stored.meta.as_mut()?.remove(source)?;
+        if stored.meta.as_ref().is_some_and(|meta| meta.is_empty()) {
+            self.remove(pfx);
+        }
+        Some(value)
+    }
+
+    pub fn get_value(&self, pfx: &Prefix<AF, NoMeta>, source: &K) -> Option<&V> {
+        self.get(pfx)?.meta.as_ref()?.get(source)
+    }
+}
+
+impl<AF, T> PrefixStore<AF, T> for TreeBitMap<AF, T>
+where
+    T: Debug,
+    AF: AddressFamily + Debug + From<u32>,
+{
+    fn insert(&mut self, pfx: Prefix<AF, T>) -> Option<Prefix<AF, T>> {
+        TreeBitMap::insert(self, pfx)
+    }
+
+    fn upsert<F>(&mut self, pfx: Prefix<AF, T>, merge: F)
+    where
+        F: FnOnce(&mut T, T),
+    {
+        TreeBitMap::upsert(self, pfx, merge)
+    }
+
+    fn get(&self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&Prefix<AF, T>> {
+        TreeBitMap::get(self, search_pfx)
+    }
+
+    fn get_mut(&mut self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&mut Prefix<AF, T>> {
+        TreeBitMap::get_mut(self, search_pfx)
+    }
+
+    fn match_longest_prefix(&self, search_pfx: &Prefix<AF, NoMeta>) -> Option<&Prefix<AF, T>> {
+        TreeBitMap::match_longest_prefix_only(self, search_pfx)
+    }
+
+    fn remove(&mut self, pfx: &Prefix<AF, NoMeta>) -> Option<Prefix<AF, T>> {
+        TreeBitMap::remove(self, pfx)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Prefix<AF, T>> + 'a> {
+        Box::new(TreeBitMap::iter(self))
+    }
+}
+
+impl<'a, AF, T> IntoIterator for &'a TreeBitMap<AF, T>
+where
+    T: Debug,
+    AF: AddressFamily + Debug + From<u32>,
+{
+    type Item = &'a Prefix<AF, T>;
+    type IntoIter = std::vec::IntoIter<&'a Prefix<AF, T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
     }
 }
 
@@ -1710,9 +2779,17 @@ impl StrideStats {
         self.created_nodes[depth_level as usize].count += 1;
     }
 
+    fn dec(&mut self, depth_level: u8) {
+        self.created_nodes[depth_level as usize].count -= 1;
+    }
+
     fn inc_prefix_count(&mut self, depth_level: u8) {
         self.prefixes_num[depth_level as usize].count += 1;
     }
+
+    fn dec_prefix_count(&mut self, depth_level: u8) {
+        self.prefixes_num[depth_level as usize].count -= 1;
+    }
 }
 
 impl Debug for StrideStats {
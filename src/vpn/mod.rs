@@ -0,0 +1,865 @@
+use crate::common::{AddressFamily, PrefixParseError};
+use num::{
+    Bounded, CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, Num, One, PrimInt,
+    Saturating, ToPrimitive, Zero,
+};
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Not, Rem, Shl, Shr, Sub};
+
+// Lookup key for VPNv4 routes (RFC 4364): the 64 bit route distinguisher
+// followed by the 32 bit IPv4 address, 96 bits in all. A prefix on these
+// keys is a route distinguisher plus an IPv4 prefix, so one TreeBitMap can
+// hold the routes of many VRFs, and a longest prefix match never leaves
+// the route distinguisher it started in. In text the prefix length counts
+// from the start of the IPv4 address: "65000:1:10.0.0.0/8".
+//
+// The key lives in the lower 96 bits of a u128 and all the arithmetic
+// wraps around at 96 bits. See `Vpnv6` for the VPNv6 keys.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Vpnv4(u128);
+
+const MASK: u128 = (1 << 96) - 1;
+const RD_BITS: u8 = 64;
+
+impl Vpnv4 {
+    pub fn new(rd: u64, addr: Ipv4Addr) -> Vpnv4 {
+        Vpnv4((rd as u128) << 32 | u32::from(addr) as u128)
+    }
+
+    pub fn rd(self) -> u64 {
+        (self.0 >> 32) as u64
+    }
+
+    pub fn addr(self) -> Ipv4Addr {
+        Ipv4Addr::from(self.0 as u32)
+    }
+
+    // Drops whatever an operation carried past the 96 bits.
+    fn wrap(n: u128) -> Vpnv4 {
+        Vpnv4(n & MASK)
+    }
+}
+
+// Writes a route distinguisher the usual way for its type: "65000:1" for
+// type 0 (2 byte AS number), "192.0.2.1:1" for type 1 (IPv4 address) and
+// "4200000000:1" for type 2 (4 byte AS number), with an "L" after AS numbers
+// that would fit in 2 bytes, "65000L:1". Other types are written as a hex
+// number.
+pub fn fmt_rd(rd: u64) -> String {
+    let value = rd & 0xffff_ffff_ffff;
+    match rd >> 48 {
+        0 => format!("{}:{}", value >> 32, value & 0xffff_ffff),
+        1 => format!("{}:{}", Ipv4Addr::from((value >> 16) as u32), value & 0xffff),
+        2 if value >> 16 > 0xffff => format!("{}:{}", value >> 16, value & 0xffff),
+        2 => format!("{}L:{}", value >> 16, value & 0xffff),
+        _ => format!("{:#x}", rd),
+    }
+}
+
+// Splits "65000:1:10.0.0.0" into the route distinguisher and the address
+// behind it, None if there's only a route distinguisher. A route
+// distinguisher has one colon, or none if it's written as a hex number.
+fn split_rd(s: &str) -> Option<(&str, &str)> {
+    let end = match s.find(':')? {
+        colon if s.starts_with("0x") => colon,
+        colon => colon + 1 + s[colon + 1..].find(':')?,
+    };
+    Some((&s[..end], &s[end + 1..]))
+}
+
+// The reverse of `fmt_rd`.
+pub fn parse_rd(s: &str) -> Result<u64, PrefixParseError> {
+    if let Some(hex) = s.strip_prefix("0x") {
+        return u64::from_str_radix(hex, 16)
+            .map_err(|_| PrefixParseError::InvalidRouteDistinguisher);
+    }
+    let (admin, assigned) = s
+        .split_once(':')
+        .ok_or(PrefixParseError::InvalidRouteDistinguisher)?;
+
+    if let Ok(addr) = admin.parse::<Ipv4Addr>() {
+        let assigned = assigned
+            .parse::<u16>()
+            .map_err(|_| PrefixParseError::InvalidRouteDistinguisher)?;
+        return Ok(1 << 48 | (u32::from(addr) as u64) << 16 | assigned as u64);
+    }
+
+    let (asn, four_byte) = match admin.strip_suffix('L') {
+        Some(asn) => (asn, true),
+        None => (admin, false),
+    };
+    let asn = asn
+        .parse::<u32>()
+        .map_err(|_| PrefixParseError::InvalidRouteDistinguisher)?;
+    if asn <= 0xffff && !four_byte {
+        let assigned = assigned
+            .parse::<u32>()
+            .map_err(|_| PrefixParseError::InvalidRouteDistinguisher)?;
+        Ok((asn as u64) << 32 | assigned as u64)
+    } else {
+        let assigned = assigned
+            .parse::<u16>()
+            .map_err(|_| PrefixParseError::InvalidRouteDistinguisher)?;
+        Ok(2 << 48 | (asn as u64) << 16 | assigned as u64)
+    }
+}
+
+impl AddressFamily for Vpnv4 {
+    const BITMASK: Vpnv4 = Vpnv4(1 << 95);
+    const BITS: u8 = 96;
+
+    fn fmt_net(net: Self) -> String {
+        format!("{}:{}", fmt_rd(net.rd()), net.addr())
+    }
+
+    fn parse_net(s: &str) -> Result<Self, PrefixParseError> {
+        let (rd, addr) = split_rd(s).ok_or(PrefixParseError::InvalidRouteDistinguisher)?;
+        let addr = addr
+            .parse::<Ipv4Addr>()
+            .map_err(PrefixParseError::InvalidAddress)?;
+        Ok(Vpnv4::new(parse_rd(rd)?, addr))
+    }
+
+    fn get_nibble(net: Self, start_bit: u8, len: u8) -> u32 {
+        (((net.0 << start_bit) & MASK) >> ((96 - len) % 96)) as u32
+    }
+
+    // A prefix that ends inside the route distinguisher spans more than
+    // one VPN, it's written as just the route distinguisher with the length
+    // in bits of that: "65000:0/48".
+    fn fmt_prefix(net: Self, len: u8) -> String {
+        match len.checked_sub(RD_BITS) {
+            Some(len) => format!("{}/{}", Self::fmt_net(net), len),
+            None => format!("{}/{}", fmt_rd(net.rd()), len),
+        }
+    }
+
+    fn parse_prefix(s: &str) -> Result<(Self, u8), PrefixParseError> {
+        let (addr, len) = s.split_once('/').ok_or(PrefixParseError::MissingLength)?;
+        let len = len.parse::<u32>().map_err(|_| PrefixParseError::InvalidLength)?;
+
+        if split_rd(addr).is_some() {
+            if len > 32 {
+                return Err(PrefixParseError::LengthOutOfRange(len));
+            }
+            Ok((Self::parse_net(addr)?, RD_BITS + len as u8))
+        } else {
+            if len > RD_BITS as u32 {
+                return Err(PrefixParseError::LengthOutOfRange(len));
+            }
+            Ok((Vpnv4::new(parse_rd(addr)?, Ipv4Addr::UNSPECIFIED), len as u8))
+        }
+    }
+}
+
+impl fmt::Debug for Vpnv4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&Self::fmt_net(*self))
+    }
+}
+
+impl fmt::Binary for Vpnv4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Binary::fmt(&self.0, f)
+    }
+}
+
+impl From<u32> for Vpnv4 {
+    fn from(n: u32) -> Self {
+        Vpnv4(n as u128)
+    }
+}
+
+// The traits that make up `PrimInt`, all for a 96 bit unsigned integer.
+
+impl Zero for Vpnv4 {
+    fn zero() -> Self {
+        Vpnv4(0)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl One for Vpnv4 {
+    fn one() -> Self {
+        Vpnv4(1)
+    }
+}
+
+impl Add for Vpnv4 {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Vpnv4::wrap(self.0.wrapping_add(rhs.0))
+    }
+}
+
+impl Sub for Vpnv4 {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Vpnv4::wrap(self.0.wrapping_sub(rhs.0))
+    }
+}
+
+impl Mul for Vpnv4 {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Vpnv4::wrap(self.0.wrapping_mul(rhs.0))
+    }
+}
+
+impl Div for Vpnv4 {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        Vpnv4(self.0 / rhs.0)
+    }
+}
+
+impl Rem for Vpnv4 {
+    type Output = Self;
+    fn rem(self, rhs: Self) -> Self {
+        Vpnv4(self.0 % rhs.0)
+    }
+}
+
+impl Num for Vpnv4 {
+    type FromStrRadixErr = std::num::ParseIntError;
+    fn from_str_radix(s: &str, radix: u32) -> Result<Self, Self::FromStrRadixErr> {
+        u128::from_str_radix(s, radix).map(|n| Vpnv4(n & MASK))
+    }
+}
+
+impl Bounded for Vpnv4 {
+    fn min_value() -> Self {
+        Vpnv4(0)
+    }
+
+    fn max_value() -> Self {
+        Vpnv4(MASK)
+    }
+}
+
+impl ToPrimitive for Vpnv4 {
+    fn to_i64(&self) -> Option<i64> {
+        self.0.to_i64()
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        self.0.to_u64()
+    }
+
+    fn to_u128(&self) -> Option<u128> {
+        Some(self.0)
+    }
+}
+
+impl num::NumCast for Vpnv4 {
+    fn from<N: ToPrimitive>(n: N) -> Option<Self> {
+        n.to_u128().filter(|n| *n <= MASK).map(Vpnv4)
+    }
+}
+
+impl Not for Vpnv4 {
+    type Output = Self;
+    fn not(self) -> Self {
+        Vpnv4(!self.0 & MASK)
+    }
+}
+
+impl BitAnd for Vpnv4 {
+    type Output = Self;
+    fn bitand(self, rhs: Self) -> Self {
+        Vpnv4(self.0 & rhs.0)
+    }
+}
+
+impl BitOr for Vpnv4 {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self {
+        Vpnv4(self.0 | rhs.0)
+    }
+}
+
+impl BitXor for Vpnv4 {
+    type Output = Self;
+    fn bitxor(self, rhs: Self) -> Self {
+        Vpnv4(self.0 ^ rhs.0)
+    }
+}
+
+impl Shl<usize> for Vpnv4 {
+    type Output = Self;
+    fn shl(self, rhs: usize) -> Self {
+        Vpnv4((self.0 << rhs) & MASK)
+    }
+}
+
+impl Shr<usize> for Vpnv4 {
+    type Output = Self;
+    fn shr(self, rhs: usize) -> Self {
+        Vpnv4(self.0 >> rhs)
+    }
+}
+
+impl CheckedAdd for Vpnv4 {
+    fn checked_add(&self, v: &Self) -> Option<Self> {
+        self.0.checked_add(v.0).filter(|n| *n <= MASK).map(Vpnv4)
+    }
+}
+
+impl CheckedSub for Vpnv4 {
+    fn checked_sub(&self, v: &Self) -> Option<Self> {
+        self.0.checked_sub(v.0).map(Vpnv4)
+    }
+}
+
+impl CheckedMul for Vpnv4 {
+    fn checked_mul(&self, v: &Self) -> Option<Self> {
+        self.0.checked_mul(v.0).filter(|n| *n <= MASK).map(Vpnv4)
+    }
+}
+
+impl CheckedDiv for Vpnv4 {
+    fn checked_div(&self, v: &Self) -> Option<Self> {
+        self.0.checked_div(v.0).map(Vpnv4)
+    }
+}
+
+impl Saturating for Vpnv4 {
+    fn saturating_add(self, v: Self) -> Self {
+        Vpnv4(std::cmp::min(self.0 + v.0, MASK))
+    }
+
+    fn saturating_sub(self, v: Self) -> Self {
+        Vpnv4(self.0.saturating_sub(v.0))
+    }
+}
+
+impl PrimInt for Vpnv4 {
+    fn count_ones(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    fn count_zeros(self) -> u32 {
+        96 - self.0.count_ones()
+    }
+
+    fn leading_zeros(self) -> u32 {
+        self.0.leading_zeros() - 32
+    }
+
+    fn trailing_zeros(self) -> u32 {
+        std::cmp::min(self.0.trailing_zeros(), 96)
+    }
+
+    fn rotate_left(self, n: u32) -> Self {
+        let n = n % 96;
+        Vpnv4(((self.0 << n) | (self.0 >> (96 - n))) & MASK)
+    }
+
+    fn rotate_right(self, n: u32) -> Self {
+        self.rotate_left(96 - n % 96)
+    }
+
+    fn signed_shl(self, n: u32) -> Self {
+        self << n as usize
+    }
+
+    fn signed_shr(self, n: u32) -> Self {
+        if self.0 & Self::BITMASK.0 == 0 {
+            return self >> n as usize;
+        }
+        Vpnv4((self.0 >> n) | (MASK & !(MASK >> n)))
+    }
+
+    fn unsigned_shl(self, n: u32) -> Self {
+        self << n as usize
+    }
+
+    fn unsigned_shr(self, n: u32) -> Self {
+        self >> n as usize
+    }
+
+    fn swap_bytes(self) -> Self {
+        Vpnv4(self.0.swap_bytes() >> 32)
+    }
+
+    fn from_be(x: Self) -> Self {
+        x.to_be()
+    }
+
+    fn from_le(x: Self) -> Self {
+        x.to_le()
+    }
+
+    fn to_be(self) -> Self {
+        if cfg!(target_endian = "big") {
+            self
+        } else {
+            self.swap_bytes()
+        }
+    }
+
+    fn to_le(self) -> Self {
+        if cfg!(target_endian = "little") {
+            self
+        } else {
+            self.swap_bytes()
+        }
+    }
+
+    fn pow(self, exp: u32) -> Self {
+        Vpnv4(self.0.wrapping_pow(exp) & MASK)
+    }
+}
+
+// Lookup key for VPNv6 routes (RFC 4659): the 64 bit route distinguisher
+// followed by the 128 bit IPv6 address, 192 bits in all, which is wider
+// than any primitive integer. Written and parsed like `Vpnv4`, with the
+// prefix length counting from the start of the IPv6 address:
+// "65000:1:2001:db8::/32".
+//
+// Ordered, like the integer it stands for, by route distinguisher first
+// and address second.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Vpnv6 {
+    rd: u64,
+    addr: u128,
+}
+
+impl Vpnv6 {
+    pub fn new(rd: u64, addr: Ipv6Addr) -> Vpnv6 {
+        Vpnv6 {
+            rd,
+            addr: u128::from(addr),
+        }
+    }
+
+    pub fn rd(self) -> u64 {
+        self.rd
+    }
+
+    pub fn addr(self) -> Ipv6Addr {
+        Ipv6Addr::from(self.addr)
+    }
+
+    fn bit(self, n: usize) -> bool {
+        (self >> n).addr & 1 == 1
+    }
+
+    // Long division, one bit at a time.
+    fn div_rem(self, rhs: Self) -> (Self, Self) {
+        assert!(!rhs.is_zero(), "attempt to divide by zero");
+        let mut quotient = Vpnv6::zero();
+        let mut rem = Vpnv6::zero();
+        for n in (0..192).rev() {
+            // Shifting a remainder with the top bit set overflows, it's
+            // larger than `rhs` then for sure.
+            let carry = rem.bit(191);
+            rem = rem << 1;
+            rem.addr |= self.bit(n) as u128;
+            quotient = quotient << 1;
+            if carry || rem >= rhs {
+                rem = rem - rhs;
+                quotient.addr |= 1;
+            }
+        }
+        (quotient, rem)
+    }
+}
+
+impl AddressFamily for Vpnv6 {
+    const BITMASK: Vpnv6 = Vpnv6 { rd: 1 << 63, addr: 0 };
+    const BITS: u8 = 192;
+
+    fn fmt_net(net: Self) -> String {
+        format!("{}:{}", fmt_rd(net.rd()), net.addr())
+    }
+
+    fn parse_net(s: &str) -> Result<Self, PrefixParseError> {
+        let (rd, addr) = split_rd(s).ok_or(PrefixParseError::InvalidRouteDistinguisher)?;
+        let addr = addr
+            .parse::<Ipv6Addr>()
+            .map_err(PrefixParseError::InvalidAddress)?;
+        Ok(Vpnv6::new(parse_rd(rd)?, addr))
+    }
+
+    fn get_nibble(net: Self, start_bit: u8, len: u8) -> u32 {
+        ((net << start_bit as usize) >> ((192 - len as usize) % 192)).addr as u32
+    }
+
+    fn fmt_prefix(net: Self, len: u8) -> String {
+        match len.checked_sub(RD_BITS) {
+            Some(len) => format!("{}/{}", Self::fmt_net(net), len),
+            None => format!("{}/{}", fmt_rd(net.rd()), len),
+        }
+    }
+
+    fn parse_prefix(s: &str) -> Result<(Self, u8), PrefixParseError> {
+        let (addr, len) = s.split_once('/').ok_or(PrefixParseError::MissingLength)?;
+        let len = len.parse::<u32>().map_err(|_| PrefixParseError::InvalidLength)?;
+
+        if split_rd(addr).is_some() {
+            if len > 128 {
+                return Err(PrefixParseError::LengthOutOfRange(len));
+            }
+            Ok((Self::parse_net(addr)?, RD_BITS + len as u8))
+        } else {
+            if len > RD_BITS as u32 {
+                return Err(PrefixParseError::LengthOutOfRange(len));
+            }
+            Ok((Vpnv6::new(parse_rd(addr)?, Ipv6Addr::UNSPECIFIED), len as u8))
+        }
+    }
+}
+
+impl fmt::Debug for Vpnv6 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&Self::fmt_net(*self))
+    }
+}
+
+impl fmt::Binary for Vpnv6 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.rd == 0 {
+            return fmt::Binary::fmt(&self.addr, f);
+        }
+        write!(f, "{:b}{:0128b}", self.rd, self.addr)
+    }
+}
+
+impl From<u32> for Vpnv6 {
+    fn from(n: u32) -> Self {
+        Vpnv6 {
+            rd: 0,
+            addr: n as u128,
+        }
+    }
+}
+
+// The traits that make up `PrimInt`, all for a 192 bit unsigned integer.
+
+impl Zero for Vpnv6 {
+    fn zero() -> Self {
+        Vpnv6 { rd: 0, addr: 0 }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.rd == 0 && self.addr == 0
+    }
+}
+
+impl One for Vpnv6 {
+    fn one() -> Self {
+        Vpnv6 { rd: 0, addr: 1 }
+    }
+}
+
+impl Add for Vpnv6 {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        let (addr, carry) = self.addr.overflowing_add(rhs.addr);
+        Vpnv6 {
+            rd: self.rd.wrapping_add(rhs.rd).wrapping_add(carry as u64),
+            addr,
+        }
+    }
+}
+
+impl Sub for Vpnv6 {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        let (addr, borrow) = self.addr.overflowing_sub(rhs.addr);
+        Vpnv6 {
+            rd: self.rd.wrapping_sub(rhs.rd).wrapping_sub(borrow as u64),
+            addr,
+        }
+    }
+}
+
+// Shift and add.
+impl Mul for Vpnv6 {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        (0..192)
+            .filter(|n| rhs.bit(*n))
+            .fold(Vpnv6::zero(), |product, n| product + (self << n))
+    }
+}
+
+impl Div for Vpnv6 {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        self.div_rem(rhs).0
+    }
+}
+
+impl Rem for Vpnv6 {
+    type Output = Self;
+    fn rem(self, rhs: Self) -> Self {
+        self.div_rem(rhs).1
+    }
+}
+
+impl Num for Vpnv6 {
+    type FromStrRadixErr = std::num::ParseIntError;
+    // Whatever doesn't fit in 192 bits doesn't fit in a u128 either, so the
+    // errors are the ones of u128.
+    fn from_str_radix(s: &str, radix: u32) -> Result<Self, Self::FromStrRadixErr> {
+        let radix_n = Vpnv6::from(radix);
+        s.chars()
+            .try_fold(Vpnv6::zero(), |n, c| {
+                n.checked_mul(&radix_n)?
+                    .checked_add(&Vpnv6::from(c.to_digit(radix)?))
+            })
+            .filter(|_| !s.is_empty())
+            .ok_or_else(|| u128::from_str_radix(s, radix).unwrap_err())
+    }
+}
+
+impl Bounded for Vpnv6 {
+    fn min_value() -> Self {
+        Vpnv6::zero()
+    }
+
+    fn max_value() -> Self {
+        Vpnv6 {
+            rd: u64::MAX,
+            addr: u128::MAX,
+        }
+    }
+}
+
+impl ToPrimitive for Vpnv6 {
+    fn to_i64(&self) -> Option<i64> {
+        self.to_u128()?.to_i64()
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        self.to_u128()?.to_u64()
+    }
+
+    fn to_u128(&self) -> Option<u128> {
+        Some(self.addr).filter(|_| self.rd == 0)
+    }
+}
+
+impl num::NumCast for Vpnv6 {
+    fn from<N: ToPrimitive>(n: N) -> Option<Self> {
+        n.to_u128().map(|addr| Vpnv6 { rd: 0, addr })
+    }
+}
+
+impl Not for Vpnv6 {
+    type Output = Self;
+    fn not(self) -> Self {
+        Vpnv6 {
+            rd: !self.rd,
+            addr: !self.addr,
+        }
+    }
+}
+
+impl BitAnd for Vpnv6 {
+    type Output = Self;
+    fn bitand(self, rhs: Self) -> Self {
+        Vpnv6 {
+            rd: self.rd & rhs.rd,
+            addr: self.addr & rhs.addr,
+        }
+    }
+}
+
+impl BitOr for Vpnv6 {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self {
+        Vpnv6 {
+            rd: self.rd | rhs.rd,
+            addr: self.addr | rhs.addr,
+        }
+    }
+}
+
+impl BitXor for Vpnv6 {
+    type Output = Self;
+    fn bitxor(self, rhs: Self) -> Self {
+        Vpnv6 {
+            rd: self.rd ^ rhs.rd,
+            addr: self.addr ^ rhs.addr,
+        }
+    }
+}
+
+impl Shl<usize> for Vpnv6 {
+    type Output = Self;
+    fn shl(self, rhs: usize) -> Self {
+        // The bits that move from the address into the route distinguisher.
+        let carry = match rhs {
+            0 => 0,
+            1..=128 => (self.addr >> (128 - rhs)) as u64,
+            129..=191 => (self.addr << (rhs - 128)) as u64,
+            _ => return Vpnv6::zero(),
+        };
+        Vpnv6 {
+            rd: self.rd.checked_shl(rhs as u32).unwrap_or(0) | carry,
+            addr: self.addr.checked_shl(rhs as u32).unwrap_or(0),
+        }
+    }
+}
+
+impl Shr<usize> for Vpnv6 {
+    type Output = Self;
+    fn shr(self, rhs: usize) -> Self {
+        // The bits that move from the route distinguisher into the address.
+        let carry = match rhs {
+            0 => 0,
+            1..=127 => (self.rd as u128) << (128 - rhs),
+            128..=191 => (self.rd >> (rhs - 128)) as u128,
+            _ => return Vpnv6::zero(),
+        };
+        Vpnv6 {
+            rd: self.rd.checked_shr(rhs as u32).unwrap_or(0),
+            addr: self.addr.checked_shr(rhs as u32).unwrap_or(0) | carry,
+        }
+    }
+}
+
+impl CheckedAdd for Vpnv6 {
+    fn checked_add(&self, v: &Self) -> Option<Self> {
+        let (addr, carry) = self.addr.overflowing_add(v.addr);
+        let rd = self.rd.checked_add(v.rd)?.checked_add(carry as u64)?;
+        Some(Vpnv6 { rd, addr })
+    }
+}
+
+impl CheckedSub for Vpnv6 {
+    fn checked_sub(&self, v: &Self) -> Option<Self> {
+        let (addr, borrow) = self.addr.overflowing_sub(v.addr);
+        let rd = self.rd.checked_sub(v.rd)?.checked_sub(borrow as u64)?;
+        Some(Vpnv6 { rd, addr })
+    }
+}
+
+impl CheckedMul for Vpnv6 {
+    fn checked_mul(&self, v: &Self) -> Option<Self> {
+        let product = *self * *v;
+        if !v.is_zero() && product / *v != *self {
+            return None;
+        }
+        Some(product)
+    }
+}
+
+impl CheckedDiv for Vpnv6 {
+    fn checked_div(&self, v: &Self) -> Option<Self> {
+        if v.is_zero() {
+            return None;
+        }
+        Some(*self / *v)
+    }
+}
+
+impl Saturating for Vpnv6 {
+    fn saturating_add(self, v: Self) -> Self {
+        self.checked_add(&v).unwrap_or_else(Vpnv6::max_value)
+    }
+
+    fn saturating_sub(self, v: Self) -> Self {
+        self.checked_sub(&v).unwrap_or_else(Vpnv6::zero)
+    }
+}
+
+impl PrimInt for Vpnv6 {
+    fn count_ones(self) -> u32 {
+        self.rd.count_ones() + self.addr.count_ones()
+    }
+
+    fn count_zeros(self) -> u32 {
+        192 - self.count_ones()
+    }
+
+    fn leading_zeros(self) -> u32 {
+        match self.rd {
+            0 => 64 + self.addr.leading_zeros(),
+            rd => rd.leading_zeros(),
+        }
+    }
+
+    fn trailing_zeros(self) -> u32 {
+        match self.addr {
+            0 => 128 + self.rd.trailing_zeros(),
+            addr => addr.trailing_zeros(),
+        }
+    }
+
+    fn rotate_left(self, n: u32) -> Self {
+        let n = (n % 192) as usize;
+        if n == 0 {
+            return self;
+        }
+        (self << n) | (self >> (192 - n))
+    }
+
+    fn rotate_right(self, n: u32) -> Self {
+        self.rotate_left(192 - n % 192)
+    }
+
+    fn signed_shl(self, n: u32) -> Self {
+        self << n as usize
+    }
+
+    fn signed_shr(self, n: u32) -> Self {
+        if !self.bit(191) {
+            return self >> n as usize;
+        }
+        (self >> n as usize) | !(Vpnv6::max_value() >> n as usize)
+    }
+
+    fn unsigned_shl(self, n: u32) -> Self {
+        self << n as usize
+    }
+
+    fn unsigned_shr(self, n: u32) -> Self {
+        self >> n as usize
+    }
+
+    // The last 8 bytes of the address become the route distinguisher and
+    // the route distinguisher the last 8 bytes of the address.
+    fn swap_bytes(self) -> Self {
+        Vpnv6 {
+            rd: (self.addr as u64).swap_bytes(),
+            addr: ((((self.addr >> 64) as u64).swap_bytes() as u128) << 64)
+                | self.rd.swap_bytes() as u128,
+        }
+    }
+
+    fn from_be(x: Self) -> Self {
+        x.to_be()
+    }
+
+    fn from_le(x: Self) -> Self {
+        x.to_le()
+    }
+
+    fn to_be(self) -> Self {
+        if cfg!(target_endian = "big") {
+            self
+        } else {
+            self.swap_bytes()
+        }
+    }
+
+    fn to_le(self) -> Self {
+        if cfg!(target_endian = "little") {
+            self
+        } else {
+            self.swap_bytes()
+        }
+    }
+
+    fn pow(self, exp: u32) -> Self {
+        (0..exp).fold(Vpnv6::one(), |n, _| n * self)
+    }
+}